@@ -5762,6 +5762,10 @@ rec {
             name = "ps4libdoc";
             packageId = "ps4libdoc";
           }
+          {
+            name = "regex";
+            packageId = "regex";
+          }
           {
             name = "renderdoc";
             packageId = "renderdoc";
@@ -5775,6 +5779,15 @@ rec {
             name = "rspirv";
             packageId = "rspirv";
           }
+          {
+            name = "serde";
+            packageId = "serde";
+            features = [ "derive" ];
+          }
+          {
+            name = "serde_json";
+            packageId = "serde_json";
+          }
           {
             name = "vulkano";
             packageId = "vulkano";
@@ -0,0 +1,239 @@
+//! A small LZ4 block compressor for the messages sent by the flush thread. Trace messages repeat
+//! the same thread and label ids over and over, so even a greedy search with a small hash table
+//! removes most of them.
+
+use alloc::vec;
+use core::slice;
+
+const MIN_MATCH: usize = 4;
+const HASH_LOG: u32 = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+/// Matches can't start within the last `MF_LIMIT` bytes of a block and the block has to end with
+/// at least `LAST_LITERALS` literals, as required by the LZ4 block format.
+const MF_LIMIT: usize = 12;
+const LAST_LITERALS: usize = 5;
+
+#[no_mangle]
+extern "C" fn trace_compress_bound(length: usize) -> usize {
+    length + length / 255 + 16
+}
+
+/// Compresses `input_length` bytes of `input` into `output`, which holds at least
+/// `trace_compress_bound(input_length)` bytes. Returns the length of the compressed block.
+#[no_mangle]
+extern "C" fn trace_compress(input: *const u8, input_length: usize, output: *mut u8) -> usize {
+    let input = unsafe { slice::from_raw_parts(input, input_length) };
+    let output = unsafe { slice::from_raw_parts_mut(output, trace_compress_bound(input_length)) };
+
+    compress_block(input, output)
+}
+
+fn compress_block(input: &[u8], output: &mut [u8]) -> usize {
+    let mut table = vec![0u32; 1 << HASH_LOG];
+    let mut out = 0;
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    if input.len() > MF_LIMIT {
+        let match_limit = input.len() - MF_LIMIT;
+        let end_limit = input.len() - LAST_LITERALS;
+
+        while pos < match_limit {
+            let sequence = read_u32(input, pos);
+            let hash = (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize;
+            let candidate = table[hash] as usize;
+            table[hash] = pos as u32;
+
+            if candidate >= pos
+                || pos - candidate > MAX_OFFSET
+                || read_u32(input, candidate) != sequence
+            {
+                pos += 1;
+                continue;
+            }
+
+            let mut length = MIN_MATCH;
+            while pos + length < end_limit && input[candidate + length] == input[pos + length] {
+                length += 1;
+            }
+
+            out = write_sequence(
+                output,
+                out,
+                &input[anchor..pos],
+                Some((pos - candidate, length)),
+            );
+            pos += length;
+            anchor = pos;
+        }
+    }
+
+    write_sequence(output, out, &input[anchor..], None)
+}
+
+fn read_u32(input: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap())
+}
+
+fn write_sequence(
+    output: &mut [u8],
+    mut out: usize,
+    literals: &[u8],
+    matched: Option<(usize, usize)>,
+) -> usize {
+    let token_idx = out;
+    out += 1;
+
+    let mut token = (literals.len().min(15) as u8) << 4;
+    if literals.len() >= 15 {
+        out = write_length(output, out, literals.len() - 15);
+    }
+
+    output[out..out + literals.len()].copy_from_slice(literals);
+    out += literals.len();
+
+    if let Some((offset, length)) = matched {
+        output[out..out + 2].copy_from_slice(&(offset as u16).to_le_bytes());
+        out += 2;
+
+        let match_length = length - MIN_MATCH;
+        token |= match_length.min(15) as u8;
+        if match_length >= 15 {
+            out = write_length(output, out, match_length - 15);
+        }
+    }
+
+    output[token_idx] = token;
+    out
+}
+
+fn write_length(output: &mut [u8], mut out: usize, mut length: usize) -> usize {
+    while length >= 255 {
+        output[out] = 255;
+        out += 1;
+        length -= 255;
+    }
+
+    output[out] = length as u8;
+    out + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compress::{compress_block, trace_compress_bound, LAST_LITERALS, MF_LIMIT};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    /// Decodes a block, checking the end of block rules the compressor has to follow.
+    fn decompress(block: &[u8], length: usize) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut pos = 0;
+
+        loop {
+            let token = block[pos];
+            pos += 1;
+
+            let literal_length = read_length(block, &mut pos, (token >> 4) as usize);
+            output.extend_from_slice(&block[pos..pos + literal_length]);
+            pos += literal_length;
+
+            if pos == block.len() {
+                break;
+            }
+
+            let offset = u16::from_le_bytes([block[pos], block[pos + 1]]) as usize;
+            pos += 2;
+            let match_length = read_length(block, &mut pos, (token & 0xf) as usize) + 4;
+
+            assert!(offset > 0 && offset <= output.len());
+            assert!(output.len() + MF_LIMIT <= length, "match starts too late");
+            assert!(output.len() + match_length + LAST_LITERALS <= length);
+
+            // matches may overlap the bytes they produce
+            let start = output.len() - offset;
+            for idx in 0..match_length {
+                output.push(output[start + idx]);
+            }
+        }
+
+        output
+    }
+
+    fn read_length(block: &[u8], pos: &mut usize, token_length: usize) -> usize {
+        let mut length = token_length;
+        if token_length == 15 {
+            loop {
+                let byte = block[*pos];
+                *pos += 1;
+                length += byte as usize;
+
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+
+        length
+    }
+
+    fn round_trip(input: &[u8]) -> usize {
+        let mut output = vec![0u8; trace_compress_bound(input.len())];
+        let length = compress_block(input, &mut output);
+
+        assert_eq!(decompress(&output[..length], input.len()), input);
+        length
+    }
+
+    /// Bytes without repeats for the compressor to find.
+    fn noise(length: usize, mut state: u64) -> Vec<u8> {
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_short_inputs() {
+        for length in 0..=MF_LIMIT + 1 {
+            round_trip(&vec![7u8; length]);
+            round_trip(&noise(length, 1));
+        }
+    }
+
+    #[test]
+    fn round_trips_long_literal_runs() {
+        for length in [15, 269, 270, 271, 600] {
+            round_trip(&noise(length, 2));
+        }
+    }
+
+    #[test]
+    fn round_trips_overlapping_matches() {
+        // a single byte matched against itself, with match lengths needing extra bytes
+        let length = round_trip(&vec![0xab; 1000]);
+        assert!(length < 16);
+
+        let pattern = b"abc".repeat(300);
+        round_trip(&pattern);
+    }
+
+    #[test]
+    fn matches_up_to_the_largest_offset() {
+        let repeated = noise(300, 3);
+        for offset in [u16::MAX as usize - 1, u16::MAX as usize, u16::MAX as usize + 1] {
+            let mut input = repeated.clone();
+            input.resize(offset, 0);
+            input.extend_from_slice(&repeated);
+            input.extend_from_slice(&noise(20, 4));
+
+            let length = round_trip(&input);
+            // the zeros compress to a few bytes, the second copy only when it's in reach
+            assert_eq!(length < 2 * repeated.len(), offset <= u16::MAX as usize);
+        }
+    }
+}
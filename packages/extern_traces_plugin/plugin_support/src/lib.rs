@@ -1,9 +1,10 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(lang_items)]
 #![feature(core_intrinsics)]
 #![feature(ptr_as_ref_unchecked)]
 extern crate alloc;
 
+mod compress;
 mod module_ids;
 mod platform;
 
@@ -17,6 +18,7 @@ use alloc::vec;
 use alloc::vec::Vec;
 use anyhow::bail;
 use bytemuck::{Pod, Zeroable};
+use core::ffi::{c_char, CStr};
 use core::slice;
 use gcn::instructions::formats::{FormattedInstruction, SOP1Instruction, SOPPInstruction};
 use gcn::instructions::operands::{ScalarDestinationOperand, ScalarSourceOperand};
@@ -66,6 +68,31 @@ struct SpanStartAdditionalData {
     extra_data_length: u64,
 }
 
+/// Request of captures made by viewers which only send a lone byte, written without the request.
+const NO_CAPTURE_REQUEST: u64 = u64::MAX;
+
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable)]
+struct SpanStartCapture {
+    message_tag: u64,
+    thread_id: u64,
+    time: u64,
+    label_id: u64,
+    request: u64,
+    trigger: u64,
+    trigger_value: u64,
+    extra_data_length: u64,
+}
+
+/// The viewer request a submit was captured for and what triggered it.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct CaptureRequest {
+    request: u64,
+    trigger: u64,
+    trigger_value: u64,
+}
+
 #[no_mangle]
 extern "C" fn sceGnmSubmitCommandBuffers_trace(
     args: *const Args,
@@ -73,6 +100,7 @@ extern "C" fn sceGnmSubmitCommandBuffers_trace(
     time: u64,
     label_id: u64,
     thread_id: u64,
+    capture: *const CaptureRequest,
 ) {
     let args = unsafe { args.as_ref_unchecked() };
     let thread_logging_state = unsafe { thread_logging_state.as_mut_unchecked() };
@@ -95,6 +123,7 @@ extern "C" fn sceGnmSubmitCommandBuffers_trace(
         thread_id,
         time,
         label_id,
+        unsafe { *capture },
     )
     .unwrap();
 }
@@ -106,6 +135,7 @@ extern "C" fn sceGnmSubmitAndFlipCommandBuffersForWorkload_trace(
     time: u64,
     label_id: u64,
     thread_id: u64,
+    capture: *const CaptureRequest,
 ) {
     let args = unsafe { args.as_ref_unchecked() };
     let thread_logging_state = unsafe { thread_logging_state.as_mut_unchecked() };
@@ -128,6 +158,7 @@ extern "C" fn sceGnmSubmitAndFlipCommandBuffersForWorkload_trace(
         thread_id,
         time,
         label_id,
+        unsafe { *capture },
     )
     .unwrap();
 }
@@ -139,8 +170,16 @@ extern "C" fn sceGnmSubmitAndFlipCommandBuffers_trace(
     time: u64,
     label_id: u64,
     thread_id: u64,
+    capture: *const CaptureRequest,
 ) {
-    sceGnmSubmitCommandBuffers_trace(args, thread_logging_state, time, label_id, thread_id);
+    sceGnmSubmitCommandBuffers_trace(
+        args,
+        thread_logging_state,
+        time,
+        label_id,
+        thread_id,
+        capture,
+    );
 }
 
 #[no_mangle]
@@ -271,6 +310,171 @@ extern "C" fn sceHttpSendRequest_trace(
     thread_logging_state.flush(res);
 }
 
+#[repr(C)]
+#[derive(Pod, Copy, Clone, Zeroable)]
+struct SpanEndAdditionalData {
+    message_tag: u64,
+    thread_id: u64,
+    time: u64,
+    extra_data_length: u64,
+}
+
+unsafe fn c_string<'a>(ptr: *const u8) -> &'a [u8] {
+    if ptr.is_null() {
+        return &[];
+    }
+
+    CStr::from_ptr(ptr as *const c_char).to_bytes()
+}
+
+/// Extra data is the name of the new thread. The created thread follows on the end of the span.
+#[no_mangle]
+extern "C" fn scePthreadCreate_trace(
+    args: *const Args,
+    thread_logging_state: *mut ThreadLoggingState,
+    time: u64,
+    label_id: u64,
+    thread_id: u64,
+) {
+    let args = unsafe { args.as_ref_unchecked() };
+    let thread_logging_state = unsafe { thread_logging_state.as_mut_unchecked() };
+
+    let name = unsafe { c_string(args.args[4] as *const u8) };
+
+    let total_size = size_of::<SpanStartAdditionalData>() + name.len();
+
+    let Some(mut res) = thread_logging_state.reserve(total_size) else {
+        return;
+    };
+
+    let span_header = SpanStartAdditionalData {
+        message_tag: 3,
+        thread_id,
+        time,
+        label_id,
+        extra_data_length: name.len() as u64,
+    };
+
+    res.write(bytemuck::cast_slice(&[span_header]));
+    res.write(name);
+
+    thread_logging_state.flush(res);
+}
+
+/// Ends a successful `scePthreadCreate` span with the id of the thread it created, as
+/// `thread_id_of` gives it, matching the thread id of the messages of that thread.
+#[no_mangle]
+extern "C" fn scePthreadCreate_end_trace(
+    thread_logging_state: *mut ThreadLoggingState,
+    time: u64,
+    thread_id: u64,
+    created_thread_id: u64,
+) {
+    let thread_logging_state = unsafe { thread_logging_state.as_mut_unchecked() };
+
+    let total_size = size_of::<SpanEndAdditionalData>() + size_of::<u64>();
+
+    let Some(mut res) = thread_logging_state.reserve(total_size) else {
+        return;
+    };
+
+    let span_header = SpanEndAdditionalData {
+        message_tag: 4,
+        thread_id,
+        time,
+        extra_data_length: size_of::<u64>() as u64,
+    };
+
+    res.write(bytemuck::cast_slice(&[span_header]));
+    res.write(bytemuck::cast_slice(&[created_thread_id]));
+
+    thread_logging_state.flush(res);
+}
+
+/// Extra data is the renamed thread followed by its new name. Threads are identified by their
+/// handle in messages, so the handle is the thread id.
+#[no_mangle]
+extern "C" fn scePthreadRename_trace(
+    args: *const Args,
+    thread_logging_state: *mut ThreadLoggingState,
+    time: u64,
+    label_id: u64,
+    thread_id: u64,
+) {
+    let args = unsafe { args.as_ref_unchecked() };
+    let thread_logging_state = unsafe { thread_logging_state.as_mut_unchecked() };
+
+    let renamed_thread_id = args.args[0];
+    let name = unsafe { c_string(args.args[1] as *const u8) };
+
+    let extra_data_length = size_of::<u64>() + name.len();
+    let total_size = size_of::<SpanStartAdditionalData>() + extra_data_length;
+
+    let Some(mut res) = thread_logging_state.reserve(total_size) else {
+        return;
+    };
+
+    let span_header = SpanStartAdditionalData {
+        message_tag: 3,
+        thread_id,
+        time,
+        label_id,
+        extra_data_length: extra_data_length as u64,
+    };
+
+    res.write(bytemuck::cast_slice(&[span_header]));
+    res.write(bytemuck::cast_slice(&[renamed_thread_id]));
+    res.write(name);
+
+    thread_logging_state.flush(res);
+}
+
+#[no_mangle]
+extern "C" fn pthread_setname_np_trace(
+    args: *const Args,
+    thread_logging_state: *mut ThreadLoggingState,
+    time: u64,
+    label_id: u64,
+    thread_id: u64,
+) {
+    scePthreadRename_trace(args, thread_logging_state, time, label_id, thread_id);
+}
+
+/// Extra data is the first argument, the handle of the lock, semaphore, queue or other object
+/// the call waits on or signals.
+#[no_mangle]
+extern "C" fn sync_object_trace(
+    args: *const Args,
+    thread_logging_state: *mut ThreadLoggingState,
+    time: u64,
+    label_id: u64,
+    thread_id: u64,
+) {
+    let args = unsafe { args.as_ref_unchecked() };
+    let thread_logging_state = unsafe { thread_logging_state.as_mut_unchecked() };
+
+    let handle = args.args[0];
+
+    let total_size = size_of::<SpanStartAdditionalData>() + size_of::<u64>();
+
+    let Some(mut res) = thread_logging_state.reserve(total_size) else {
+        return;
+    };
+
+    let span_header = SpanStartAdditionalData {
+        message_tag: 3,
+        thread_id,
+        time,
+        label_id,
+        extra_data_length: size_of::<u64>() as u64,
+    };
+
+    res.write(bytemuck::cast_slice(&[span_header]));
+    res.write(bytemuck::cast_slice(&[handle]));
+
+    thread_logging_state.flush(res);
+}
+
 fn trace_command_buffer_submit(
     thread_logging_state: &mut ThreadLoggingState,
     draw_command_buffers: &[&[u8]],
@@ -278,10 +482,17 @@ fn trace_command_buffer_submit(
     thread_id: u64,
     time: u64,
     label_id: u64,
+    capture: CaptureRequest,
 ) -> Result<(), anyhow::Error> {
     let mut shaders = ShadersCollector::new();
 
-    let mut total_size = size_of::<SpanStartAdditionalData>()
+    let header_size = if capture.request == NO_CAPTURE_REQUEST {
+        size_of::<SpanStartAdditionalData>()
+    } else {
+        size_of::<SpanStartCapture>()
+    };
+
+    let mut total_size = header_size
         + size_of::<u32>()
         + (draw_command_buffers.len() * size_of::<u32>())
         + (compute_command_buffers.len() * size_of::<u32>());
@@ -324,15 +535,31 @@ fn trace_command_buffer_submit(
         return Ok(());
     };
 
-    let span_header = SpanStartAdditionalData {
-        message_tag: 3,
-        thread_id,
-        time,
-        label_id,
-        extra_data_length: total_size as u64 - size_of::<SpanStartAdditionalData>() as u64,
-    };
+    let extra_data_length = (total_size - header_size) as u64;
+    if capture.request == NO_CAPTURE_REQUEST {
+        let span_header = SpanStartAdditionalData {
+            message_tag: 3,
+            thread_id,
+            time,
+            label_id,
+            extra_data_length,
+        };
 
-    res.write(bytemuck::cast_slice(&[span_header]));
+        res.write(bytemuck::cast_slice(&[span_header]));
+    } else {
+        let span_header = SpanStartCapture {
+            message_tag: 10,
+            thread_id,
+            time,
+            label_id,
+            request: capture.request,
+            trigger: capture.trigger,
+            trigger_value: capture.trigger_value,
+            extra_data_length,
+        };
+
+        res.write(bytemuck::cast_slice(&[span_header]));
+    }
 
     let count = draw_command_buffers.len() as u32;
     res.write(bytemuck::cast_slice(&[count]));
@@ -1,3 +1,5 @@
 pub mod alloc;
+// tests run on the host, with the panic handler of std
+#[cfg(not(test))]
 pub mod errors;
 pub mod stdio;
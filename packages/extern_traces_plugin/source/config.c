@@ -12,10 +12,13 @@
     "[global]\n" \
     ";target_address = 192.168.1.133\n" \
     ";target_port = 9090\n" \
+    ";compress = 0\n" \
+    ";compact_spans = 0\n" \
     "\n" \
     "; Game-specific settings\n" \
     ";[CUSA08010]\n" \
-    ";original_tls_size = 0\n"
+    ";original_tls_size = 0\n" \
+    ";capture_args = 0\n"
 
 void create_default_config(void) {
     char dir_path[256];
@@ -63,6 +66,12 @@ bool load_config(const char* titleid, PluginConfig* config) {
     if (address) strncpy(config->target_address, address, sizeof(config->target_address) - 1);
     if (port) config->target_port = atoi(port);
 
+    const char *compress = ini_table_get_entry(ini, "global", "compress");
+    if (compress) config->compress = atoi(compress) != 0;
+
+    const char *compact_spans = ini_table_get_entry(ini, "global", "compact_spans");
+    if (compact_spans) config->compact_spans = atoi(compact_spans) != 0;
+
     // Read game-specific settings
     const char *tls_size = ini_table_get_entry(ini, titleid, "original_tls_size");
     if (tls_size) {
@@ -73,6 +82,9 @@ bool load_config(const char* titleid, PluginConfig* config) {
         return false;
     }
 
+    const char *capture_args = ini_table_get_entry(ini, titleid, "capture_args");
+    if (capture_args) config->capture_args = atoi(capture_args) != 0;
+
     ini_table_destroy(ini);
     return true;
 }
\ No newline at end of file
@@ -10,6 +10,9 @@ typedef struct {
     char target_address[256];
     uint16_t target_port;
     uint32_t original_tls_size;
+    bool capture_args;
+    bool compress;
+    bool compact_spans;
 } PluginConfig;
 
 bool load_config(const char* titleid, PluginConfig* config);
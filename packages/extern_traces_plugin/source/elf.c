@@ -445,6 +445,26 @@ void cleanup_jump_slot_relocation_list(JumpSlotRelocationList* list) {
     list->capacity = 0;
 }
 
+static const char* sync_object_call_nids[SYNC_OBJECT_CALL_COUNT] = {
+    "9UK1vLZQft4", // scePthreadMutexLock
+    "upoVrzMHFeE", // scePthreadMutexTrylock
+    "IafI2PxcPnQ", // scePthreadMutexTimedlock
+    "tn3VlD0hG60", // scePthreadMutexUnlock
+    "7H0iTOciTLo", // pthread_mutex_lock
+    "2Z+PpY6CaJg", // pthread_mutex_unlock
+    "WKAXJ4XBPQ4", // scePthreadCondWait
+    "BmMjYxmew1w", // scePthreadCondTimedwait
+    "kDh-NfxgMtE", // scePthreadCondSignal
+    "JGgj7Uvrl+A", // scePthreadCondBroadcast
+    "Zxa0VhQVTsk", // sceKernelWaitSema
+    "12wOHk8ywb0", // sceKernelPollSema
+    "4czppHBiriw", // sceKernelSignalSema
+    "fzyMKs9kim0", // sceKernelWaitEqueue
+    "F6e0kwo4cnk", // sceKernelTriggerUserEvent
+    "JTvBflhYazQ", // sceKernelWaitEventFlag
+    "IOnSvHzqu6A", // sceKernelSetEventFlag
+};
+
 void fill_specific_symbols_table(const JumpSlotRelocationList* list, struct SpecificSymbolsTable* table) {
     table->sceGnmSubmitAndFlipCommandBuffersForWorkload = -1;
     table->sceGnmSubmitAndFlipCommandBuffers = -1;
@@ -453,6 +473,12 @@ void fill_specific_symbols_table(const JumpSlotRelocationList* list, struct Spec
     table->sceAjmBatchJobRunBufferRa = -1;
     table->sceAjmBatchJobControlBufferRa = -1;
     table->sceHttpSendRequest = -1;
+    table->scePthreadCreate = -1;
+    table->scePthreadRename = -1;
+    table->pthread_setname_np = -1;
+    for (size_t j = 0; j < SYNC_OBJECT_CALL_COUNT; j++) {
+        table->sync_object_calls[j] = -1;
+    }
 
     for (size_t i = 0; i < list->count; i++) {
         const SymbolInfo* symbol_info = list->items[i].symbol_info;
@@ -526,5 +552,47 @@ void fill_specific_symbols_table(const JumpSlotRelocationList* list, struct Spec
         ) {
             table->sceHttpSendRequest = i;
         }
+
+        if (
+            strncmp(
+                symbol_info->data.parsed.name,
+                "6UgtwV+0zb4",
+                sizeof(symbol_info->data.parsed.name) - 1
+            ) == 0
+        ) {
+            table->scePthreadCreate = i;
+        }
+
+        if (
+            strncmp(
+                symbol_info->data.parsed.name,
+                "GBUY7ywdULE",
+                sizeof(symbol_info->data.parsed.name) - 1
+            ) == 0
+        ) {
+            table->scePthreadRename = i;
+        }
+
+        if (
+            strncmp(
+                symbol_info->data.parsed.name,
+                "cB4rMoKU4UI",
+                sizeof(symbol_info->data.parsed.name) - 1
+            ) == 0
+        ) {
+            table->pthread_setname_np = i;
+        }
+
+        for (size_t j = 0; j < SYNC_OBJECT_CALL_COUNT; j++) {
+            if (
+                strncmp(
+                    symbol_info->data.parsed.name,
+                    sync_object_call_nids[j],
+                    sizeof(symbol_info->data.parsed.name) - 1
+                ) == 0
+            ) {
+                table->sync_object_calls[j] = i;
+            }
+        }
     }
 }
@@ -132,6 +132,9 @@ typedef struct {
 void find_jump_slot_relocations(const DynamicInfo* info, JumpSlotRelocationList* result);
 void cleanup_jump_slot_relocation_list(JumpSlotRelocationList* list);
 
+// calls taking a lock, semaphore, queue or other sync object as their first argument
+#define SYNC_OBJECT_CALL_COUNT 17
+
 struct SpecificSymbolsTable {
     int64_t sceGnmSubmitAndFlipCommandBuffersForWorkload;
     int64_t sceGnmSubmitAndFlipCommandBuffers;
@@ -140,6 +143,10 @@ struct SpecificSymbolsTable {
     int64_t sceAjmBatchJobRunBufferRa;
     int64_t sceAjmBatchJobControlBufferRa;
     int64_t sceHttpSendRequest;
+    int64_t scePthreadCreate;
+    int64_t scePthreadRename;
+    int64_t pthread_setname_np;
+    int64_t sync_object_calls[SYNC_OBJECT_CALL_COUNT];
 };
 
 void fill_specific_symbols_table(const JumpSlotRelocationList* list, struct SpecificSymbolsTable* table);
\ No newline at end of file
@@ -6,6 +6,7 @@
 
 #include "logger.h"
 #include "time.h"
+#include "tracing.h"
 
 #include "plugin_common.h"
 
@@ -220,6 +221,115 @@ ssize_t send_all(int sock, const uint8_t *buffer, size_t length)
     return total_bytes_sent;
 }
 
+struct CompressedMessages
+{
+    uint64_t message_tag;
+    uint64_t uncompressed_length;
+    uint64_t compressed_length;
+};
+
+// messages are compressed together once there's enough of them or they waited long enough. blocks
+// never hold more than this, the viewer rejects larger ones.
+#define COMPRESSED_BLOCK_SIZE (64 * 1024)
+#define COMPRESSED_BLOCK_MAX_AGE_MS 10
+
+static bool compress_messages = false;
+static bool compact_spans = false;
+static uint64_t compressed_block_max_age = 0;
+
+// only used by the flush thread
+static uint8_t *block = NULL;
+static size_t block_length = 0;
+static size_t block_capacity = 0;
+static uint64_t block_start_time = 0;
+static uint8_t *compressed_block = NULL;
+static size_t compressed_block_capacity = 0;
+
+static bool grow_buffer(uint8_t **buffer, size_t *capacity, size_t length) {
+    if (length <= *capacity) {
+        return true;
+    }
+
+    size_t new_capacity = max(*capacity * 2, length);
+    uint8_t *new_buffer = realloc(*buffer, new_capacity);
+    if (new_buffer == NULL) {
+        return false;
+    }
+
+    *buffer = new_buffer;
+    *capacity = new_capacity;
+    return true;
+}
+
+// sends the pending messages, as a compressed block unless compressing doesn't make them smaller
+ssize_t flush_compressed_block(int sock) {
+    if (block_length == 0) {
+        return 0;
+    }
+
+    size_t uncompressed_length = block_length;
+    block_length = 0;
+
+    if (!grow_buffer(&compressed_block, &compressed_block_capacity, trace_compress_bound(uncompressed_length))) {
+        final_printf("allocation failed, sending messages uncompressed\n");
+        return send_all(sock, block, uncompressed_length);
+    }
+
+    size_t compressed_length = trace_compress(block, uncompressed_length, compressed_block);
+    if (compressed_length + sizeof(struct CompressedMessages) >= uncompressed_length) {
+        return send_all(sock, block, uncompressed_length);
+    }
+
+    struct CompressedMessages header = {
+        .message_tag = 11,
+        .uncompressed_length = uncompressed_length,
+        .compressed_length = compressed_length,
+    };
+
+    ssize_t bytes_sent = send_all(sock, (const uint8_t *)&header, sizeof(header));
+    if (bytes_sent < 0) {
+        return bytes_sent;
+    }
+
+    return send_all(sock, compressed_block, compressed_length);
+}
+
+// sends the block when `length` more bytes of whole messages wouldn't fit in it
+static ssize_t make_room_in_block(int sock, size_t length) {
+    if (!compress_messages || block_length + length <= COMPRESSED_BLOCK_SIZE) {
+        return 0;
+    }
+
+    return flush_compressed_block(sock);
+}
+
+// sends messages, or adds them to the block being compressed. the block has to have room for them,
+// they may be part of a message which is only whole once the rest is added.
+ssize_t send_messages(int sock, const uint8_t *buffer, size_t length, bool compress) {
+    if (!compress) {
+        return send_all(sock, buffer, length);
+    }
+
+    if (!grow_buffer(&block, &block_capacity, block_length + length)) {
+        // messages are framed the same compressed or not, so keeping the order is enough
+        ssize_t bytes_sent = flush_compressed_block(sock);
+        if (bytes_sent < 0) {
+            return bytes_sent;
+        }
+
+        return send_all(sock, buffer, length);
+    }
+
+    if (block_length == 0) {
+        block_start_time = get_current_time_rdtscp();
+    }
+
+    memcpy(block + block_length, buffer, length);
+    block_length += length;
+
+    return length;
+}
+
 ssize_t flush_buffer_contents(struct BufferState *state, int sock) {
     uint64_t write_idx = state->write_idx;
     if (write_idx == state->read_idx)
@@ -227,12 +337,25 @@ ssize_t flush_buffer_contents(struct BufferState *state, int sock) {
         return 0;
     }
 
+    uint64_t size = state->size - sizeof(struct BufferState);
+
+    // messages can wrap around the end of the buffer, so they're added to a block all at once.
+    // more than fits in a block is sent uncompressed.
+    size_t length = write_idx > state->read_idx ? write_idx - state->read_idx : size - state->read_idx + write_idx;
+    ssize_t flushed = make_room_in_block(sock, length);
+    if (flushed < 0)
+    {
+        return flushed;
+    }
+
+    bool compress = compress_messages && length <= COMPRESSED_BLOCK_SIZE;
+
     if (write_idx > state->read_idx)
     {
         // no wrapping case
         size_t bytes_to_send = write_idx - state->read_idx;
 
-        ssize_t bytes_sent = send_all(sock, &state->buffer[state->read_idx], bytes_to_send);
+        ssize_t bytes_sent = send_messages(sock, &state->buffer[state->read_idx], bytes_to_send, compress);
         if (bytes_sent < 0)
         {
             return bytes_sent;
@@ -245,14 +368,13 @@ ssize_t flush_buffer_contents(struct BufferState *state, int sock) {
     {
         // wrapping case (write_idx < read_idx)
         ssize_t total_bytes_sent = 0;
-        uint64_t size = state->size - sizeof(struct BufferState);
 
         {
             // read first half (from read index to end of buffer)
             size_t bytes_to_send_first = size - state->read_idx;
             if (bytes_to_send_first > 0)
             {
-                ssize_t bytes_sent = send_all(sock, &state->buffer[state->read_idx], bytes_to_send_first);
+                ssize_t bytes_sent = send_messages(sock, &state->buffer[state->read_idx], bytes_to_send_first, compress);
                 if (bytes_sent < 0)
                 {
                     return bytes_sent;
@@ -268,7 +390,7 @@ ssize_t flush_buffer_contents(struct BufferState *state, int sock) {
             size_t bytes_to_send_second = write_idx;
             if (bytes_to_send_second > 0)
             {
-                ssize_t bytes_sent = send_all(sock, &state->buffer[0], bytes_to_send_second);
+                ssize_t bytes_sent = send_messages(sock, &state->buffer[0], bytes_to_send_second, compress);
                 if (bytes_sent < 0)
                 {
                     return bytes_sent;
@@ -299,8 +421,42 @@ ssize_t flush_buffer(struct BufferState* state, int sock)
     return flush_buffer_contents(state, sock);
 }
 
+struct ThreadContext
+{
+    uint64_t message_tag;
+    uint64_t thread_id;
+};
+
+static bool buffer_has_messages(struct BufferState *state) {
+    return state->last_buffer != NULL || state->write_idx != state->read_idx;
+}
+
 ssize_t flush_logging_entries(struct ThreadLoggingState *state, int sock)
 {
+    // checked first, so messages written after finding the buffer empty aren't sent without their
+    // thread
+    if (!buffer_has_messages(state->current_buffer)) {
+        return 0;
+    }
+
+    // compact spans don't carry their thread, it's sent before the buffer of the thread
+    if (compact_spans) {
+        struct ThreadContext context = {
+            .message_tag = 12,
+            .thread_id = state->thread_id,
+        };
+
+        ssize_t bytes_sent = make_room_in_block(sock, sizeof(context));
+        if (bytes_sent < 0) {
+            return bytes_sent;
+        }
+
+        bytes_sent = send_messages(sock, (const uint8_t *)&context, sizeof(context), compress_messages);
+        if (bytes_sent < 0) {
+            return bytes_sent;
+        }
+    }
+
     return flush_buffer(state->current_buffer, sock);
 }
 
@@ -331,7 +487,12 @@ ssize_t flush_counters(struct ThreadLoggingState *state, int sock) {
         .time = time,
     };
 
-    ssize_t bytes_sent = send_all(sock, (const uint8_t *)&counters_update_message, sizeof(struct CountersUpdate));
+    ssize_t bytes_sent = make_room_in_block(sock, sizeof(struct CountersUpdate));
+    if (bytes_sent < 0) {
+        return bytes_sent;
+    }
+
+    bytes_sent = send_messages(sock, (const uint8_t *)&counters_update_message, sizeof(struct CountersUpdate), compress_messages);
     if (bytes_sent < 0) {
         return bytes_sent;
     }
@@ -354,6 +515,12 @@ struct InitialMessageHeader {
     uint64_t anchor_timestamp;
 };
 
+struct CommandProtocol
+{
+    uint64_t message_tag;
+    uint64_t version;
+};
+
 void *flush_thread(void *arg)
 {
     struct FlushThreadArgs* args = arg;
@@ -449,6 +616,18 @@ void *flush_thread(void *arg)
 
     final_printf("sent symbols metadata\n");
 
+    struct CommandProtocol command_protocol = {
+        .message_tag = 7,
+        .version = COMMAND_PROTOCOL_VERSION,
+    };
+    send_all(sock, (uint8_t *)&command_protocol, sizeof(struct CommandProtocol));
+
+    final_printf("sent command protocol version\n");
+
+    compress_messages = args->compress;
+    compact_spans = args->compact_spans;
+    compressed_block_max_age = initial_message.tsc_frequency / 1000 * COMPRESSED_BLOCK_MAX_AGE_MS;
+
     args->is_ready = true;
 
     while (true)
@@ -462,6 +641,9 @@ void *flush_thread(void *arg)
                 continue;
             }
 
+            // read before flushing, so messages written before the thread finished are sent
+            bool is_finished = state->is_finished;
+
             ssize_t bytes_sent = flush_logging_entries(state, sock);
             if (bytes_sent < 0)
             {
@@ -478,7 +660,7 @@ void *flush_thread(void *arg)
                 return NULL;
             }
 
-            if (state->is_finished)
+            if (is_finished)
             {
                 free(state);
                 unsafe_write_atomic(&global_states[i], NULL);
@@ -486,6 +668,17 @@ void *flush_thread(void *arg)
             }
         }
 
+        if (block_length >= COMPRESSED_BLOCK_SIZE ||
+            (block_length > 0 && get_current_time_rdtscp() - block_start_time > compressed_block_max_age))
+        {
+            if (flush_compressed_block(sock) < 0)
+            {
+                final_printf("send failed\n");
+                close(sock);
+                return NULL;
+            }
+        }
+
         // once every 10ms
         // sceKernelUsleep(10000);
         scePthreadYield();
@@ -501,10 +694,16 @@ void fini_thread_local_state()
     state->is_finished = true;
 }
 
+uint64_t thread_id_of(OrbisPthread thread)
+{
+    // threads are identified by their pthread handle rather than the kernel thread id, which
+    // can't be looked up for another thread
+    return (uint64_t)thread;
+}
+
 struct ThreadLoggingState *init_thread_local_state()
 {
-    OrbisPthread thread = scePthreadSelf();
-    uint64_t thread_id = (uint64_t)thread;
+    uint64_t thread_id = thread_id_of(scePthreadSelf());
 
     final_printf("init_thread_local_state\n");
 
@@ -520,6 +719,11 @@ struct ThreadLoggingState *init_thread_local_state()
     thread_logging_state->dropped_packets_count = 0;
     thread_logging_state->last_dropped_packets_count = 0;
     thread_logging_state->last_counter_flush_time = 0;
+    thread_logging_state->call_depth = 0;
+    thread_logging_state->created_thread = NULL;
+    thread_logging_state->skipped_depth = 0;
+    thread_logging_state->has_compact_time = false;
+    thread_logging_state->compact_time = 0;
 
     struct BufferState* buffer_state = new_buffer_state(INITIAL_ALLOCATION_SIZE);
     if (!buffer_state) {
@@ -547,6 +751,7 @@ struct ThreadLoggingState *init_thread_local_state()
 void destructor_function(void *ptr)
 {
     struct ThreadLoggingState *state = (struct ThreadLoggingState *)ptr;
+    write_thread_exit(state);
     state->is_finished = true;
 }
 
@@ -575,3 +780,8 @@ struct ThreadLoggingState *lazy_read_value(struct ThreadLoggingState *initial_st
 
     return state;
 }
+
+struct ThreadLoggingState *current_thread_logging_state()
+{
+    return lazy_read_value((struct ThreadLoggingState *)read_thread_logging_state_slow());
+}
@@ -3,11 +3,15 @@
 #include <stdbool.h>
 #include <stdint.h>
 #include <stddef.h>
+#include <orbis/libkernel.h>
 #include "elf.h"
 #include "config.h"
 
 #define INITIAL_ALLOCATION_SIZE (16 * 1024)
 
+// version of the framed commands understood by listen_commands_thread, sent to the viewer
+#define COMMAND_PROTOCOL_VERSION 4
+
 struct BufferState {
     uint64_t write_idx;
     uint64_t read_idx;
@@ -17,12 +21,30 @@ struct BufferState {
     uint8_t buffer[];
 };
 
+// calls followed per thread to know which call returns, calls nested deeper are counted only
+#define MAX_CALL_DEPTH 64
+
 struct ThreadLoggingState
 {
     uint64_t thread_id;
     bool is_finished;
     struct BufferState* current_buffer;
-    uint64_t last_label_id;
+
+    // label ids of the calls which didn't return yet, innermost last
+    uint64_t call_labels[MAX_CALL_DEPTH];
+    uint64_t call_depth;
+
+    // where scePthreadCreate writes the new thread, read once the call returns
+    OrbisPthread *created_thread;
+
+    // calls left untraced, because tracing was paused or they weren't sampled, which didn't
+    // return yet
+    uint64_t skipped_depth;
+
+    // time of the last compact span written, which the next one is relative to. unset until the
+    // first one and after one is dropped.
+    bool has_compact_time;
+    uint64_t compact_time;
 
     uint64_t dropped_packets_count;
     uint64_t last_dropped_packets_count;
@@ -34,16 +56,31 @@ struct FlushThreadArgs {
     DynamicInfo* dynamic_info;
     JumpSlotRelocationList* jump_slot_relocations;
     int sock;
+
+    // sends messages in lz4 compressed blocks
+    bool compress;
+
+    // announces the thread of each buffer, for the compact spans in it
+    bool compact_spans;
 };
 
 void *flush_thread(void *arg);
 void fini_thread_local_state();
+// id of a thread in messages, the same for the handle scePthreadCreate writes for its creator
+// as for the thread itself
+uint64_t thread_id_of(OrbisPthread thread);
+
 struct ThreadLoggingState *init_thread_local_state();
 bool init_lazy_destructor();
 struct ThreadLoggingState *lazy_read_value();
+struct ThreadLoggingState *current_thread_logging_state();
 
 void set_static_tls_base(uint16_t base);
 
+// lz4 block compression, implemented in plugin_support
+size_t trace_compress_bound(size_t length);
+size_t trace_compress(const uint8_t *input, size_t input_length, uint8_t *output);
+
 struct BufferReservation {
     struct BufferState* buffer;
     uint64_t write_idx;
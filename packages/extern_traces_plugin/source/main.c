@@ -28,31 +28,158 @@ struct ListenCommandsThreadArgs {
     int sock;
 };
 
+// first byte of a framed command, followed by the length of the rest of the frame, the sequence
+// number answered with, the command id and its payload. older viewers send a lone 0x0 instead,
+// capturing the next submit.
+#define COMMAND_FRAME_MARKER 0xff
+#define MAX_COMMAND_FRAME_LENGTH (64 * 1024)
+
+enum Command {
+    COMMAND_CAPTURE_SUBMITS = 0,
+    COMMAND_SET_ARG_CAPTURE = 1,
+    COMMAND_SET_PAUSED = 2,
+    COMMAND_SET_SAMPLING = 3,
+    COMMAND_ARM_CAPTURE = 4,
+};
+
+static bool recv_all(int sock, uint8_t *buffer, size_t length) {
+    size_t total_bytes_read = 0;
+    while (total_bytes_read < length) {
+        ssize_t bytes_read = recv(sock, buffer + total_bytes_read, length - total_bytes_read, 0);
+
+        if (bytes_read == -1) {
+            final_printf("error reading from socket\n");
+            return false;
+        } else if (bytes_read == 0) {
+            final_printf("connection closed by peer\n");
+            return false;
+        }
+
+        total_bytes_read += bytes_read;
+    }
+
+    return true;
+}
+
+// runs a framed command, returning why it failed or NULL once it's applied. captures are tagged
+// with the sequence of the command asking for them.
+static const char *run_command(struct ThreadLoggingState *state, uint32_t sequence, uint16_t command_id, const uint8_t *payload, uint32_t payload_length) {
+    switch (command_id) {
+        case COMMAND_CAPTURE_SUBMITS: {
+            if (payload_length < sizeof(uint32_t)) {
+                return "payload too short";
+            }
+
+            uint32_t count;
+            memcpy(&count, payload, sizeof(count));
+            capture_submits(sequence, count);
+            return NULL;
+        }
+        case COMMAND_SET_ARG_CAPTURE: {
+            if (payload_length < sizeof(uint64_t) + 1) {
+                return "payload too short";
+            }
+
+            uint64_t label_id;
+            memcpy(&label_id, payload, sizeof(label_id));
+
+            // viewers before protocol 4 don't send argument data lengths
+            uint32_t arg_data[6];
+            bool has_arg_data = payload_length >= sizeof(label_id) + 1 + sizeof(arg_data);
+            if (has_arg_data) {
+                memcpy(arg_data, payload + sizeof(label_id) + 1, sizeof(arg_data));
+            }
+
+            bool enabled = payload[sizeof(label_id)] != 0;
+            if (!set_symbol_capture_args(label_id, enabled, has_arg_data ? arg_data : NULL)) {
+                return "unknown symbol";
+            }
+
+            return NULL;
+        }
+        case COMMAND_SET_PAUSED: {
+            if (payload_length < 1) {
+                return "payload too short";
+            }
+
+            set_tracing_paused(payload[0] != 0);
+            return NULL;
+        }
+        case COMMAND_SET_SAMPLING:
+            return set_sampling(state, payload, payload_length);
+        case COMMAND_ARM_CAPTURE: {
+            uint32_t count;
+            uint32_t trigger;
+            uint64_t trigger_value;
+            if (payload_length < sizeof(count) + sizeof(trigger) + sizeof(trigger_value)) {
+                return "payload too short";
+            }
+
+            memcpy(&count, payload, sizeof(count));
+            memcpy(&trigger, payload + sizeof(count), sizeof(trigger));
+            memcpy(&trigger_value, payload + sizeof(count) + sizeof(trigger), sizeof(trigger_value));
+            return arm_capture(sequence, count, trigger, trigger_value);
+        }
+        default:
+            return "unknown command, the plugin may be an older build";
+    }
+}
+
 void *listen_commands_thread(void *arg) {
     struct ListenCommandsThreadArgs* args = arg;
     int sock = args->sock;
+
+    // responses are written like spans, for the flush thread to send
+    struct ThreadLoggingState *state = current_thread_logging_state();
     args->is_ready = true;
 
-    ssize_t bytes_read;
     uint8_t byte;
+    // too large for the stack of the thread
+    static uint8_t frame[MAX_COMMAND_FRAME_LENGTH];
 
     while (true) {
-        bytes_read = recv(sock, &byte, 1, 0);
-
-        if (bytes_read == -1) {
-            final_printf("error reading from socket\n");
-            return NULL;
-        } else if (bytes_read == 0) {
-            final_printf("connection closed by peer\n");
+        if (!recv_all(sock, &byte, 1)) {
             return NULL;
         }
 
         if (byte == 0x0) {
-            capture_next_submit();
-        } else {
+            capture_submits(NO_CAPTURE_REQUEST, 1);
+            continue;
+        }
+
+        if (byte != COMMAND_FRAME_MARKER) {
             final_printf("unknown message received %d\n", byte);
             return NULL;
         }
+
+        uint32_t length;
+        if (!recv_all(sock, (uint8_t *)&length, sizeof(length))) {
+            return NULL;
+        }
+
+        if (length < sizeof(uint32_t) + sizeof(uint16_t) || length > MAX_COMMAND_FRAME_LENGTH) {
+            final_printf("invalid command frame length %u\n", length);
+            return NULL;
+        }
+
+        if (!recv_all(sock, frame, length)) {
+            return NULL;
+        }
+
+        uint32_t sequence;
+        uint16_t command_id;
+        memcpy(&sequence, frame, sizeof(sequence));
+        memcpy(&command_id, frame + sizeof(sequence), sizeof(command_id));
+
+        size_t header_length = sizeof(sequence) + sizeof(command_id);
+        const char *error = run_command(state, sequence, command_id, frame + header_length, length - header_length);
+        if (error != NULL) {
+            final_printf("command %d failed: %s\n", command_id, error);
+        }
+
+        if (state != NULL) {
+            write_command_response(state, sequence, error);
+        }
     }
 }
 
@@ -78,8 +205,11 @@ s32 attr_module_hidden module_start(s64 argc, const void *args)
         return 1;
     }
 
-    final_printf("Configuration loaded: target_address=%s, target_port=%d, original_tls_size=%d\n",
-                 config.target_address, config.target_port, config.original_tls_size);
+    final_printf("Configuration loaded: target_address=%s, target_port=%d, original_tls_size=%d, capture_args=%d, compress=%d, compact_spans=%d\n",
+                 config.target_address, config.target_port, config.original_tls_size, config.capture_args, config.compress, config.compact_spans);
+
+    set_capture_args(config.capture_args);
+    set_compact_spans(config.compact_spans);
 
     int sock = socket(AF_INET, SOCK_STREAM, 0);
     if (sock < 0)
@@ -164,6 +294,7 @@ s32 attr_module_hidden module_start(s64 argc, const void *args)
     DynamicInfo info = parse_dynamic_section(dynlib_segment + offset, dynamic_segment_size, dynlib_segment, dynlib_segment_size);
 
     find_jump_slot_relocations(&info, &jump_slot_relocations);
+    init_symbol_filters(jump_slot_relocations.count);
     
     struct SpecificSymbolsTable specific_symbols_table;
     fill_specific_symbols_table(&jump_slot_relocations, &specific_symbols_table);
@@ -189,6 +320,8 @@ s32 attr_module_hidden module_start(s64 argc, const void *args)
         .dynamic_info = &info,
         .jump_slot_relocations = &jump_slot_relocations,
         .sock = sock,
+        .compress = config.compress,
+        .compact_spans = config.compact_spans,
     };
 
     OrbisPthread flush_thread_thread;
@@ -27,83 +27,618 @@ struct SpanStartAdditionalData
     uint64_t extra_data_length;
 };
 
-struct SpanEnd
+struct SpanStartArgs
+{
+    uint64_t message_tag;
+    uint64_t thread_id;
+    uint64_t time;
+    uint64_t label_id;
+    uint64_t args[6];
+};
+
+struct SpanStartArgsData
+{
+    uint64_t message_tag;
+    uint64_t thread_id;
+    uint64_t time;
+    uint64_t label_id;
+    uint64_t args[6];
+    uint64_t data_length;
+};
+
+// header of the data behind an argument register in SpanStartArgsData
+struct ArgData
+{
+    uint64_t register_idx;
+    uint64_t length;
+};
+
+struct SpanEndReturnValue
+{
+    uint64_t message_tag;
+    uint64_t thread_id;
+    uint64_t time;
+    uint64_t return_value;
+};
+
+struct SamplingUpdate
+{
+    uint64_t message_tag;
+    uint64_t time;
+    uint64_t default_rate;
+    uint64_t rate_count;
+};
+
+struct SamplingRate
+{
+    uint64_t label_id;
+    uint64_t rate;
+};
+
+struct CommandResponse
+{
+    uint64_t message_tag;
+    uint64_t sequence;
+    uint64_t status;
+    uint64_t message_length;
+};
+
+struct ThreadExit
 {
     uint64_t message_tag;
     uint64_t thread_id;
     uint64_t time;
 };
 
-static bool should_capture_next_submit = false;
+static bool is_sync_object_call(uint64_t label_id) {
+    for (size_t i = 0; i < SYNC_OBJECT_CALL_COUNT; i++) {
+        if (label_id == sharedTable.sync_object_calls[i]) {
+            return true;
+        }
+    }
 
-void capture_next_submit() {
-    should_capture_next_submit = true;
+    return false;
 }
 
-void emit_span_start(uint64_t label_id, struct ThreadLoggingState* initial_state, struct Args* args) {
-    struct ThreadLoggingState *state = (struct ThreadLoggingState *)lazy_read_value(initial_state);
-    state->last_label_id = label_id;
+static bool should_capture_args = false;
+
+void set_capture_args(bool enabled) {
+    should_capture_args = enabled;
+}
+
+// one byte tags of the compact encoding, never the first byte of a full message tag
+#define COMPACT_TIME_SYNC 0x80
+#define COMPACT_SPAN_START 0x81
+#define COMPACT_SPAN_END 0x82
+
+// time sync tag and time, then the span tag, delta and value
+#define MAX_COMPACT_SPAN_LENGTH (1 + 1 + 8 + 10 + 10)
+
+static bool should_write_compact_spans = false;
+
+void set_compact_spans(bool enabled) {
+    should_write_compact_spans = enabled;
+}
+
+// 7 bits a byte, least significant first
+static size_t write_varint(uint8_t *out, uint64_t value) {
+    size_t length = 0;
+    while (value >= 0x80) {
+        out[length++] = (value & 0x7f) | 0x80;
+        value >>= 7;
+    }
+
+    out[length++] = value;
+    return length;
+}
+
+// writes a span start or end relative to the last compact span of the thread, along with its
+// label id or return value
+static void write_compact_span(struct ThreadLoggingState *state, uint8_t tag, uint64_t time, uint64_t value) {
+    uint8_t message[MAX_COMPACT_SPAN_LENGTH];
+    size_t length = 0;
+
+    // times can't go back, which a thread moving between cores may see
+    if (!state->has_compact_time || time < state->compact_time) {
+        message[length++] = COMPACT_TIME_SYNC;
+        memcpy(message + length, &time, sizeof(time));
+        length += sizeof(time);
+        state->compact_time = time;
+    }
+
+    message[length++] = tag;
+    length += write_varint(message + length, time - state->compact_time);
+    length += write_varint(message + length, value);
+
+    uint64_t dropped_packets_count = state->dropped_packets_count;
+    write_to_buffer(state, message, length);
+
+    // the next span can only be relative to this one if it made it into the buffer
+    state->has_compact_time = state->dropped_packets_count == dropped_packets_count;
+    state->compact_time = time;
+}
+
+// per symbol settings, by label id
+static size_t symbol_filters_count = 0;
+
+// symbols whose arguments are captured regardless of should_capture_args
+static bool *symbol_capture_args = NULL;
+
+// length of the data captured behind each argument register, 0 for none
+static uint32_t (*symbol_arg_data)[6] = NULL;
+
+// a rate of n traces one in every n calls of the symbol, 0 traces none
+static uint32_t *symbol_sample_rates = NULL;
+static uint32_t *symbol_call_counts = NULL;
+
+void init_symbol_filters(size_t symbol_count) {
+    symbol_capture_args = calloc(symbol_count, sizeof(bool));
+    symbol_arg_data = calloc(symbol_count, sizeof(*symbol_arg_data));
+    symbol_sample_rates = calloc(symbol_count, sizeof(uint32_t));
+    symbol_call_counts = calloc(symbol_count, sizeof(uint32_t));
+    if (symbol_capture_args == NULL || symbol_arg_data == NULL || symbol_sample_rates == NULL || symbol_call_counts == NULL) {
+        final_printf("allocation failed\n");
+        return;
+    }
+
+    for (size_t i = 0; i < symbol_count; i++) {
+        symbol_sample_rates[i] = 1;
+    }
+
+    symbol_filters_count = symbol_count;
+}
+
+bool set_symbol_capture_args(uint64_t label_id, bool enabled, const uint32_t *arg_data) {
+    if (label_id >= symbol_filters_count) {
+        return false;
+    }
+
+    symbol_capture_args[label_id] = enabled;
+    for (int i = 0; i < 6; i++) {
+        uint32_t length = arg_data == NULL ? 0 : arg_data[i];
+        if (length != ARG_DATA_STRING && length > MAX_ARG_DATA_LENGTH) {
+            length = MAX_ARG_DATA_LENGTH;
+        }
+
+        symbol_arg_data[label_id][i] = length;
+    }
+
+    return true;
+}
+
+// call counts are shared between threads without synchronization, so sampling is approximate
+static bool is_sampled(uint64_t label_id) {
+    if (label_id >= symbol_filters_count) {
+        return true;
+    }
+
+    uint32_t rate = symbol_sample_rates[label_id];
+    if (rate <= 1) {
+        return rate == 1;
+    }
+
+    uint32_t call_count = symbol_call_counts[label_id]++;
+    return call_count % rate == 0;
+}
+
+// replaces the rates of every symbol with the default rate followed by a rate for each listed
+// symbol, then writes the applied rates to the trace
+const char *set_sampling(struct ThreadLoggingState *state, const uint8_t *payload, uint32_t payload_length) {
+    uint32_t default_rate;
+    uint32_t rate_count;
+    size_t entry_size = sizeof(uint64_t) + sizeof(uint32_t);
+    if (payload_length < sizeof(default_rate) + sizeof(rate_count)) {
+        return "payload too short";
+    }
+
+    memcpy(&default_rate, payload, sizeof(default_rate));
+    memcpy(&rate_count, payload + sizeof(default_rate), sizeof(rate_count));
+    const uint8_t *entries = payload + sizeof(default_rate) + sizeof(rate_count);
+    if (payload_length < sizeof(default_rate) + sizeof(rate_count) + rate_count * entry_size) {
+        return "payload too short";
+    }
+
+    // check every symbol before changing anything
+    for (uint32_t i = 0; i < rate_count; i++) {
+        uint64_t label_id;
+        memcpy(&label_id, entries + i * entry_size, sizeof(label_id));
+        if (label_id >= symbol_filters_count) {
+            return "unknown symbol";
+        }
+    }
+
+    for (size_t i = 0; i < symbol_filters_count; i++) {
+        symbol_sample_rates[i] = default_rate;
+    }
+
+    for (uint32_t i = 0; i < rate_count; i++) {
+        uint64_t label_id;
+        uint32_t rate;
+        memcpy(&label_id, entries + i * entry_size, sizeof(label_id));
+        memcpy(&rate, entries + i * entry_size + sizeof(label_id), sizeof(rate));
+        symbol_sample_rates[label_id] = rate;
+    }
+
+    if (state == NULL) {
+        return NULL;
+    }
+
+    struct SamplingUpdate update = {
+        .message_tag = 9,
+        .time = get_current_time_rdtscp(),
+        .default_rate = default_rate,
+        .rate_count = rate_count,
+    };
+
+    struct BufferReservation reservation = thread_logging_state_reserve_space(
+        state,
+        sizeof(update) + rate_count * sizeof(struct SamplingRate)
+    );
+    if (reservation.buffer == NULL) {
+        return NULL;
+    }
+
+    buffer_reservation_write(&reservation, (const uint8_t *)&update, sizeof(update));
+    for (uint32_t i = 0; i < rate_count; i++) {
+        uint64_t label_id;
+        memcpy(&label_id, entries + i * entry_size, sizeof(label_id));
+
+        struct SamplingRate rate = {
+            .label_id = label_id,
+            .rate = symbol_sample_rates[label_id],
+        };
+        buffer_reservation_write(&reservation, (const uint8_t *)&rate, sizeof(rate));
+    }
+    thread_logging_state_flush_reservation(state, reservation);
+
+    return NULL;
+}
+
+// submits are captured for the request which asked for them, passed along to the viewer
+struct CaptureRequest {
+    uint64_t request;
+    uint64_t trigger;
+    uint64_t trigger_value;
+};
+
+static uint32_t submits_to_capture = 0;
+static struct CaptureRequest current_capture = { .request = NO_CAPTURE_REQUEST };
+
+void capture_submits(uint64_t request, uint32_t count) {
+    current_capture = (struct CaptureRequest){
+        .request = request,
+        .trigger = CAPTURE_TRIGGER_IMMEDIATE,
+    };
+    submits_to_capture = count;
+}
+
+// a capture waiting for its trigger, replaced when another one is armed
+static bool is_capture_armed = false;
+static uint32_t armed_submit_count = 0;
+static struct CaptureRequest armed_capture;
+
+// time of the last flip, frames are measured between flips whether or not they were traced
+static uint64_t last_flip_time = 0;
+
+const char *arm_capture(uint64_t request, uint32_t count, uint64_t trigger, uint64_t trigger_value) {
+    if (trigger == CAPTURE_TRIGGER_AFTER_SYMBOL && trigger_value >= symbol_filters_count) {
+        return "unknown symbol";
+    }
+
+    if (trigger != CAPTURE_TRIGGER_AFTER_SYMBOL && trigger != CAPTURE_TRIGGER_AFTER_LONG_FRAME) {
+        return "unknown trigger";
+    }
+
+    is_capture_armed = false;
+    armed_capture = (struct CaptureRequest){
+        .request = request,
+        .trigger = trigger,
+        .trigger_value = trigger_value,
+    };
+    armed_submit_count = count;
+    is_capture_armed = true;
+
+    return NULL;
+}
+
+static void fire_armed_capture() {
+    is_capture_armed = false;
+    current_capture = armed_capture;
+    submits_to_capture = armed_submit_count;
+}
+
+static bool is_flip(uint64_t label_id) {
+    return label_id == sharedTable.sceGnmSubmitAndFlipCommandBuffersForWorkload ||
+        label_id == sharedTable.sceGnmSubmitAndFlipCommandBuffers;
+}
+
+// records the flip, returning whether it ended a frame long enough to fire the armed capture
+static bool is_long_frame_flip(uint64_t label_id) {
+    if (!is_flip(label_id)) {
+        return false;
+    }
+
     uint64_t time = get_current_time_rdtscp();
-    if (label_id == sharedTable.sceGnmSubmitAndFlipCommandBuffersForWorkload && should_capture_next_submit) {
-        should_capture_next_submit = false;
+    bool is_long_frame = last_flip_time != 0 && time - last_flip_time > armed_capture.trigger_value;
+    last_flip_time = time;
+
+    return is_capture_armed && armed_capture.trigger == CAPTURE_TRIGGER_AFTER_LONG_FRAME && is_long_frame;
+}
+
+static bool is_paused = false;
+
+void set_tracing_paused(bool paused) {
+    is_paused = paused;
+}
+
+void write_command_response(struct ThreadLoggingState *state, uint32_t sequence, const char *error) {
+    size_t message_length = error == NULL ? 0 : strlen(error);
+
+    struct CommandResponse response = {
+        .message_tag = 8,
+        .sequence = sequence,
+        .status = error == NULL ? 0 : 1,
+        .message_length = message_length,
+    };
+
+    // reserved together so the flush thread never sends half of the response
+    struct BufferReservation reservation = thread_logging_state_reserve_space(state, sizeof(response) + message_length);
+    if (reservation.buffer == NULL) {
+        return;
+    }
+
+    buffer_reservation_write(&reservation, (const uint8_t *)&response, sizeof(response));
+    if (message_length > 0) {
+        buffer_reservation_write(&reservation, (const uint8_t *)error, message_length);
+    }
+    thread_logging_state_flush_reservation(state, reservation);
+}
+
+void write_thread_exit(struct ThreadLoggingState *state) {
+    struct ThreadExit exit = {
+        .message_tag = 13,
+        .thread_id = state->thread_id,
+        .time = get_current_time_rdtscp(),
+    };
+
+    write_to_buffer(state, (const uint8_t *)&exit, sizeof(exit));
+}
+
+// writes the argument registers along with the data behind the registers the viewer asked for.
+// the call reads that data itself, so pointers which aren't NULL are expected to be valid.
+static void write_span_start_args_data(struct ThreadLoggingState *state, uint64_t time, uint64_t label_id, struct Args* args) {
+    const uint32_t *arg_data = symbol_arg_data[label_id];
+
+    struct SpanStartArgsData span = {
+        .message_tag = 14,
+        .thread_id = state->thread_id,
+        .time = time,
+        .label_id = label_id,
+        .data_length = 0,
+    };
+
+    size_t lengths[6] = {0};
+    for (int i = 0; i < 6; i++) {
+        span.args[i] = args->args[i];
+
+        if (arg_data[i] == 0 || args->args[i] == 0) {
+            continue;
+        }
+
+        // strings longer than MAX_ARG_DATA_LENGTH are cut off
+        const char *data = (const char *)args->args[i];
+        lengths[i] = arg_data[i] == ARG_DATA_STRING ? strnlen(data, MAX_ARG_DATA_LENGTH) : arg_data[i];
+        span.data_length += sizeof(struct ArgData) + lengths[i];
+    }
+
+    // reserved together so the flush thread never sends half of the span
+    struct BufferReservation reservation = thread_logging_state_reserve_space(state, sizeof(span) + span.data_length);
+    if (reservation.buffer == NULL) {
+        return;
+    }
+
+    buffer_reservation_write(&reservation, (const uint8_t *)&span, sizeof(span));
+    for (int i = 0; i < 6; i++) {
+        if (arg_data[i] == 0 || args->args[i] == 0) {
+            continue;
+        }
+
+        struct ArgData header = {
+            .register_idx = i,
+            .length = lengths[i],
+        };
+
+        buffer_reservation_write(&reservation, (const uint8_t *)&header, sizeof(header));
+        buffer_reservation_write(&reservation, (const uint8_t *)args->args[i], lengths[i]);
+    }
+    thread_logging_state_flush_reservation(state, reservation);
+}
+
+static bool has_arg_data(uint64_t label_id) {
+    if (label_id >= symbol_filters_count) {
+        return false;
+    }
+
+    for (int i = 0; i < 6; i++) {
+        if (symbol_arg_data[label_id][i] != 0) {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+static void write_span_start(struct ThreadLoggingState *state, uint64_t time, uint64_t label_id, struct Args* args) {
+    bool capture_args = should_capture_args ||
+        (label_id < symbol_filters_count && symbol_capture_args[label_id]);
+
+    if (capture_args && has_arg_data(label_id)) {
+        write_span_start_args_data(state, time, label_id, args);
+        return;
+    }
+
+    if (capture_args) {
+        struct SpanStartArgs span = {
+            .message_tag = 6,
+            .thread_id = state->thread_id,
+            .time = time,
+            .label_id = label_id,
+        };
+
+        for (int i = 0; i < 6; i++) {
+            span.args[i] = args->args[i];
+        }
+
+        write_to_buffer(state, (const uint8_t *)&span, sizeof(span));
+        return;
+    }
+
+    if (should_write_compact_spans) {
+        write_compact_span(state, COMPACT_SPAN_START, time, label_id);
+        return;
+    }
+
+    struct SpanStart span = {
+        .message_tag = 0,
+        .thread_id = state->thread_id,
+        .time = time,
+        .label_id = label_id,
+    };
+
+    write_to_buffer(state, (const uint8_t *)&span, sizeof(span));
+}
+
+static void trace_span_start(struct ThreadLoggingState *state, uint64_t label_id, struct Args* args) {
+    uint64_t time = get_current_time_rdtscp();
+    if (label_id == sharedTable.sceGnmSubmitAndFlipCommandBuffersForWorkload && submits_to_capture > 0) {
+        submits_to_capture -= 1;
 
         sceGnmSubmitAndFlipCommandBuffersForWorkload_trace(
-            args, state, time, label_id, state->thread_id
+            args, state, time, label_id, state->thread_id, &current_capture
         );
-    } else if (label_id == sharedTable.sceGnmSubmitAndFlipCommandBuffers && should_capture_next_submit) {
-        should_capture_next_submit = false;
+    } else if (label_id == sharedTable.sceGnmSubmitAndFlipCommandBuffers && submits_to_capture > 0) {
+        submits_to_capture -= 1;
 
         sceGnmSubmitAndFlipCommandBuffers_trace(
-            args, state, time, label_id, state->thread_id
+            args, state, time, label_id, state->thread_id, &current_capture
         );
 
-    } else if (label_id == sharedTable.sceGnmSubmitCommandBuffers && should_capture_next_submit) {
-        should_capture_next_submit = false;
+    } else if (label_id == sharedTable.sceGnmSubmitCommandBuffers && submits_to_capture > 0) {
+        submits_to_capture -= 1;
 
         sceGnmSubmitCommandBuffers_trace(
-            args, state, time, label_id, state->thread_id
+            args, state, time, label_id, state->thread_id, &current_capture
         );
     } else if (label_id == sharedTable.sceSysmoduleLoadModule) {
         sceSysmoduleLoadModule_trace(args);
 
-        struct SpanStart span = {
-            .message_tag = 0,
-            .thread_id = state->thread_id,
-            .time = time,
-            .label_id = label_id,
-        };
-
-        write_to_buffer(state, (const uint8_t *)&span, sizeof(span));
+        write_span_start(state, time, label_id, args);
     } else if (label_id == sharedTable.sceAjmBatchJobRunBufferRa) {
         sceAjmBatchJobRunBufferRa_trace(args, state, time, label_id, state->thread_id);
     } else if (label_id == sharedTable.sceAjmBatchJobControlBufferRa) {
         sceAjmBatchJobControlBufferRa_trace(args, state, time, label_id, state->thread_id);
     } else if (label_id == sharedTable.sceHttpSendRequest) {
         sceHttpSendRequest_trace(args, state, time, label_id, state->thread_id);
+    } else if (label_id == sharedTable.scePthreadCreate) {
+        state->created_thread = (OrbisPthread *)args->args[0];
+
+        scePthreadCreate_trace(args, state, time, label_id, state->thread_id);
+    } else if (label_id == sharedTable.scePthreadRename) {
+        scePthreadRename_trace(args, state, time, label_id, state->thread_id);
+    } else if (label_id == sharedTable.pthread_setname_np) {
+        pthread_setname_np_trace(args, state, time, label_id, state->thread_id);
+    } else if (is_sync_object_call(label_id)) {
+        sync_object_trace(args, state, time, label_id, state->thread_id);
     } else {
-        struct SpanStart span = {
-            .message_tag = 0,
-            .thread_id = state->thread_id,
-            .time = time,
-            .label_id = label_id,
-        };
+        write_span_start(state, time, label_id, args);
+    }
+}
 
-        write_to_buffer(state, (const uint8_t *)&span, sizeof(span));
+// label id of calls nested too deep to be followed
+#define UNKNOWN_LABEL_ID UINT64_MAX
+
+static void push_call(struct ThreadLoggingState *state, uint64_t label_id) {
+    if (state->call_depth < MAX_CALL_DEPTH) {
+        state->call_labels[state->call_depth] = label_id;
+    }
+
+    state->call_depth += 1;
+}
+
+// label id of the call which is returning
+static uint64_t pop_call(struct ThreadLoggingState *state) {
+    if (state->call_depth == 0) {
+        return UNKNOWN_LABEL_ID;
+    }
+
+    state->call_depth -= 1;
+    if (state->call_depth >= MAX_CALL_DEPTH) {
+        return UNKNOWN_LABEL_ID;
+    }
+
+    return state->call_labels[state->call_depth];
+}
+
+void emit_span_start(uint64_t label_id, struct ThreadLoggingState* initial_state, struct Args* args) {
+    struct ThreadLoggingState *state = (struct ThreadLoggingState *)lazy_read_value(initial_state);
+    push_call(state, label_id);
+
+    bool fires_capture = is_long_frame_flip(label_id);
+
+    // calls entered while paused or left out by sampling are dropped along with the calls they
+    // make
+    if (is_paused || state->skipped_depth > 0 || !is_sampled(label_id)) {
+        state->skipped_depth += 1;
+    } else {
+        trace_span_start(state, label_id, args);
+    }
+
+    // the submits after the long frame are captured, not the flip ending it
+    if (fires_capture) {
+        fire_armed_capture();
     }
 }
 
 void emit_span_end(struct ThreadLoggingState* initial_state, void* return_value) {
     struct ThreadLoggingState *state = (struct ThreadLoggingState *)lazy_read_value(initial_state);
     uint64_t time = get_current_time_rdtscp();
+    uint64_t label_id = pop_call(state);
 
-    if (state->last_label_id == sharedTable.sceSysmoduleLoadModule) {
+    if (label_id == sharedTable.sceSysmoduleLoadModule) {
         reregister_hooks();
     }
 
-    struct SpanEnd span = {
-        .message_tag = 1,
+    if (is_capture_armed && armed_capture.trigger == CAPTURE_TRIGGER_AFTER_SYMBOL &&
+        armed_capture.trigger_value == label_id) {
+        fire_armed_capture();
+    }
+
+    if (state->skipped_depth > 0) {
+        state->skipped_depth -= 1;
+        return;
+    }
+
+    if (label_id == sharedTable.scePthreadCreate && state->created_thread != NULL) {
+        OrbisPthread *created_thread = state->created_thread;
+        state->created_thread = NULL;
+
+        if (return_value == 0) {
+            uint64_t created_thread_id = thread_id_of(*created_thread);
+            scePthreadCreate_end_trace(state, time, state->thread_id, created_thread_id);
+            return;
+        }
+    }
+
+    if (should_write_compact_spans) {
+        write_compact_span(state, COMPACT_SPAN_END, time, (uint64_t)return_value);
+        return;
+    }
+
+    struct SpanEndReturnValue span = {
+        .message_tag = 5,
         .thread_id = state->thread_id,
         .time = time,
+        .return_value = (uint64_t)return_value,
     };
 
     write_to_buffer(state, (const uint8_t *)&span, sizeof(span));
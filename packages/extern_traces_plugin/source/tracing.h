@@ -30,4 +30,31 @@ void emit_span_start(uint64_t label_id, struct ThreadLoggingState *initial_state
 void emit_span_end(struct ThreadLoggingState *initial_state, void* return_value);
 
 void initialize_specific_symbols_table(struct SpecificSymbolsTable* table);
-void capture_next_submit();
\ No newline at end of file
+// request of captures asked for by older viewers, which aren't tagged with a request
+#define NO_CAPTURE_REQUEST UINT64_MAX
+
+enum CaptureTrigger {
+    CAPTURE_TRIGGER_IMMEDIATE = 0,
+    // the next submits once a call of a symbol, given as the trigger value, returns
+    CAPTURE_TRIGGER_AFTER_SYMBOL = 1,
+    // the next submits once a frame took longer than the trigger value, in tsc cycles
+    CAPTURE_TRIGGER_AFTER_LONG_FRAME = 2,
+};
+
+void capture_submits(uint64_t request, uint32_t count);
+const char *arm_capture(uint64_t request, uint32_t count, uint64_t trigger, uint64_t trigger_value);
+void set_capture_args(bool enabled);
+void set_compact_spans(bool enabled);
+void init_symbol_filters(size_t symbol_count);
+// length of argument data which is a nul terminated string rather than a fixed number of bytes
+#define ARG_DATA_STRING UINT32_MAX
+// most bytes captured behind an argument register
+#define MAX_ARG_DATA_LENGTH 1024
+
+// arg_data holds the length of the data to capture behind each argument register, or is NULL to
+// only capture the registers
+bool set_symbol_capture_args(uint64_t label_id, bool enabled, const uint32_t *arg_data);
+const char *set_sampling(struct ThreadLoggingState *state, const uint8_t *payload, uint32_t payload_length);
+void set_tracing_paused(bool paused);
+void write_command_response(struct ThreadLoggingState *state, uint32_t sequence, const char *error);
+void write_thread_exit(struct ThreadLoggingState *state);
\ No newline at end of file
@@ -0,0 +1,245 @@
+use crate::app::tracing::utils::format_time;
+use crate::app::Scene;
+use crate::diff::{TraceDiff, TraceSummary};
+use crate::stats::DurationSummary;
+use eframe::egui;
+use eframe::egui::{vec2, CentralPanel, Color32, Context, Frame, Grid, Margin, ScrollArea, Ui};
+use ps4libdoc::LoadedDocumentation;
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::thread::JoinHandle;
+
+/// Compares per symbol and per frame durations of two trace files.
+pub struct DiffScene {
+    base_path: PathBuf,
+    changed_path: PathBuf,
+    loading_thread_handle: Option<JoinHandle<io::Result<TraceDiff>>>,
+    diff: Option<io::Result<TraceDiff>>,
+    filter: String,
+}
+
+impl DiffScene {
+    pub fn from_file_paths(ctx: Context, base_path: PathBuf, changed_path: PathBuf) -> DiffScene {
+        let loading_thread_handle = {
+            let base_path = base_path.clone();
+            let changed_path = changed_path.clone();
+
+            thread::spawn(move || {
+                let result = TraceDiff::read(&base_path, &changed_path);
+                ctx.request_repaint();
+
+                result
+            })
+        };
+
+        DiffScene {
+            base_path,
+            changed_path,
+            loading_thread_handle: Some(loading_thread_handle),
+            diff: None,
+            filter: String::new(),
+        }
+    }
+
+    pub fn update(&mut self, ctx: &Context, docs: &LoadedDocumentation) -> Option<Scene> {
+        if self
+            .loading_thread_handle
+            .as_ref()
+            .is_some_and(|it| it.is_finished())
+        {
+            let result = match self.loading_thread_handle.take().unwrap().join() {
+                Ok(result) => result,
+                Err(_) => Err(io::Error::other("background thread panicked")),
+            };
+
+            self.diff = Some(result);
+        }
+
+        let mut next_scene = None;
+
+        CentralPanel::default()
+            .frame(Frame {
+                fill: ctx.style().visuals.panel_fill,
+                inner_margin: Margin::symmetric(8., 4.),
+                ..Default::default()
+            })
+            .show(ctx, |ui| {
+                ui.allocate_space(vec2(ui.available_width(), 0.));
+
+                ui.horizontal(|ui| {
+                    if ui.button("back").clicked() {
+                        next_scene.replace(Scene::initial());
+                    }
+
+                    ui.heading("compare traces");
+                });
+
+                ui.label(format!("base: {}", self.base_path.display()));
+                ui.label(format!("changed: {}", self.changed_path.display()));
+
+                match &self.diff {
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("reading traces");
+                        });
+                    }
+                    Some(Err(err)) => {
+                        ui.colored_label(
+                            ui.style().visuals.error_fg_color,
+                            format!("failed to load traces: {}", err),
+                        );
+                    }
+                    Some(Ok(diff)) => render_diff(ui, diff, docs, &mut self.filter),
+                }
+            });
+
+        next_scene
+    }
+}
+
+fn render_diff(ui: &mut Ui, diff: &TraceDiff, docs: &LoadedDocumentation, filter: &mut String) {
+    ui.add_space(8.);
+
+    if diff.base.frames.is_some() || diff.changed.frames.is_some() {
+        ui.strong("frames");
+
+        Grid::new("frame_diff")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("base");
+                ui.label("changed");
+                ui.label("change");
+                ui.end_row();
+
+                let frames = (diff.base.frames.as_ref(), diff.changed.frames.as_ref());
+                render_count_row(ui, "frames", frames);
+                render_duration_row(ui, diff, "mean", frames, |it| it.mean);
+                render_duration_row(ui, diff, "median", frames, |it| it.p50);
+                render_duration_row(ui, diff, "p95", frames, |it| it.p95);
+                render_duration_row(ui, diff, "max", frames, |it| it.max);
+            });
+
+        ui.add_space(8.);
+    }
+
+    ui.horizontal(|ui| {
+        ui.strong("symbols");
+        ui.add(egui::TextEdit::singleline(filter).hint_text("filter"));
+    });
+
+    let filter = filter.to_lowercase();
+    let symbols = diff
+        .symbols
+        .iter()
+        .map(|key| (key, diff.symbol_name(docs, key)))
+        .filter(|(_, name)| name.to_lowercase().contains(&filter))
+        .collect::<Vec<_>>();
+
+    ScrollArea::vertical().show(ui, |ui| {
+        Grid::new("symbol_diff")
+            .num_columns(7)
+            .striped(true)
+            .show(ui, |ui| {
+                for label in [
+                    "symbol",
+                    "base calls",
+                    "changed calls",
+                    "base total",
+                    "changed total",
+                    "change",
+                    "mean change",
+                ] {
+                    ui.label(label);
+                }
+                ui.end_row();
+
+                for (key, name) in symbols {
+                    let base = diff.base.symbols.get(key).map(|it| &it.durations);
+                    let changed = diff.changed.symbols.get(key).map(|it| &it.durations);
+
+                    ui.label(name);
+                    ui.label(base.map_or(0, |it| it.count).to_string());
+                    ui.label(changed.map_or(0, |it| it.count).to_string());
+                    ui.label(optional_time(&diff.base, base.map(|it| it.total)));
+                    ui.label(optional_time(&diff.changed, changed.map(|it| it.total)));
+                    render_delta(ui, diff.total_delta(key));
+
+                    match (base, changed) {
+                        (Some(base), Some(changed)) => render_delta(
+                            ui,
+                            diff.changed.seconds(changed.mean) - diff.base.seconds(base.mean),
+                        ),
+                        _ => {
+                            ui.label("-");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+fn render_count_row(
+    ui: &mut Ui,
+    label: &str,
+    (base, changed): (Option<&DurationSummary>, Option<&DurationSummary>),
+) {
+    let base = base.map_or(0, |it| it.count);
+    let changed = changed.map_or(0, |it| it.count);
+
+    ui.label(label);
+    ui.label(base.to_string());
+    ui.label(changed.to_string());
+    ui.label(format!("{:+}", changed as i64 - base as i64));
+    ui.end_row();
+}
+
+fn render_duration_row(
+    ui: &mut Ui,
+    diff: &TraceDiff,
+    label: &str,
+    (base, changed): (Option<&DurationSummary>, Option<&DurationSummary>),
+    value: fn(&DurationSummary) -> u64,
+) {
+    ui.label(label);
+    ui.label(optional_time(&diff.base, base.map(value)));
+    ui.label(optional_time(&diff.changed, changed.map(value)));
+    match (base, changed) {
+        (Some(base), Some(changed)) => render_delta(
+            ui,
+            diff.changed.seconds(value(changed)) - diff.base.seconds(value(base)),
+        ),
+        _ => {
+            ui.label("-");
+        }
+    }
+    ui.end_row();
+}
+
+fn optional_time(trace: &TraceSummary, cycles: Option<u64>) -> String {
+    match cycles {
+        Some(cycles) => format_time(trace.seconds(cycles)),
+        None => "-".to_string(),
+    }
+}
+
+/// Shows a change in seconds, red when it got slower and green when it got faster.
+fn render_delta(ui: &mut Ui, seconds: f64) {
+    let text = if seconds < 0. {
+        format!("-{}", format_time(-seconds))
+    } else {
+        format!("+{}", format_time(seconds))
+    };
+
+    if seconds > 0. {
+        ui.colored_label(Color32::RED, text);
+    } else if seconds < 0. {
+        ui.colored_label(Color32::GREEN, text);
+    } else {
+        ui.label(text);
+    }
+}
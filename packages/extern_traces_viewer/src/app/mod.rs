@@ -1,14 +1,19 @@
+use crate::app::diff::DiffScene;
 use crate::app::start::StartScene;
 use crate::app::tracing::TracingScene;
+use crate::trace_file;
 use eframe::egui::Context;
 use ps4libdoc::LoadedDocumentation;
+use rfd::FileDialog;
 
+pub mod diff;
 pub mod start;
 pub mod tracing;
 
 pub enum Scene {
     Start(StartScene),
     Tracing(TracingScene),
+    Diff(DiffScene),
 }
 
 impl Scene {
@@ -17,6 +22,15 @@ impl Scene {
     }
 }
 
+/// A file dialog for opening traces, which tells saved traces apart from raw captures.
+pub fn trace_file_dialog() -> FileDialog {
+    FileDialog::new()
+        .add_filter("traces", &[trace_file::EXTENSION, "bin"])
+        .add_filter("saved traces", &[trace_file::EXTENSION])
+        .add_filter("raw captures", &["bin"])
+        .add_filter("all files", &["*"])
+}
+
 pub struct App {
     docs: LoadedDocumentation,
     scene: Scene,
@@ -38,6 +52,7 @@ impl eframe::App for App {
         let next_scene = match self.scene {
             Scene::Start(ref mut landing_page) => landing_page.update(ctx),
             Scene::Tracing(ref mut tracing_scene) => tracing_scene.update(ctx, &self.docs),
+            Scene::Diff(ref mut diff_scene) => diff_scene.update(ctx, &self.docs),
         };
 
         if let Some(next_scene) = next_scene {
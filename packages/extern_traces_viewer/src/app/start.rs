@@ -1,7 +1,8 @@
+use crate::app::diff::DiffScene;
+use crate::app::tracing::recording::Recording;
 use crate::app::tracing::TracingScene;
-use crate::app::Scene;
+use crate::app::{trace_file_dialog, Scene};
 use eframe::egui::{vec2, CentralPanel, Context, Frame, Key, Margin};
-use rfd::FileDialog;
 use std::net::{AddrParseError, SocketAddr};
 use std::str::FromStr;
 
@@ -56,7 +57,7 @@ impl StartScene {
                     ui.label("load traces");
                     let button = ui.button("open");
                     if button.clicked() {
-                        if let Some(file_path) = FileDialog::new().pick_file() {
+                        if let Some(file_path) = trace_file_dialog().pick_file() {
                             next_scene.replace(Scene::Tracing(TracingScene::from_file_path(
                                 ctx.clone(),
                                 file_path,
@@ -67,6 +68,26 @@ impl StartScene {
 
                 ui.allocate_space(vec2(0., 16.));
 
+                ui.vertical(|ui| {
+                    ui.label("compare traces");
+                    let button = ui.button("open base and changed");
+                    if button.clicked() {
+                        if let Some((base_path, changed_path)) = trace_file_dialog()
+                            .set_title("base trace")
+                            .pick_file()
+                            .zip(trace_file_dialog().set_title("changed trace").pick_file())
+                        {
+                            next_scene.replace(Scene::Diff(DiffScene::from_file_paths(
+                                ctx.clone(),
+                                base_path,
+                                changed_path,
+                            )));
+                        }
+                    }
+                });
+
+                ui.allocate_space(vec2(0., 16.));
+
                 ui.vertical(|ui| {
                     ui.label("listen for traces");
 
@@ -82,6 +103,7 @@ impl StartScene {
                                 next_scene.replace(Scene::Tracing(TracingScene::from_network(
                                     ctx.clone(),
                                     addr,
+                                    Recording::temporary(),
                                 )));
                             }
                             Err(err) => {
@@ -0,0 +1,128 @@
+use crate::proto::TraceEvent;
+use crate::spans::{ReorderCounts, SpanAssembler, ThreadSpan};
+use crate::trace_file::{ChunkInfo, TraceFile, TraceIndex};
+use eframe::egui::Context;
+use std::collections::BTreeMap;
+use std::io;
+use std::ops::Range;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+pub struct ChunkRequest {
+    pub thread_id: u64,
+    pub chunks: Range<usize>,
+}
+
+pub struct LoadedChunks {
+    pub thread_id: u64,
+    pub chunks: Range<usize>,
+    pub spans: Vec<ThreadSpan>,
+    pub reorder_counts: ReorderCounts,
+}
+
+/// Loads the spans of chunks of an indexed trace file on a background thread.
+pub struct ChunkLoader {
+    requests: Sender<Vec<ChunkRequest>>,
+    responses: Receiver<io::Result<LoadedChunks>>,
+    last_requested: BTreeMap<u64, Range<usize>>,
+}
+
+impl ChunkLoader {
+    pub fn spawn(ctx: Context, file: TraceFile, index: &TraceIndex) -> ChunkLoader {
+        let (requests, receiver_requests) = channel::<Vec<ChunkRequest>>();
+        let (sender_responses, responses) = channel();
+        let threads = index.threads.clone();
+
+        thread::spawn(move || {
+            while let Ok(requests) = receiver_requests.recv() {
+                // only the latest request for each thread matters
+                let mut pending = BTreeMap::new();
+                for request in requests
+                    .into_iter()
+                    .chain(receiver_requests.try_iter().flatten())
+                {
+                    pending.insert(request.thread_id, request.chunks);
+                }
+
+                for (thread_id, chunks) in pending {
+                    let Some(thread_chunks) = threads.get(&thread_id) else {
+                        continue;
+                    };
+
+                    let result = load_spans(&file, &thread_chunks[chunks.clone()]).map(
+                        |(spans, reorder_counts)| LoadedChunks {
+                            thread_id,
+                            chunks,
+                            spans,
+                            reorder_counts,
+                        },
+                    );
+
+                    if sender_responses.send(result).is_err() {
+                        return;
+                    }
+
+                    ctx.request_repaint();
+                }
+            }
+        });
+
+        ChunkLoader {
+            requests,
+            responses,
+            last_requested: BTreeMap::new(),
+        }
+    }
+
+    pub fn request(&mut self, requests: Vec<ChunkRequest>) {
+        let requests = requests
+            .into_iter()
+            .filter(|it| self.last_requested.get(&it.thread_id) != Some(&it.chunks))
+            .collect::<Vec<_>>();
+
+        if requests.is_empty() {
+            return;
+        }
+
+        for request in &requests {
+            self.last_requested
+                .insert(request.thread_id, request.chunks.clone());
+        }
+
+        let _ = self.requests.send(requests);
+    }
+
+    pub fn try_recv(&self) -> Option<io::Result<LoadedChunks>> {
+        self.responses.try_recv().ok()
+    }
+}
+
+fn load_spans(
+    file: &TraceFile,
+    chunks: &[ChunkInfo],
+) -> io::Result<(Vec<ThreadSpan>, ReorderCounts)> {
+    let mut assembler = match chunks.first() {
+        Some(chunk) => {
+            SpanAssembler::resume_after(chunk.previous_end_time, chunk.open_starts.clone())
+        }
+        None => SpanAssembler::new(),
+    };
+    let mut spans = Vec::new();
+
+    for chunk in chunks {
+        for event in file.chunk_events(chunk)? {
+            let TraceEvent::Span(event) = event else {
+                continue;
+            };
+
+            if let Some(span) = assembler.push(event) {
+                spans.push(span);
+            }
+        }
+
+        // matches how spans were counted when the chunks were written
+        spans.extend(assembler.drain());
+    }
+
+    Ok((spans, assembler.counts()))
+}
@@ -0,0 +1,243 @@
+use crate::proto::{CaptureTrigger, CommandResponse, SamplingFilter, TraceCommand};
+use crate::sampling::describe_rate;
+use std::sync::mpsc::Sender;
+
+/// Commands sent to the plugin of a live trace, along with its answers to them.
+pub struct PluginCommands {
+    /// Encoded commands, written to the connection by the server thread.
+    sender: Sender<Vec<u8>>,
+
+    /// Command protocol version announced by the plugin. Plugins which don't announce one only
+    /// understand capturing the next submit, and don't answer.
+    protocol_version: Option<u64>,
+    next_sequence: u32,
+
+    pub sent: Vec<SentCommand>,
+}
+
+pub struct SentCommand {
+    /// Sequence number the plugin answers with, for framed commands.
+    pub sequence: Option<u32>,
+    pub command: TraceCommand,
+    pub status: CommandStatus,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CommandStatus {
+    /// Waiting for the plugin to answer.
+    Pending,
+
+    /// Sent to a plugin which doesn't answer commands.
+    Unacknowledged,
+    Applied,
+    Failed(String),
+}
+
+impl PluginCommands {
+    pub fn new(sender: Sender<Vec<u8>>) -> PluginCommands {
+        PluginCommands {
+            sender,
+            protocol_version: None,
+            next_sequence: 0,
+            sent: Vec::new(),
+        }
+    }
+
+    pub fn protocol_version(&self) -> Option<u64> {
+        self.protocol_version
+    }
+
+    pub fn set_protocol_version(&mut self, version: u64) {
+        self.protocol_version = Some(version);
+    }
+
+    /// Whether the plugin understands a command.
+    pub fn supports(&self, command: &TraceCommand) -> bool {
+        match self.protocol_version {
+            Some(version) => command.protocol_version() <= version,
+            None => command.encode_legacy().is_some(),
+        }
+    }
+
+    pub fn send(&mut self, command: TraceCommand) {
+        let encoded = if !self.supports(&command) {
+            None
+        } else if self.protocol_version.is_some() {
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+
+            Some((
+                Some(sequence),
+                command.encode(sequence),
+                CommandStatus::Pending,
+            ))
+        } else {
+            command
+                .encode_legacy()
+                .map(|frame| (None, frame, CommandStatus::Unacknowledged))
+        };
+
+        let Some((sequence, frame, mut status)) = encoded else {
+            self.sent.push(SentCommand {
+                sequence: None,
+                command,
+                status: CommandStatus::Failed(
+                    "not supported by the plugin, it may be an older build".to_string(),
+                ),
+            });
+            return;
+        };
+
+        if self.sender.send(frame).is_err() {
+            status = CommandStatus::Failed("the plugin disconnected".to_string());
+        }
+
+        self.sent.push(SentCommand {
+            sequence,
+            command,
+            status,
+        });
+    }
+
+    pub fn respond(&mut self, response: CommandResponse) {
+        let Some(sent) = self
+            .sent
+            .iter_mut()
+            .rev()
+            .find(|it| it.sequence == Some(response.sequence))
+        else {
+            return;
+        };
+
+        sent.status = match response.error {
+            None => CommandStatus::Applied,
+            Some(error) => CommandStatus::Failed(error),
+        };
+    }
+
+    /// Whether tracing was last asked to pause, and that wasn't refused.
+    pub fn is_paused(&self) -> bool {
+        self.last_accepted(|command| match command {
+            TraceCommand::SetPaused { paused } => Some(*paused),
+            _ => None,
+        })
+        .unwrap_or(false)
+    }
+
+    /// Whether arguments of a symbol were last asked to be captured, and that wasn't refused.
+    pub fn is_capturing_args(&self, symbol_idx: u64) -> bool {
+        self.last_accepted(|command| match command {
+            TraceCommand::SetArgCapture {
+                label_id, enabled, ..
+            } if *label_id == symbol_idx => Some(*enabled),
+            _ => None,
+        })
+        .unwrap_or(false)
+    }
+
+    /// The sampling filter last sent to the plugin, and not refused.
+    pub fn sampling(&self) -> Option<SamplingFilter> {
+        self.last_accepted(|command| match command {
+            TraceCommand::SetSampling(filter) => Some(filter.clone()),
+            _ => None,
+        })
+    }
+
+    fn last_accepted<T>(&self, value: impl Fn(&TraceCommand) -> Option<T>) -> Option<T> {
+        self.sent
+            .iter()
+            .rev()
+            .filter(|it| !matches!(it.status, CommandStatus::Failed(..)))
+            .find_map(|it| value(&it.command))
+    }
+}
+
+pub fn describe_command(command: &TraceCommand) -> String {
+    match command {
+        TraceCommand::CaptureSubmits { count: 1 } => "capture next submit".to_string(),
+        TraceCommand::CaptureSubmits { count } => format!("capture next {} submits", count),
+        TraceCommand::SetArgCapture {
+            label_id, enabled, ..
+        } => format!(
+            "{} argument capture for symbol {}",
+            if *enabled { "enable" } else { "disable" },
+            label_id
+        ),
+        TraceCommand::SetPaused { paused: true } => "pause tracing".to_string(),
+        TraceCommand::SetPaused { paused: false } => "resume tracing".to_string(),
+        TraceCommand::SetSampling(filter) => format!(
+            "set sampling of {} symbols, others {}",
+            filter.rates.len(),
+            describe_rate(filter.default_rate)
+        ),
+        TraceCommand::ArmCapture { count, trigger } => format!(
+            "capture next {} submits {}",
+            count,
+            match trigger {
+                CaptureTrigger::Immediate => "now".to_string(),
+                CaptureTrigger::AfterSymbol { label_id } =>
+                    format!("after a call of symbol {}", label_id),
+                CaptureTrigger::AfterLongFrame { cycles } =>
+                    format!("after a frame over {} cycles", cycles),
+            }
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tracing::commands::{CommandStatus, PluginCommands};
+    use crate::proto::{CommandResponse, TraceCommand};
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn falls_back_to_legacy_commands() {
+        let (sender, receiver) = channel();
+        let mut commands = PluginCommands::new(sender);
+
+        commands.send(TraceCommand::CaptureSubmits { count: 1 });
+        commands.send(TraceCommand::SetPaused { paused: true });
+
+        assert_eq!(receiver.try_recv().unwrap(), vec![0x0]);
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(commands.sent[0].status, CommandStatus::Unacknowledged);
+        assert!(matches!(commands.sent[1].status, CommandStatus::Failed(..)));
+        assert!(!commands.is_paused());
+    }
+
+    #[test]
+    fn matches_responses_to_framed_commands() {
+        let (sender, receiver) = channel();
+        let mut commands = PluginCommands::new(sender);
+        commands.set_protocol_version(1);
+
+        commands.send(TraceCommand::SetPaused { paused: true });
+        commands.send(TraceCommand::SetArgCapture {
+            label_id: 3,
+            enabled: true,
+            arg_data: [0; 6],
+        });
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            vec![0xff, 7, 0, 0, 0, 0, 0, 0, 0, 2, 0, 1]
+        );
+        assert_eq!(
+            receiver.try_recv().unwrap().len(),
+            1 + 4 + 4 + 2 + 9 + 6 * 4
+        );
+
+        commands.respond(CommandResponse {
+            sequence: 1,
+            error: Some("unknown symbol".to_string()),
+        });
+        commands.respond(CommandResponse {
+            sequence: 0,
+            error: None,
+        });
+
+        assert_eq!(commands.sent[0].status, CommandStatus::Applied);
+        assert!(commands.is_paused());
+        assert!(!commands.is_capturing_args(3));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::app::tracing::utils::format_time;
+use crate::app::tracing::view_state::ViewState;
+use eframe::egui;
+use eframe::egui::{pos2, vec2, Color32, Rect, Rounding, Stroke, Ui};
+use std::ops::Range;
+
+const GRAPH_HEIGHT: f32 = 48.;
+
+/// Frame times within these budgets are drawn green and yellow, anything slower red.
+const TARGET_60_FPS: f64 = 1. / 60.;
+const TARGET_30_FPS: f64 = 1. / 30.;
+
+/// Frame times are drawn up to this height, so one long stall doesn't flatten every other bar.
+const MAX_SHOWN_FRAME_TIME: f64 = 0.1;
+
+/// Draws a bar per frame above the timeline. Clicking a bar zooms the timeline to the frame.
+pub fn render_frame_graph(ui: &mut Ui, view_state: &mut ViewState, timeline_width: f32) {
+    let frames = &view_state.frames;
+    if frames.is_empty() {
+        return;
+    }
+
+    let (response, painter) = ui.allocate_painter(
+        vec2(ui.available_width(), GRAPH_HEIGHT),
+        egui::Sense::click(),
+    );
+    let rect = response.rect;
+
+    let tsc_frequency = view_state.initial_message.tsc_frequency as f64;
+    let seconds = |frame: &Range<u64>| (frame.end - frame.start) as f64 / tsc_frequency;
+
+    let max_frame_time = frames
+        .frames()
+        .map(|it| seconds(&it))
+        .fold(TARGET_30_FPS * 1.2, f64::max)
+        .min(MAX_SHOWN_FRAME_TIME);
+    let y = |frame_time: f64| {
+        rect.max.y - (frame_time.min(max_frame_time) / max_frame_time) as f32 * rect.height()
+    };
+
+    // one column per frame, or the slowest of the frames sharing a column once they don't fit
+    let column_count = frames.len().min(rect.width().max(1.) as usize);
+    let column_width = rect.width() / column_count as f32;
+    let column_frames = |column: usize| {
+        (column * frames.len() / column_count)..((column + 1) * frames.len() / column_count)
+    };
+    let slowest_frame = |column: usize| {
+        column_frames(column).max_by(|a, b| {
+            let a = seconds(&frames.frame(*a).unwrap());
+            let b = seconds(&frames.frame(*b).unwrap());
+
+            a.total_cmp(&b)
+        })
+    };
+
+    let (low, hi) = view_state
+        .timeline_position_state
+        .position(timeline_width as _)
+        .range(timeline_width as _);
+
+    for column in 0..column_count {
+        let Some(frame_idx) = slowest_frame(column) else {
+            continue;
+        };
+        let frame = frames.frame(frame_idx).unwrap();
+        let frame_time = seconds(&frame);
+
+        let color = if frame_time <= TARGET_60_FPS {
+            Color32::GREEN
+        } else if frame_time <= TARGET_30_FPS {
+            Color32::YELLOW
+        } else {
+            Color32::RED
+        };
+
+        let is_visible = (frame.end as f64) > low && (frame.start as f64) < hi;
+
+        let left = rect.min.x + column as f32 * column_width;
+        painter.rect_filled(
+            Rect {
+                min: pos2(left, y(frame_time)),
+                max: pos2(left + column_width.max(1.), rect.max.y),
+            },
+            Rounding::default(),
+            if is_visible {
+                color
+            } else {
+                color.gamma_multiply(0.5)
+            },
+        );
+    }
+
+    let line_color = ui.style().visuals.weak_text_color();
+    for budget in [TARGET_60_FPS, TARGET_30_FPS] {
+        painter.hline(rect.x_range(), y(budget), Stroke::new(1., line_color));
+    }
+
+    let Some(hover_position) = response.hover_pos() else {
+        return;
+    };
+
+    let column = (((hover_position.x - rect.min.x) / column_width) as usize).min(column_count - 1);
+    let Some(frame_idx) = slowest_frame(column) else {
+        return;
+    };
+    let frame = frames.frame(frame_idx).unwrap();
+    let frame_time = seconds(&frame);
+
+    let clicked = response.clicked();
+    response.on_hover_text(format!(
+        "frame {}: {}\nclick to zoom",
+        frame_idx,
+        format_time(frame_time)
+    ));
+
+    if clicked {
+        view_state
+            .timeline_position_state
+            .zoom_to(frame.start, frame.end, timeline_width as _);
+    }
+}
@@ -1,106 +1,329 @@
+mod chunk_loader;
+mod commands;
+mod frame_graph;
 mod panes;
+pub mod recording;
+mod session;
+mod span_query;
 mod timeline_position;
-mod utils;
+pub mod utils;
 mod view_state;
 
+use crate::app::tracing::chunk_loader::ChunkLoader;
+use crate::app::tracing::commands::{describe_command, CommandStatus, PluginCommands};
+use crate::app::tracing::frame_graph::render_frame_graph;
 use crate::app::tracing::panes::{create_tree, Pane, PaneResponse, TreeBehavior, TreeBehaviorArgs};
+use crate::app::tracing::recording::{Recording, TeeReader};
+use crate::app::tracing::session::SessionFile;
 use crate::app::tracing::utils::{format_time, human_readable_size};
-use crate::app::tracing::view_state::{SpanRef, ThreadSpan, ViewState, ViewStateContainer};
+use crate::app::tracing::view_state::{Annotation, SpanRef, ViewState, ViewStateContainer};
 use crate::app::Scene;
-use crate::proto::{InitialMessage, LibraryInfo, ModuleInfo, TraceCommand, TraceEvent};
+use crate::frames::FrameMarkers;
+use crate::proto::{
+    InitialMessage, SpanEvent, TraceCommand, TraceEvent, TraceEventReader, COMMAND_PROTOCOL_VERSION,
+};
+use crate::sce_error::SceError;
+use crate::spans::ThreadSpan;
+use crate::sync::SyncOp;
+use crate::threads::ThreadNames;
+use crate::trace_file;
+use crate::trace_file::{TraceFile, TraceIndex};
 use eframe::egui::scroll_area::ScrollBarVisibility;
 use eframe::egui::{
     pos2, vec2, Align, Align2, CentralPanel, Color32, Context, FontFamily, FontId, Frame, Id,
-    Layout, Margin, Painter, Rect, Rounding, ScrollArea, SidePanel, Stroke, TopBottomPanel, Ui,
-    Vec2b,
+    Layout, Margin, Painter, Pos2, Rangef, Rect, Rounding, ScrollArea, SidePanel, Stroke,
+    TopBottomPanel, Ui, Vec2b,
 };
 use eframe::{egui, emath};
 use egui_tiles::{Tile, Tree};
-use ps4libdoc::{LoadedDocumentation, SymbolDocumentation};
+use ps4libdoc::LoadedDocumentation;
+use rfd::FileDialog;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{BufReader, ErrorKind, Read, Write};
 use std::net::{SocketAddr, TcpListener};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread::JoinHandle;
 use std::{io, thread};
 
+const THREAD_ROW_HEIGHT: f32 = 20.;
+const THREAD_ROW_PADDING_VERTICAL: f32 = 2.;
+
 pub struct TracingScene {
     last_width: Option<f32>,
+
+    /// Time range shown on the timeline in the last frame.
+    last_visible_range: Option<(f64, f64)>,
     state: ViewStateContainer,
-    receiver: Receiver<TraceEvent>,
+
+    /// Events of the trace, then `None` once the stream ended.
+    receiver: Receiver<Option<TraceEvent>>,
     tree: Tree<Pane>,
-    loading_thread_handle: JoinHandle<io::Result<()>>,
-    sender_commands: Option<Sender<TraceCommand>>,
+    loading_thread_handle: Option<JoinHandle<io::Result<()>>>,
+    loading_error: Option<io::Error>,
+
+    /// Commands to the plugin, when connected to one.
+    commands: Option<PluginCommands>,
+    recording: Option<Recording>,
+    save_thread_handle: Option<JoinHandle<io::Result<()>>>,
+    last_save_error: Option<io::Error>,
+    chunk_loader: Option<ChunkLoader>,
+
+    /// Reads frame boundaries and thread names of an indexed file, whose spans are only loaded as
+    /// they're shown.
+    scan_handle: Option<JoinHandle<io::Result<ScannedTrace>>>,
+
+    /// Viewer state saved next to a trace file, restored when it's opened again.
+    session: Option<SessionFile>,
 }
 
 impl TracingScene {
-    pub fn from_network(ctx: Context, socket_addr: SocketAddr) -> TracingScene {
+    pub fn from_network(
+        ctx: Context,
+        socket_addr: SocketAddr,
+        recording: Recording,
+    ) -> TracingScene {
         let (sender, receiver) = channel();
         let (sender_commands, receiver_commands) = channel();
 
-        let loading_thread_handle =
-            thread::spawn(move || run_server(ctx, socket_addr, sender.clone(), receiver_commands));
+        let recording_path = recording.path().to_path_buf();
+        let loading_thread_handle = thread::spawn(move || {
+            run_server(
+                ctx,
+                socket_addr,
+                recording_path,
+                sender.clone(),
+                receiver_commands,
+            )
+        });
 
         TracingScene {
             last_width: None,
+            last_visible_range: None,
             state: ViewStateContainer::new(),
             receiver,
-            sender_commands: Some(sender_commands),
+            commands: Some(PluginCommands::new(sender_commands)),
             tree: create_tree(),
-            loading_thread_handle,
+            loading_thread_handle: Some(loading_thread_handle),
+            loading_error: None,
+            recording: Some(recording),
+            save_thread_handle: None,
+            last_save_error: None,
+            chunk_loader: None,
+            scan_handle: None,
+            session: None,
         }
     }
 
     pub fn from_file_path(ctx: Context, path: PathBuf) -> TracingScene {
         let (sender, receiver) = channel();
 
-        let loading_thread_handle = thread::spawn(move || -> io::Result<()> {
-            let file = File::open(path)?;
-
-            read_stream(ctx, file, sender)?;
-
-            Ok(())
-        });
-
-        TracingScene {
+        let mut scene = TracingScene {
             last_width: None,
+            last_visible_range: None,
             state: ViewStateContainer::Empty,
             receiver,
             tree: create_tree(),
-            loading_thread_handle,
-            sender_commands: None,
+            loading_thread_handle: None,
+            loading_error: None,
+            commands: None,
+            recording: None,
+            save_thread_handle: None,
+            last_save_error: None,
+            chunk_loader: None,
+            scan_handle: None,
+            session: None,
+        };
+
+        match SessionFile::open(&path) {
+            Ok(session) => scene.session = Some(session),
+            Err(err) => {
+                scene.loading_error = Some(io::Error::new(
+                    err.kind(),
+                    format!("failed to read session: {}", err),
+                ));
+            }
         }
+
+        match open_indexed(&path) {
+            Ok(Some((file, index))) => match file.initial_message() {
+                Ok(initial_message) => {
+                    scene.state.initialize(initial_message);
+                    if let ViewStateContainer::Initialized(state) = &mut scene.state {
+                        state.attach_index(&index);
+
+                        match file.counters() {
+                            Ok(counters) => {
+                                for counters in counters {
+                                    state.update_counters(counters);
+                                }
+                            }
+                            Err(err) => {
+                                scene.loading_error = Some(err);
+                            }
+                        }
+
+                        match file.sampling() {
+                            Ok(updates) => {
+                                for update in updates {
+                                    state.sampling.push(update);
+                                }
+                            }
+                            Err(err) => {
+                                scene.loading_error = Some(err);
+                            }
+                        }
+                    }
+
+                    scene.chunk_loader = Some(ChunkLoader::spawn(ctx.clone(), file, &index));
+                    scene.scan_handle = Some(thread::spawn(move || {
+                        let result = TraceFile::open(&path)
+                            .and_then(|it| it.stream())
+                            .and_then(scan_trace);
+                        ctx.request_repaint();
+
+                        result
+                    }));
+                }
+                Err(err) => {
+                    scene.loading_error = Some(err);
+                }
+            },
+            Ok(None) => {
+                scene.loading_thread_handle = Some(thread::spawn(move || -> io::Result<()> {
+                    let result = (|| -> io::Result<()> {
+                        let stream = TraceFile::open(&path)?.stream()?;
+
+                        read_stream(ctx.clone(), stream, sender)?;
+
+                        Ok(())
+                    })();
+
+                    ctx.request_repaint();
+
+                    result
+                }));
+            }
+            Err(err) => {
+                scene.loading_error = Some(err);
+            }
+        }
+
+        scene
     }
 
     fn process_events(&mut self) {
         while let Ok(event) = self.receiver.try_recv() {
-            match &mut self.state {
-                state @ ViewStateContainer::Empty => {
+            match (&mut self.state, event) {
+                (ViewStateContainer::Initialized(state), None) => {
+                    // nothing else will arrive, release spans held back for reordering
+                    state.flush_pending_spans();
+                }
+                (ViewStateContainer::Empty, None) => {}
+                (state @ ViewStateContainer::Empty, Some(event)) => {
+                    // nothing can be placed before the initial message, such as the end of a
+                    // call the capture started in, so it's dropped like the assembler does
                     let TraceEvent::Start(init) = event else {
-                        unimplemented!();
+                        continue;
                     };
 
                     state.initialize(init);
                 }
-                ViewStateContainer::Initialized(state) => {
-                    let TraceEvent::Span(init) = event else {
-                        continue;
-                    };
-                    state.update_span(init);
-                }
+                (ViewStateContainer::Initialized(state), Some(event)) => match event {
+                    TraceEvent::Span(span) => state.update_span(span),
+                    TraceEvent::CountersUpdate(counters) => state.update_counters(counters),
+                    TraceEvent::CommandProtocol(version) => {
+                        if let Some(commands) = &mut self.commands {
+                            commands.set_protocol_version(version);
+                        }
+                    }
+                    TraceEvent::CommandResponse(response) => {
+                        if let Some(commands) = &mut self.commands {
+                            commands.respond(response);
+                        }
+                    }
+                    TraceEvent::SamplingUpdate(update) => state.sampling.push(update),
+                    TraceEvent::ThreadExit(exit) => state.thread_names.observe_exit(&exit),
+                    TraceEvent::Start(..)
+                    | TraceEvent::Compressed(..)
+                    | TraceEvent::ThreadContext(..)
+                    | TraceEvent::CompactSpan(..) => {}
+                },
             };
         }
+
+        // only live traces keep going, spans of files are released once the file is read
+        if let (Some(..), ViewStateContainer::Initialized(state)) =
+            (&self.commands, &mut self.state)
+        {
+            state.release_late_spans();
+        }
+
+        if let (Some(chunk_loader), ViewStateContainer::Initialized(state)) =
+            (&self.chunk_loader, &mut self.state)
+        {
+            while let Some(result) = chunk_loader.try_recv() {
+                match result {
+                    Ok(loaded) => state.apply_loaded_chunks(loaded),
+                    Err(err) => {
+                        self.loading_error.replace(err);
+                    }
+                }
+            }
+        }
+    }
+
+    fn poll_background_threads(&mut self) {
+        if let Some(result) = take_finished(&mut self.loading_thread_handle) {
+            self.loading_error = result.err();
+        }
+
+        if let Some(result) = take_finished(&mut self.save_thread_handle) {
+            self.last_save_error = result.err();
+        }
+
+        if let Some(result) = take_finished(&mut self.scan_handle) {
+            match (result, &mut self.state) {
+                (Ok(scanned), ViewStateContainer::Initialized(state)) => {
+                    state.frames.extend(scanned.flips);
+                    state.thread_names = scanned.thread_names;
+                }
+                (Ok(..), ViewStateContainer::Empty) => {}
+                (Err(err), _) => {
+                    self.loading_error.replace(err);
+                }
+            }
+        }
     }
 
     pub fn update(&mut self, ctx: &Context, docs: &LoadedDocumentation) -> Option<Scene> {
         self.process_events();
+        self.poll_background_threads();
         let mut next_scene = None;
 
         let ViewStateContainer::Initialized(view_state) = &mut self.state else {
-            return None;
+            if let Some(err) = &self.loading_error {
+                CentralPanel::default().show(ctx, |ui| {
+                    ui.colored_label(
+                        ui.style().visuals.error_fg_color,
+                        format!("failed to load traces: {}", err),
+                    );
+
+                    let button = ui.button("back");
+                    if button.clicked() {
+                        next_scene.replace(Scene::initial());
+                    }
+                });
+            }
+
+            return next_scene;
         };
 
+        if let Some(session) = &mut self.session {
+            session.restore(view_state, &mut self.tree);
+        }
+
         let panel = TopBottomPanel::top("summary_toolbar").show(ctx, |ui| {
             ui.with_layout(Layout::left_to_right(Align::Min), |ui| {
                 ui.label(" ");
@@ -113,9 +336,10 @@ impl TracingScene {
                 let mut behavior = TreeBehavior {
                     args: TreeBehaviorArgs {
                         last_width: self.last_width,
+                        visible_range: self.last_visible_range,
                         view_state,
                         docs: &docs,
-                        commands: self.sender_commands.as_mut(),
+                        commands: self.commands.as_mut(),
                     },
                     pane_response: None,
                 };
@@ -158,6 +382,19 @@ impl TracingScene {
                 render_central_panel_contents(ui, ctx, view_state, &mut self.last_width, &docs)
             });
 
+        let (low, hi, _) = central_panel_response.inner;
+        self.last_visible_range = Some((low, hi));
+
+        if let Some(session) = &mut self.session {
+            if let Err(err) = session.save(view_state, &self.tree, self.last_visible_range) {
+                self.last_save_error = Some(err);
+            }
+        }
+
+        if let Some(chunk_loader) = &mut self.chunk_loader {
+            chunk_loader.request(view_state.chunk_requests(low, hi));
+        }
+
         egui::Area::new(Id::new("summary_toolbar_contents"))
             .fixed_pos(panel.response.rect.min)
             .show(ctx, |ui| {
@@ -166,6 +403,49 @@ impl TracingScene {
                 ui.horizontal_centered(|ui| {
                     ui.add_space(ui.spacing().menu_spacing);
 
+                    if let Some(recording) = &self.recording {
+                        if self.save_thread_handle.is_some() {
+                            ui.spinner();
+                            ui.label("saving trace");
+                        } else if ui.button("save trace").clicked() {
+                            if let Some(file_path) = FileDialog::new()
+                                .set_file_name(format!("traces.{}", trace_file::EXTENSION))
+                                .add_filter("saved traces", &[trace_file::EXTENSION])
+                                .save_file()
+                            {
+                                let source = recording.path().to_path_buf();
+                                let annotations = serde_json::to_vec(&view_state.annotations);
+                                let ctx = ctx.clone();
+
+                                self.last_save_error = None;
+                                self.save_thread_handle.replace(thread::spawn(move || {
+                                    let result = (|| -> io::Result<()> {
+                                        trace_file::write_from_raw(&source, &file_path)?;
+                                        TraceFile::open(&file_path)?
+                                            .write_annotations(&annotations?)?;
+
+                                        Ok(())
+                                    })();
+                                    ctx.request_repaint();
+
+                                    result
+                                }));
+                            }
+                        }
+                    }
+
+                    if let Some(it) = &self.last_save_error {
+                        ui.colored_label(ui.style().visuals.error_fg_color, format!("{}", it));
+                    }
+
+                    if let Some(commands) = &mut self.commands {
+                        render_commands_status(ui, commands);
+                    }
+
+                    if let Some(it) = &self.loading_error {
+                        ui.colored_label(ui.style().visuals.error_fg_color, format!("{}", it));
+                    }
+
                     ui.label(format!(
                         "spans: {}",
                         human_readable_size(view_state.total_spans())
@@ -179,6 +459,47 @@ impl TracingScene {
 
                     ui.label(format!("threads: {}", view_state.threads.len()));
 
+                    {
+                        let dropped_packets = view_state.dropped_packets_total();
+                        let text =
+                            format!("dropped: {}", human_readable_size(dropped_packets as _));
+                        let response = if dropped_packets > 0 {
+                            ui.colored_label(ui.style().visuals.warn_fg_color, text)
+                        } else {
+                            ui.label(text)
+                        };
+
+                        response.on_hover_ui(|ui| {
+                            for (thread_id, thread) in &view_state.threads {
+                                let total = thread.dropped_packets_total();
+                                if total > 0 {
+                                    ui.label(format!("thread {}: {}", thread_id, total));
+                                }
+                            }
+                        });
+                    }
+
+                    {
+                        let reorder_counts = view_state.reorder_counts();
+                        if reorder_counts.corrected > 0 || reorder_counts.unrecoverable > 0 {
+                            let text = format!(
+                                "out of order: {} fixed, {} lost",
+                                human_readable_size(reorder_counts.corrected as _),
+                                human_readable_size(reorder_counts.unrecoverable as _)
+                            );
+
+                            if reorder_counts.unrecoverable > 0 {
+                                ui.colored_label(ui.style().visuals.warn_fg_color, text)
+                            } else {
+                                ui.label(text)
+                            }
+                            .on_hover_text(
+                                "spans which arrived out of order. fixed spans were put back in \
+                                 order, lost spans arrived too late and were dropped.",
+                            );
+                        }
+                    }
+
                     {
                         let range = central_panel_response.inner;
                         let (min, max, _) = range;
@@ -211,18 +532,170 @@ impl TracingScene {
     }
 }
 
-fn read_stream(ctx: Context, mut stream: impl Read, sender: Sender<TraceEvent>) -> io::Result<()> {
+impl Drop for TracingScene {
+    fn drop(&mut self) {
+        // changes made since the last write would be lost otherwise, saves are rate limited
+        if let (Some(session), ViewStateContainer::Initialized(view_state)) =
+            (&mut self.session, &self.state)
+        {
+            if let Err(err) = session.flush(view_state, &self.tree, self.last_visible_range) {
+                eprintln!("failed to save session: {}", err);
+            }
+        }
+    }
+}
+
+/// Takes the result of the thread behind `handle` once it has finished.
+fn take_finished<T>(handle: &mut Option<JoinHandle<io::Result<T>>>) -> Option<io::Result<T>> {
+    if !handle.as_ref().is_some_and(|it| it.is_finished()) {
+        return None;
+    }
+
+    let result = match handle.take().unwrap().join() {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::other("background thread panicked")),
+    };
+
+    Some(result)
+}
+
+/// Pausing of a live trace and the outcome of the last command sent to the plugin.
+fn render_commands_status(ui: &mut Ui, commands: &mut PluginCommands) {
+    let is_paused = commands.is_paused();
+    let pause = TraceCommand::SetPaused { paused: !is_paused };
+    if commands.supports(&pause)
+        && ui
+            .button(if is_paused {
+                "resume tracing"
+            } else {
+                "pause tracing"
+            })
+            .clicked()
+    {
+        commands.send(pause);
+    }
+
+    let Some(last) = commands.sent.last() else {
+        return;
+    };
+
+    let status = |status: &CommandStatus| match status {
+        CommandStatus::Pending => "sent".to_string(),
+        CommandStatus::Unacknowledged => "sent, not acknowledged by older plugins".to_string(),
+        CommandStatus::Applied => "applied".to_string(),
+        CommandStatus::Failed(err) => format!("failed: {}", err),
+    };
+
+    let text = format!(
+        "{}: {}",
+        describe_command(&last.command),
+        status(&last.status)
+    );
+    let response = if let CommandStatus::Failed(..) = last.status {
+        ui.colored_label(ui.style().visuals.error_fg_color, text)
+    } else {
+        ui.label(text)
+    };
+
+    response.on_hover_ui(|ui| {
+        match commands.protocol_version() {
+            Some(version) => ui.label(format!(
+                "command protocol v{}, viewer speaks v{}",
+                version, COMMAND_PROTOCOL_VERSION
+            )),
+            None => ui.label("plugin without a command protocol"),
+        };
+
+        for sent in commands.sent.iter().rev() {
+            ui.label(format!(
+                "{}: {}",
+                describe_command(&sent.command),
+                status(&sent.status)
+            ));
+        }
+    });
+}
+
+/// Opens an indexed trace file. Files without an index are read in full instead.
+fn open_indexed(path: &Path) -> io::Result<Option<(TraceFile, TraceIndex)>> {
+    let file = TraceFile::open(path)?;
+    let Some(index) = file.index()? else {
+        return Ok(None);
+    };
+
+    Ok(Some((file, index)))
+}
+
+/// What's needed from every span of an indexed file before its spans are loaded.
+struct ScannedTrace {
+    flips: Vec<u64>,
+    thread_names: ThreadNames,
+}
+
+fn scan_trace(mut stream: impl Read) -> io::Result<ScannedTrace> {
     let initial_message = InitialMessage::read(&mut stream)?;
-    sender.send(TraceEvent::Start(initial_message)).unwrap();
-    ctx.request_repaint();
+    let frames = FrameMarkers::new(&initial_message);
+    let mut thread_names = ThreadNames::new(&initial_message);
 
+    let mut reader = TraceEventReader::new(stream);
+    let mut flips = Vec::new();
     loop {
-        let trace_event = TraceEvent::read(&mut stream)?;
-        if let TraceEvent::CountersUpdate(counters) = &trace_event {
-            println!("{:#?}", counters);
+        match reader.read() {
+            Ok(TraceEvent::Span(event)) => {
+                if let SpanEvent::Start(start) = &event {
+                    if frames.is_flip(start.label_id) {
+                        flips.push(start.time);
+                    }
+                }
+
+                thread_names.observe(&event);
+            }
+            Ok(TraceEvent::ThreadExit(exit)) => thread_names.observe_exit(&exit),
+            Ok(..) => continue,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
         }
+    }
+
+    Ok(ScannedTrace {
+        flips,
+        thread_names,
+    })
+}
 
-        sender.send(trace_event).unwrap();
+/// Sends the events of `stream`, followed by `None` once it ended or failed.
+fn read_stream(
+    ctx: Context,
+    stream: impl Read,
+    sender: Sender<Option<TraceEvent>>,
+) -> io::Result<()> {
+    let result = send_events(&ctx, stream, &sender);
+
+    sender.send(None).unwrap();
+    ctx.request_repaint();
+
+    result
+}
+
+fn send_events(
+    ctx: &Context,
+    mut stream: impl Read,
+    sender: &Sender<Option<TraceEvent>>,
+) -> io::Result<()> {
+    let initial_message = InitialMessage::read(&mut stream)?;
+    sender
+        .send(Some(TraceEvent::Start(initial_message)))
+        .unwrap();
+    ctx.request_repaint();
+
+    let mut reader = TraceEventReader::new(stream);
+    loop {
+        let trace_event = match reader.read() {
+            Ok(trace_event) => trace_event,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        sender.send(Some(trace_event)).unwrap();
         ctx.request_repaint()
     }
 }
@@ -230,8 +703,9 @@ fn read_stream(ctx: Context, mut stream: impl Read, sender: Sender<TraceEvent>)
 fn run_server(
     ctx: Context,
     addr: SocketAddr,
-    sender: Sender<TraceEvent>,
-    receiver: Receiver<TraceCommand>,
+    recording_path: PathBuf,
+    sender: Sender<Option<TraceEvent>>,
+    receiver: Receiver<Vec<u8>>,
 ) -> io::Result<()> {
     let listener = TcpListener::bind(addr)?;
 
@@ -242,6 +716,9 @@ fn run_server(
     let stream = connection?;
     let mut stream_clone = stream.try_clone()?;
 
+    let recording_file = File::create(recording_path)?;
+    let stream = BufReader::new(TeeReader::new(stream, recording_file));
+
     let sender_clone = sender.clone();
     let ctx_clone = ctx.clone();
     thread::spawn(move || {
@@ -251,7 +728,7 @@ fn run_server(
     });
 
     for command in receiver.into_iter() {
-        stream_clone.write_all(&[command as u8])?;
+        stream_clone.write_all(&command)?;
     }
 
     Ok(())
@@ -267,187 +744,492 @@ fn render_central_panel_contents(
     let available_width = ui.available_width();
     last_width.replace(available_width);
 
-    let thread_row_height = 20.;
-    let thread_row_padding_vertical = 2.;
-
-    let total_height = view_state.threads.len() as f32 * thread_row_height;
+    render_frame_graph(ui, view_state, available_width);
+
+    let visible_threads = view_state
+        .ordered_threads()
+        .into_iter()
+        .filter(|it| !view_state.thread_layout.hidden.contains(it))
+        .collect::<Vec<_>>();
+    let (pinned_threads, scrolled_threads): (Vec<_>, Vec<_>) = visible_threads
+        .into_iter()
+        .partition(|it| view_state.thread_layout.pinned.contains(it));
+
+    let mut rows = DrawnRows::default();
+
+    let pinned = if pinned_threads.is_empty() {
+        None
+    } else {
+        let pinned = render_thread_rows(
+            ui,
+            ctx,
+            view_state,
+            &pinned_threads,
+            available_width,
+            docs,
+            &mut rows,
+        );
+        ui.separator();
+
+        Some(pinned)
+    };
 
     let response = ScrollArea::vertical()
         .auto_shrink(Vec2b::FALSE)
         .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
         .show(ui, |ui| {
-            let (response, painter) = ui.allocate_painter(
-                vec2(available_width, total_height),
-                egui::Sense::click_and_drag(),
-            );
+            render_thread_rows(
+                ui,
+                ctx,
+                view_state,
+                &scrolled_threads,
+                available_width,
+                docs,
+                &mut rows,
+            )
+        });
 
-            let display_position = view_state
-                .timeline_position_state
-                .position(response.rect.width() as _);
+    let (low, hi, visible) = response.inner;
+    render_wakes(ui, view_state, &rows, low, hi);
 
-            let (low, hi) = display_position.range(response.rect.width() as _);
-            let range = hi - low;
+    let pinned_visible = pinned.map_or(0, |(_, _, visible)| visible);
 
-            let is_clicked = response.clicked();
+    (low, hi, visible + pinned_visible)
+}
 
-            let hover_position = response.hover_pos();
+/// Where threads were drawn in a frame, across the pinned and the scrolling rows.
+#[derive(Default)]
+struct DrawnRows {
+    /// Visible part of the rows.
+    clip_rect: Option<Rect>,
+    x_range: Option<Rangef>,
 
-            let mut total_visible = 0;
+    /// Top of the rows of each drawn thread.
+    thread_tops: BTreeMap<u64, f32>,
 
-            for (thread_idx, (thread_id, thread_state)) in view_state.threads.iter_mut().enumerate()
-            {
-                let same_thread_as_selected = view_state
-                    .selected_span
-                    .as_ref()
-                    .map_or(false, |it| it.thread_id == *thread_id);
+    /// Spans which woke a drawn waiting span, along with the end of the waiting span.
+    wakes: Vec<(SpanRef, Pos2)>,
+}
 
-                let visible_range = {
-                    let spans = &thread_state.spans;
+/// Draws the rows of `thread_ids`, handling selection, markers, panning and zooming over them.
+/// Returns the visible time range and the number of visible spans.
+fn render_thread_rows(
+    ui: &mut Ui,
+    ctx: &Context,
+    view_state: &mut ViewState,
+    thread_ids: &[u64],
+    available_width: f32,
+    docs: &LoadedDocumentation,
+    rows: &mut DrawnRows,
+) -> (f64, f64, usize) {
+    let total_height = thread_ids
+        .iter()
+        .filter_map(|it| view_state.threads.get(it))
+        .map(|it| it.row_count())
+        .sum::<usize>() as f32
+        * THREAD_ROW_HEIGHT;
+
+    let (response, painter) = ui.allocate_painter(
+        vec2(available_width, total_height),
+        egui::Sense::click_and_drag(),
+    );
 
-                    let i = spans.partition_point(|span| (span.end_time as f64) < low);
-                    let j = spans.partition_point(|span| (span.start_time as f64) < hi);
+    let display_position = view_state
+        .timeline_position_state
+        .position(response.rect.width() as _);
 
-                    i..j
-                };
+    let (low, hi) = display_position.range(response.rect.width() as _);
+    let range = hi - low;
 
-                total_visible += visible_range.len();
+    let is_clicked = response.clicked();
 
-                let view_spans = thread_state.folded_spans_state.fold(
-                    visible_range,
-                    &thread_state.spans,
-                    display_position.cycles_per_pixel as u64 * 4,
-                );
+    let hover_position = response.hover_pos();
 
-                for (start_idx, end_idx) in view_spans {
-                    let start_span = &thread_state.spans[*start_idx];
-                    let end_span = &thread_state.spans[*end_idx - 1];
-                    let folded = *start_idx != *end_idx - 1;
+    let visible_rect = response.rect.intersect(ui.clip_rect());
+    rows.clip_rect = Some(match rows.clip_rect {
+        Some(clip_rect) => clip_rect.union(visible_rect),
+        None => visible_rect,
+    });
+    rows.x_range = Some(response.rect.x_range());
 
-                    let x_range = {
-                        let range = response.rect.x_range();
-                        (range.min as f64)..=(range.max as f64)
-                    };
+    let mut total_visible = 0;
 
-                    let span_min =
-                        emath::remap(start_span.start_time as f64, (low)..=(hi), x_range.clone());
-                    let span_max =
-                        emath::remap(end_span.end_time as f64, (low)..=(hi), x_range.clone());
-
-                    let rect = Rect {
-                        min: pos2(
-                            span_min as f32,
-                            response.rect.min.y + thread_idx as f32 * thread_row_height,
-                        ),
-                        max: pos2(
-                            span_max as f32,
-                            response.rect.min.y
-                                + thread_idx as f32 * thread_row_height
-                                + (thread_row_height - thread_row_padding_vertical),
-                        ),
-                    };
+    let names = thread_ids
+        .iter()
+        .map(|it| view_state.thread_name(*it))
+        .collect::<Vec<_>>();
 
-                    let is_hovered = if let Some(hover_position) = hover_position {
-                        if rect.contains(hover_position) {
-                            true
-                        } else {
-                            false
-                        }
+    let mut row_top = response.rect.min.y;
+    for (thread_id, name) in thread_ids.iter().zip(names) {
+        let Some(thread_state) = view_state.threads.get_mut(thread_id) else {
+            continue;
+        };
+
+        let thread_top = row_top;
+        let thread_bottom = thread_top + thread_state.row_count() as f32 * THREAD_ROW_HEIGHT;
+        row_top = thread_bottom;
+        rows.thread_tops.insert(*thread_id, thread_top);
+
+        let same_thread_as_selected = view_state
+            .selected_span
+            .as_ref()
+            .map_or(false, |it| it.thread_id == *thread_id);
+
+        for chunk_idx in thread_state.chunks_in_range(low, hi) {
+            if thread_state.loaded_chunks.contains(&chunk_idx) {
+                continue;
+            }
+
+            let chunk = &thread_state.chunks[chunk_idx];
+            let x_range = {
+                let range = response.rect.x_range();
+                (range.min as f64)..=(range.max as f64)
+            };
+
+            let chunk_min = emath::remap(chunk.start_time as f64, low..=hi, x_range.clone());
+            let chunk_max = emath::remap(chunk.end_time as f64, low..=hi, x_range);
+
+            painter.rect_filled(
+                Rect {
+                    min: pos2(chunk_min as f32, thread_top),
+                    max: pos2(
+                        chunk_max as f32,
+                        thread_bottom - THREAD_ROW_PADDING_VERTICAL,
+                    ),
+                },
+                Rounding::default(),
+                Color32::DARK_GRAY,
+            );
+        }
+
+        for (depth, level) in thread_state.levels.iter_mut().enumerate() {
+            let visible_range = {
+                let spans = &thread_state.spans;
+
+                let i = level
+                    .spans
+                    .partition_point(|idx| (spans[*idx].end_time as f64) < low);
+                let j = level
+                    .spans
+                    .partition_point(|idx| (spans[*idx].start_time as f64) < hi);
+
+                i..j
+            };
+
+            total_visible += visible_range.len();
+
+            let view_spans = level.folded_spans_state.fold(
+                visible_range,
+                &level.spans,
+                &thread_state.spans,
+                display_position.cycles_per_pixel as u64 * 4,
+            );
+
+            let y = thread_top + depth as f32 * THREAD_ROW_HEIGHT;
+
+            for (start_position, end_position) in view_spans {
+                let start_idx = level.spans[*start_position];
+                let end_idx = level.spans[*end_position - 1];
+                let folded = *start_position != *end_position - 1;
+
+                let start_span = &thread_state.spans[start_idx];
+                let end_span = &thread_state.spans[end_idx];
+
+                let x_range = {
+                    let range = response.rect.x_range();
+                    (range.min as f64)..=(range.max as f64)
+                };
+
+                let span_min =
+                    emath::remap(start_span.start_time as f64, (low)..=(hi), x_range.clone());
+                let span_max =
+                    emath::remap(end_span.end_time as f64, (low)..=(hi), x_range.clone());
+
+                let rect = Rect {
+                    min: pos2(span_min as f32, y),
+                    max: pos2(
+                        span_max as f32,
+                        y + (THREAD_ROW_HEIGHT - THREAD_ROW_PADDING_VERTICAL),
+                    ),
+                };
+
+                let is_hovered = if let Some(hover_position) = hover_position {
+                    if rect.contains(hover_position) {
+                        true
                     } else {
                         false
-                    };
+                    }
+                } else {
+                    false
+                };
 
-                    if folded {
-                        painter.rect_filled(rect, Rounding::default(), Color32::YELLOW);
-                    } else {
-                        let span = &thread_state.spans[*start_idx];
+                if folded {
+                    painter.rect_filled(rect, Rounding::default(), Color32::YELLOW);
+                } else {
+                    let span = &thread_state.spans[start_idx];
 
-                        let is_selected = if same_thread_as_selected {
-                            view_state
-                                .selected_span
-                                .as_ref()
-                                .map_or(false, |it| it.span_idx == *start_idx)
-                        } else {
-                            false
-                        };
+                    let is_selected = if same_thread_as_selected {
+                        view_state.selected_span.as_ref().map_or(false, |it| {
+                            it.span_idx == thread_state.spans_offset + start_idx
+                        })
+                    } else {
+                        false
+                    };
 
-                        let is_same_type_as_selected = view_state
-                            .current_symbol_detail
-                            .map_or(false, |it| it == (span.label_id as usize));
+                    let is_search_result = view_state
+                        .search_results
+                        .contains(*thread_id, thread_state.spans_offset + start_idx);
 
-                        if is_hovered && is_clicked {
-                            let selected_span_metadata = SpanRef {
-                                thread_id: *thread_id,
-                                span_idx: *start_idx,
-                            };
+                    let is_same_type_as_selected = view_state
+                        .current_symbol_detail
+                        .map_or(false, |it| it == (span.label_id as usize));
 
-                            view_state.selected_span.replace(selected_span_metadata);
-                            view_state.current_symbol_detail.replace(span.label_id as _);
+                    if is_hovered && is_clicked {
+                        let selected_span_metadata = SpanRef {
+                            thread_id: *thread_id,
+                            span_idx: thread_state.spans_offset + start_idx,
                         };
 
-                        painter.rect_filled(
-                            rect,
-                            Rounding::default(),
-                            if is_selected {
-                                Color32::BLUE
-                            } else if is_same_type_as_selected {
-                                Color32::LIGHT_BLUE
-                            } else {
-                                Color32::GREEN
-                            },
-                        );
-
-                        let text_color = ui.style().visuals.text_color();
-                        render_text(
-                            &painter,
-                            span,
-                            &view_state.initial_message,
-                            docs,
-                            rect,
-                            text_color,
-                        );
-                    }
+                        view_state.selected_span.replace(selected_span_metadata);
+                        view_state.current_symbol_detail.replace(span.label_id as _);
+                    };
 
-                    if is_hovered {
-                        painter.rect_stroke(
-                            rect,
-                            Rounding::default(),
-                            Stroke::new(1.0f32, Color32::BLACK),
-                        );
+                    painter.rect_filled(
+                        rect,
+                        Rounding::default(),
+                        if is_selected {
+                            Color32::BLUE
+                        } else if is_search_result {
+                            Color32::from_rgb(255, 140, 0)
+                        } else if is_same_type_as_selected {
+                            Color32::LIGHT_BLUE
+                        } else if SceError::from_span(&view_state.initial_message, span).is_some() {
+                            Color32::RED
+                        } else {
+                            Color32::GREEN
+                        },
+                    );
+
+                    if let Some((object, SyncOp::Wait)) = view_state.sync_calls.call(span) {
+                        if let Some(waker) = view_state.sync_signals.waker(
+                            object,
+                            *thread_id,
+                            span.start_time,
+                            span.end_time,
+                        ) {
+                            rows.wakes.push((waker.clone(), rect.right_center()));
+                        }
                     }
+
+                    let text_color = ui.style().visuals.text_color();
+                    render_text(
+                        &painter,
+                        span,
+                        &view_state.initial_message,
+                        docs,
+                        rect,
+                        text_color,
+                    );
+                }
+
+                if is_hovered {
+                    painter.rect_stroke(
+                        rect,
+                        Rounding::default(),
+                        Stroke::new(1.0f32, Color32::BLACK),
+                    );
                 }
             }
+        }
+
+        // Shade windows where messages were dropped so gaps aren't mistaken for idle time
+        for dropped in thread_state.dropped_packets_in_range(low, hi) {
+            let x_range = {
+                let range = response.rect.x_range();
+                (range.min as f64)..=(range.max as f64)
+            };
 
-            // Panning
-            if hover_position.is_some() {
-                let scroll_delta = ctx.input(|it| it.smooth_scroll_delta);
-                let percentage = scroll_delta.x / response.rect.width();
-                let diff = -(percentage as f64 * range);
+            let dropped_min =
+                emath::remap(dropped.time_range.start as f64, low..=hi, x_range.clone());
+            let dropped_max = emath::remap(dropped.time_range.end as f64, low..=hi, x_range);
+
+            painter.rect_filled(
+                Rect {
+                    min: pos2(dropped_min as f32, thread_top),
+                    max: pos2(dropped_max as f32, thread_bottom),
+                },
+                Rounding::default(),
+                Color32::from_rgba_unmultiplied(255, 0, 0, 64),
+            );
+        }
+
+        let thread_x_range = {
+            let range = response.rect.x_range();
+            (range.min as f64)..=(range.max as f64)
+        };
 
-                view_state
-                    .timeline_position_state
-                    .translate_x(diff, display_position);
+        if let Some(thread) = view_state.thread_names.get(*thread_id) {
+            let created = thread
+                .created
+                .as_ref()
+                .map(|it| (it.time, Color32::from_rgb(200, 120, 255)));
+            let exited = thread
+                .exited
+                .map(|time| (time, Color32::from_rgb(255, 160, 60)));
+
+            for (time, color) in created.into_iter().chain(exited) {
+                if !(low..hi).contains(&(time as f64)) {
+                    continue;
+                }
+
+                let x = emath::remap(time as f64, low..=hi, thread_x_range.clone());
+                painter.vline(x as f32, thread_top..=thread_bottom, Stroke::new(2., color));
             }
+        }
 
-            // Zooming
-            (|| -> Option<()> {
-                let hover_position = hover_position?;
-                let zoom_delta = ctx.input(|it| it.zoom_delta()) as f64;
-                let anchor_position = (hover_position.x / response.rect.width()) as f64;
+        render_thread_label(&painter, ui, pos2(response.rect.min.x, thread_top), name);
+    }
 
-                view_state.timeline_position_state.zoom_anchored(
-                    1. / zoom_delta,
-                    anchor_position,
-                    response.rect.width() as f64,
-                    display_position,
-                );
+    let x_range = {
+        let range = response.rect.x_range();
+        (range.min as f64)..=(range.max as f64)
+    };
 
-                Some(())
-            })();
+    if response.double_clicked() {
+        if let Some(position) = response.interact_pointer_pos() {
+            let time = emath::remap(position.x as f64, x_range.clone(), low..=hi);
 
-            (low, hi, total_visible)
-        });
+            view_state.annotations.push(Annotation {
+                name: format!("marker {}", view_state.annotations.len() + 1),
+                start_time: time as u64,
+                end_time: None,
+            });
+        }
+    }
+
+    render_annotations(
+        &painter,
+        response.rect.intersect(ui.clip_rect()),
+        &view_state.annotations,
+        |time| emath::remap(time as f64, low..=hi, x_range.clone()) as f32,
+    );
+
+    // Panning
+    if hover_position.is_some() {
+        let scroll_delta = ctx.input(|it| it.smooth_scroll_delta);
+        let percentage = scroll_delta.x / response.rect.width();
+        let diff = -(percentage as f64 * range);
+
+        view_state
+            .timeline_position_state
+            .translate_x(diff, display_position);
+    }
+
+    // Zooming
+    (|| -> Option<()> {
+        let hover_position = hover_position?;
+        let zoom_delta = ctx.input(|it| it.zoom_delta()) as f64;
+        let anchor_position = (hover_position.x / response.rect.width()) as f64;
+
+        view_state.timeline_position_state.zoom_anchored(
+            1. / zoom_delta,
+            anchor_position,
+            response.rect.width() as f64,
+            display_position,
+        );
 
-    response.inner
+        Some(())
+    })();
+
+    (low, hi, total_visible)
+}
+
+/// Draws arrows from the start of signalling spans to the end of the waiting spans they woke.
+fn render_wakes(ui: &Ui, view_state: &ViewState, rows: &DrawnRows, low: f64, hi: f64) {
+    let (Some(clip_rect), Some(x_range)) = (rows.clip_rect, rows.x_range) else {
+        return;
+    };
+
+    let painter = ui.painter().with_clip_rect(clip_rect);
+    let stroke = Stroke::new(1., ui.style().visuals.text_color().gamma_multiply(0.8));
+    let x_range = (x_range.min as f64)..=(x_range.max as f64);
+
+    for (waker, woken) in &rows.wakes {
+        let Some(thread_top) = rows.thread_tops.get(&waker.thread_id) else {
+            continue;
+        };
+        let Some(span) = view_state
+            .threads
+            .get(&waker.thread_id)
+            .and_then(|it| it.span(waker.span_idx))
+        else {
+            continue;
+        };
+
+        let origin = pos2(
+            emath::remap(span.start_time as f64, low..=hi, x_range.clone()) as f32,
+            thread_top
+                + span.depth as f32 * THREAD_ROW_HEIGHT
+                + (THREAD_ROW_HEIGHT - THREAD_ROW_PADDING_VERTICAL) / 2.,
+        );
+
+        painter.arrow(origin, *woken - origin, stroke);
+    }
+}
+
+/// Names a thread at the top left of its rows, over its spans.
+fn render_thread_label(painter: &Painter, ui: &Ui, position: Pos2, name: String) {
+    let layout = painter.layout_no_wrap(
+        name,
+        FontId::proportional(11.),
+        ui.style().visuals.text_color(),
+    );
+
+    let rect = Align2::LEFT_TOP.anchor_size(position + vec2(2., 1.), layout.size());
+    painter.rect_filled(
+        rect.expand(1.),
+        Rounding::same(2.),
+        ui.style().visuals.panel_fill.gamma_multiply(0.8),
+    );
+    painter.galley(rect.min, layout, ui.style().visuals.text_color());
+}
+
+/// Draws markers as lines and range annotations as shaded bands, named at the top of `rect`.
+fn render_annotations(
+    painter: &Painter,
+    rect: Rect,
+    annotations: &[Annotation],
+    x_position: impl Fn(u64) -> f32,
+) {
+    let color = Color32::from_rgb(100, 150, 255);
+
+    for annotation in annotations {
+        let start = x_position(annotation.start_time);
+        let end = annotation.end_time.map_or(start, &x_position);
+        if end < rect.min.x || start > rect.max.x {
+            continue;
+        }
+
+        if annotation.end_time.is_some() {
+            painter.rect_filled(
+                Rect {
+                    min: pos2(start, rect.min.y),
+                    max: pos2(end, rect.max.y),
+                },
+                Rounding::default(),
+                color.gamma_multiply(0.15),
+            );
+        }
+
+        painter.vline(start, rect.y_range(), Stroke::new(1., color));
+        painter.text(
+            pos2(start.max(rect.min.x) + 2., rect.min.y + 2.),
+            Align2::LEFT_TOP,
+            &annotation.name,
+            FontId::monospace(12.),
+            color,
+        );
+    }
 }
 
 fn render_text(
@@ -458,38 +1240,10 @@ fn render_text(
     rect: Rect,
     color: Color32,
 ) {
-    let Some(symbol) = initial_message.symbols.get(span.label_id as usize) else {
+    let Some(text) = initial_message.symbol_name(docs, span.label_id as usize) else {
         return;
     };
 
-    let library = initial_message.libraries.get(&(symbol.library_id as _));
-    let module = initial_message.modules.get(&(symbol.module_id as _));
-
-    let resolved_symbol_name =
-        (|| -> Option<(&ModuleInfo, &LibraryInfo, Option<&SymbolDocumentation>)> {
-            let library = library?;
-            let module = module?;
-            let resolved_symbol = docs.lookup(&module.name, &library.name, &symbol.name);
-
-            Some((module, library, resolved_symbol))
-        })();
-
-    let text = match resolved_symbol_name {
-        Some((
-            _module,
-            _library,
-            Some(SymbolDocumentation {
-                name: Some(resolved_symbol_name),
-                ..
-            }),
-        )) => format!("{}", resolved_symbol_name),
-        Some((module, library, Some(SymbolDocumentation { name: None, .. })))
-        | Some((module, library, None)) => {
-            format!("{}::{}::{}", module.name, library.name, symbol.name)
-        }
-        None => format!("{}", symbol.name),
-    };
-
     let layout = painter.layout_no_wrap(
         text,
         FontId {
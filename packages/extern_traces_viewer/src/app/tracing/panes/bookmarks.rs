@@ -0,0 +1,131 @@
+use crate::app::tracing::panes::{PaneResponse, TreeBehaviorArgs};
+use crate::app::tracing::utils::format_time;
+use crate::app::tracing::view_state::Annotation;
+use eframe::egui;
+use eframe::egui::{vec2, ScrollArea, Ui};
+
+/// Markers and range annotations on the timeline.
+pub struct BookmarksPane {
+    name: String,
+}
+
+impl BookmarksPane {
+    pub fn init() -> BookmarksPane {
+        BookmarksPane {
+            name: "".to_string(),
+        }
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "bookmarks".to_string().into()
+    }
+
+    pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
+        let view_state = &mut args.view_state;
+
+        ui.allocate_space(vec2(ui.available_width(), 0.));
+
+        ui.add(
+            egui::TextEdit::singleline(&mut self.name)
+                .hint_text("name")
+                .desired_width(ui.available_width()),
+        );
+
+        let selected_span = view_state.selected_span.as_ref().and_then(|span_ref| {
+            view_state
+                .threads
+                .get(&span_ref.thread_id)?
+                .span(span_ref.span_idx)
+        });
+
+        let mut added = None;
+        ui.horizontal(|ui| {
+            if let Some((low, hi)) = args.visible_range {
+                if ui.button("mark visible range").clicked() {
+                    added = Some((low as u64, Some(hi as u64)));
+                }
+            }
+
+            if let Some(span) = selected_span {
+                if ui.button("mark selected span").clicked() {
+                    added = Some((span.start_time, Some(span.end_time)));
+                }
+            }
+        });
+
+        ui.weak("double click the timeline to drop a marker");
+
+        if let Some((start_time, end_time)) = added {
+            let name = if self.name.is_empty() {
+                format!("bookmark {}", view_state.annotations.len() + 1)
+            } else {
+                std::mem::take(&mut self.name)
+            };
+
+            view_state.annotations.push(Annotation {
+                name,
+                start_time,
+                end_time,
+            });
+        }
+
+        ui.separator();
+
+        let tsc_frequency = view_state.initial_message.tsc_frequency as f64;
+        let anchor_timestamp = view_state.initial_message.anchor_timestamp;
+        let time = |cycles: u64| format_time(cycles as f64 / tsc_frequency);
+
+        let mut removed = None;
+        let mut jump_to = None;
+
+        ScrollArea::vertical().show(ui, |ui| {
+            for (idx, annotation) in view_state.annotations.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    if ui.button("go").clicked() {
+                        jump_to = Some(idx);
+                    }
+
+                    ui.add(egui::TextEdit::singleline(&mut annotation.name).desired_width(160.));
+
+                    let start = time(annotation.start_time.saturating_sub(anchor_timestamp));
+                    match annotation.end_time {
+                        Some(end_time) => ui.label(format!(
+                            "{} for {}",
+                            start,
+                            time(end_time.saturating_sub(annotation.start_time))
+                        )),
+                        None => ui.label(start),
+                    };
+
+                    if ui.button("delete").clicked() {
+                        removed = Some(idx);
+                    }
+                });
+            }
+        });
+
+        if let Some(idx) = jump_to {
+            let annotation = &view_state.annotations[idx];
+            let (start, end) = match annotation.end_time {
+                Some(end_time) => (annotation.start_time as f64, end_time as f64),
+                None => {
+                    // keep the zoom level, centered on the marker
+                    let half_width = args.visible_range.map_or(0., |(low, hi)| (hi - low) / 2.);
+                    let time = annotation.start_time as f64;
+
+                    (time - half_width, time + half_width)
+                }
+            };
+
+            if end > start {
+                view_state.timeline_position_state.show_range(start, end);
+            }
+        }
+
+        if let Some(idx) = removed {
+            view_state.annotations.remove(idx);
+        }
+
+        None
+    }
+}
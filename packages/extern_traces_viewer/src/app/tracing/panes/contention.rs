@@ -0,0 +1,194 @@
+use crate::app::tracing::panes::{PaneKey, PaneResponse, TreeBehaviorArgs};
+use crate::app::tracing::utils::format_time;
+use crate::app::tracing::view_state::{SpanRef, ViewState};
+use crate::sync::{SyncObject, SyncOp};
+use eframe::egui;
+use eframe::egui::{vec2, Grid, ScrollArea, Ui};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Number of objects listed.
+const TOP_OBJECTS: usize = 100;
+
+/// Blocked threads named in a row, the rest are listed when hovering it.
+const ROW_THREADS: usize = 3;
+
+/// Locks, semaphores and queues threads waited on the longest in the range shown on the timeline.
+pub struct ContentionPane {
+    last_ranking: Option<Ranking>,
+}
+
+#[derive(Default)]
+struct Contention {
+    waits: u64,
+
+    /// Cycles spent waiting, clipped to the range.
+    total: u64,
+
+    /// Duration of the longest wait, and the span of it.
+    longest: Option<(u64, SpanRef)>,
+
+    /// Cycles each thread spent waiting, clipped to the range.
+    threads: BTreeMap<u64, u64>,
+}
+
+/// Contention of every object for a range, along with what it was computed from.
+struct Ranking {
+    range: (f64, f64),
+    spans_generation: u64,
+    objects: Vec<(SyncObject, Contention)>,
+}
+
+impl ContentionPane {
+    pub fn init() -> ContentionPane {
+        ContentionPane { last_ranking: None }
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "contention".to_string().into()
+    }
+
+    pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
+        let range = args.visible_range?;
+        let view_state = &mut args.view_state;
+
+        ui.allocate_space(vec2(ui.available_width(), 0.));
+
+        let spans_generation = view_state.spans_generation();
+        let is_stale = match &self.last_ranking {
+            None => true,
+            Some(last) => last.range != range || last.spans_generation != spans_generation,
+        };
+
+        if is_stale {
+            let mut objects = rank_objects(view_state, range)
+                .into_iter()
+                .collect::<Vec<_>>();
+            objects.sort_by_key(|(_, it)| Reverse(it.total));
+
+            self.last_ranking.replace(Ranking {
+                range,
+                spans_generation,
+                objects,
+            });
+        }
+
+        let ranking = self.last_ranking.as_ref().unwrap();
+        if ranking.objects.is_empty() {
+            ui.weak("no waits on locks, semaphores or queues in the visible range");
+            return None;
+        }
+
+        let tsc_frequency = view_state.initial_message.tsc_frequency as f64;
+        let time = |cycles: u64| format_time(cycles as f64 / tsc_frequency);
+
+        let mut pane_response = None;
+
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("contention")
+                .num_columns(5)
+                .striped(true)
+                .show(ui, |ui| {
+                    for label in ["object", "waits", "total", "longest", "blocked threads"] {
+                        ui.label(label);
+                    }
+                    ui.end_row();
+
+                    for (object, contention) in ranking.objects.iter().take(TOP_OBJECTS) {
+                        let link = ui.link(object.to_string());
+                        if let Some((_, span_ref)) = &contention.longest {
+                            if link.on_hover_text("select the longest wait").clicked() {
+                                view_state.selected_span = Some(span_ref.clone());
+
+                                pane_response.replace(PaneResponse::FocusPane(
+                                    PaneKey::CurrentlySelectedSpanDetail,
+                                ));
+                            }
+                        }
+
+                        ui.label(contention.waits.to_string());
+                        ui.label(time(contention.total));
+                        ui.label(
+                            contention
+                                .longest
+                                .as_ref()
+                                .map_or_else(|| "-".to_string(), |(duration, _)| time(*duration)),
+                        );
+
+                        let mut threads = contention.threads.iter().collect::<Vec<_>>();
+                        threads.sort_by_key(|(_, it)| Reverse(**it));
+                        let threads = threads
+                            .into_iter()
+                            .map(|(thread_id, cycles)| {
+                                format!(
+                                    "{} ({})",
+                                    view_state.thread_name(*thread_id),
+                                    time(*cycles)
+                                )
+                            })
+                            .collect::<Vec<_>>();
+
+                        let mut text = threads[..threads.len().min(ROW_THREADS)].join(", ");
+                        if threads.len() > ROW_THREADS {
+                            text.push_str(&format!(" and {} more", threads.len() - ROW_THREADS));
+                        }
+                        ui.label(text).on_hover_text(threads.join("\n"));
+                        ui.end_row();
+                    }
+                });
+        });
+
+        pane_response
+    }
+}
+
+/// Waits on every object with wait spans overlapping `low..hi`.
+fn rank_objects(view_state: &ViewState, (low, hi): (f64, f64)) -> BTreeMap<SyncObject, Contention> {
+    let mut objects = BTreeMap::<SyncObject, Contention>::new();
+
+    for (thread_id, thread) in &view_state.threads {
+        let spans = &thread.spans;
+
+        for level in &thread.levels {
+            let i = level
+                .spans
+                .partition_point(|idx| (spans[*idx].end_time as f64) < low);
+            let j = level
+                .spans
+                .partition_point(|idx| (spans[*idx].start_time as f64) < hi);
+
+            for span_idx in &level.spans[i.min(j)..j] {
+                let span = &spans[*span_idx];
+                let Some((object, SyncOp::Wait)) = view_state.sync_calls.call(span) else {
+                    continue;
+                };
+
+                let clipped = ((span.end_time as f64).min(hi) - (span.start_time as f64).max(low))
+                    .max(0.) as u64;
+                let duration = span.end_time - span.start_time;
+
+                let contention = objects.entry(object).or_default();
+                contention.waits += 1;
+                contention.total += clipped;
+                *contention.threads.entry(*thread_id).or_default() += clipped;
+
+                let is_longest = match &contention.longest {
+                    Some((longest, _)) => duration > *longest,
+                    None => true,
+                };
+
+                if is_longest {
+                    contention.longest = Some((
+                        duration,
+                        SpanRef {
+                            thread_id: *thread_id,
+                            span_idx: thread.spans_offset + *span_idx,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    objects
+}
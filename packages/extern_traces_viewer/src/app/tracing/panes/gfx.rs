@@ -1,37 +1,385 @@
+use crate::app::tracing::commands::{CommandStatus, PluginCommands};
 use crate::app::tracing::panes::{PaneKey, PaneResponse, TreeBehaviorArgs};
-use crate::proto::TraceCommand;
+use crate::app::tracing::utils::format_time;
+use crate::app::tracing::view_state::{SpanRef, ViewState};
+use crate::proto::{CaptureTrigger, InitialMessage, SubmitCapture, TraceCommand};
 use eframe::egui;
-use eframe::egui::Ui;
+use eframe::egui::{vec2, CollapsingHeader, ComboBox, DragValue, ScrollArea, Ui};
+use ps4libdoc::LoadedDocumentation;
+use std::collections::BTreeMap;
 
-pub struct GraphicsCapturePane {}
+pub struct GraphicsCapturePane {
+    /// Submits captured by the next capture.
+    capture_count: u32,
+    trigger: TriggerKind,
+    long_frame_ms: f64,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum TriggerKind {
+    Now,
+    AfterSymbol,
+    AfterLongFrame,
+}
 
 impl GraphicsCapturePane {
+    pub fn init() -> GraphicsCapturePane {
+        GraphicsCapturePane {
+            capture_count: 1,
+            trigger: TriggerKind::Now,
+            long_frame_ms: 33.4,
+        }
+    }
+
     pub fn title(&self) -> egui::WidgetText {
         "graphics capture".to_string().into()
     }
 
     pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
         let mut pane_response = None;
+        let initial_message = &args.view_state.initial_message;
+        let docs = args.docs;
 
         if let Some(ref mut commands) = args.commands {
-            let button = ui.button("capture frame");
-            if button.clicked() {
-                commands.send(TraceCommand::CaptureFrame).unwrap();
+            // older plugins only capture a single submit
+            if commands.protocol_version().is_none() {
+                if ui.button("capture frame").clicked() {
+                    commands.send(TraceCommand::CaptureSubmits { count: 1 });
+                }
+            } else {
+                self.capture_ui(commands, args.view_state, docs, ui);
             }
+
+            ui.separator();
         }
 
-        for (idx, span_ref) in args.view_state.extra_data_messages.iter().enumerate() {
-            let link_response = ui.link(format!("{:?}", idx));
+        ui.allocate_space(vec2(ui.available_width(), 0.));
 
-            if link_response.clicked() {
-                args.view_state.selected_span.replace(span_ref.clone());
+        let view_state = &args.view_state;
+        let groups = group_by_capture(view_state.extra_data_messages.iter().map(|span_ref| {
+            let capture = view_state
+                .threads
+                .get(&span_ref.thread_id)
+                .and_then(|thread| thread.span(span_ref.span_idx))
+                .and_then(|span| span.capture.as_deref());
 
-                pane_response.replace(PaneResponse::FocusPane(
-                    PaneKey::CurrentlySelectedSpanDetail,
-                ));
+            (span_ref, capture)
+        }));
+        let mut selected = None;
+
+        ScrollArea::vertical().show(ui, |ui| {
+            for (request, capture) in &groups.captures {
+                let title = format!(
+                    "capture {}: {}, {} submits",
+                    request,
+                    describe_trigger(&capture.trigger, initial_message, docs),
+                    capture.spans.len()
+                );
+
+                CollapsingHeader::new(title)
+                    .id_source(("capture", request))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for (idx, span_ref) in capture.spans.iter().enumerate() {
+                            if ui.link(format!("submit {}", idx)).clicked() {
+                                selected = Some(span_ref.clone());
+                            }
+                        }
+                    });
+            }
+
+            if let Some(commands) = &args.commands {
+                for (request, trigger) in waiting_captures(commands, &groups) {
+                    ui.weak(format!(
+                        "capture {}: {}, waiting",
+                        request,
+                        describe_trigger(&trigger, initial_message, docs)
+                    ));
+                }
             }
+
+            if !groups.other.is_empty() {
+                CollapsingHeader::new(format!("other extra data ({})", groups.other.len()))
+                    .id_source("other_extra_data")
+                    .default_open(groups.captures.is_empty())
+                    .show(ui, |ui| {
+                        for (idx, span_ref) in groups.other.iter().enumerate() {
+                            if ui.link(format!("{:?}", idx)).clicked() {
+                                selected = Some(span_ref.clone());
+                            }
+                        }
+                    });
+            }
+        });
+
+        if let Some(span_ref) = selected {
+            args.view_state.selected_span.replace(span_ref);
+
+            pane_response.replace(PaneResponse::FocusPane(
+                PaneKey::CurrentlySelectedSpanDetail,
+            ));
         }
 
         pane_response
     }
+
+    fn capture_ui(
+        &mut self,
+        commands: &mut PluginCommands,
+        view_state: &ViewState,
+        docs: &LoadedDocumentation,
+        ui: &mut Ui,
+    ) {
+        let initial_message = &view_state.initial_message;
+
+        let trigger = match self.trigger {
+            TriggerKind::Now => Some(CaptureTrigger::Immediate),
+            TriggerKind::AfterSymbol => {
+                view_state
+                    .current_symbol_detail
+                    .map(|symbol_idx| CaptureTrigger::AfterSymbol {
+                        label_id: symbol_idx as u64,
+                    })
+            }
+            TriggerKind::AfterLongFrame => Some(CaptureTrigger::AfterLongFrame {
+                cycles: (self.long_frame_ms / 1000. * initial_message.tsc_frequency as f64) as u64,
+            }),
+        };
+
+        let command = trigger.map(|trigger| match trigger {
+            CaptureTrigger::Immediate => TraceCommand::CaptureSubmits {
+                count: self.capture_count,
+            },
+            trigger => TraceCommand::ArmCapture {
+                count: self.capture_count,
+                trigger,
+            },
+        });
+
+        ui.horizontal(|ui| {
+            let can_send = command
+                .as_ref()
+                .is_some_and(|command| commands.supports(command));
+
+            if ui
+                .add_enabled(can_send, egui::Button::new("capture"))
+                .clicked()
+            {
+                if let Some(command) = command.clone() {
+                    commands.send(command);
+                }
+            }
+
+            ui.add(DragValue::new(&mut self.capture_count).range(1..=64));
+            ui.label("submits");
+
+            ComboBox::from_id_source("capture_trigger")
+                .selected_text(match self.trigger {
+                    TriggerKind::Now => "now",
+                    TriggerKind::AfterSymbol => "after symbol",
+                    TriggerKind::AfterLongFrame => "after long frame",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.trigger, TriggerKind::Now, "now");
+                    ui.selectable_value(
+                        &mut self.trigger,
+                        TriggerKind::AfterSymbol,
+                        "after symbol",
+                    );
+                    ui.selectable_value(
+                        &mut self.trigger,
+                        TriggerKind::AfterLongFrame,
+                        "after long frame",
+                    );
+                });
+        });
+
+        match self.trigger {
+            TriggerKind::Now => {}
+            TriggerKind::AfterSymbol => match trigger {
+                Some(trigger) => {
+                    ui.label(describe_trigger(&trigger, initial_message, docs));
+                }
+                None => {
+                    ui.weak("open a symbol in the symbol detail pane to capture after it");
+                }
+            },
+            TriggerKind::AfterLongFrame => {
+                ui.horizontal(|ui| {
+                    ui.label("frames longer than");
+                    ui.add(
+                        DragValue::new(&mut self.long_frame_ms)
+                            .range(1.0..=1000.0)
+                            .suffix(" ms"),
+                    );
+                });
+            }
+        }
+
+        if command
+            .as_ref()
+            .is_some_and(|command| !commands.supports(command))
+        {
+            ui.weak("the plugin doesn't support capture triggers, it may be an older build");
+        }
+    }
+}
+
+/// Submits captured for one capture request.
+struct CaptureGroup {
+    trigger: CaptureTrigger,
+    spans: Vec<SpanRef>,
+}
+
+struct CaptureGroups {
+    /// By the sequence of the command which asked for the capture.
+    captures: BTreeMap<u32, CaptureGroup>,
+
+    /// Extra data sent without a capture request, like captures asked for by older viewers.
+    other: Vec<SpanRef>,
+}
+
+fn group_by_capture<'a>(
+    messages: impl Iterator<Item = (&'a SpanRef, Option<&'a SubmitCapture>)>,
+) -> CaptureGroups {
+    let mut groups = CaptureGroups {
+        captures: BTreeMap::new(),
+        other: Vec::new(),
+    };
+
+    for (span_ref, capture) in messages {
+        match capture {
+            Some(SubmitCapture { request, trigger }) => groups
+                .captures
+                .entry(*request)
+                .or_insert_with(|| CaptureGroup {
+                    trigger: *trigger,
+                    spans: Vec::new(),
+                })
+                .spans
+                .push(span_ref.clone()),
+            None => groups.other.push(span_ref.clone()),
+        }
+    }
+
+    groups
+}
+
+/// Captures the plugin accepted but didn't capture any submit for yet. The plugin only keeps the
+/// last immediate capture and the last armed one, earlier ones were replaced.
+fn waiting_captures(
+    commands: &PluginCommands,
+    groups: &CaptureGroups,
+) -> Vec<(u32, CaptureTrigger)> {
+    let accepted = || {
+        commands
+            .sent
+            .iter()
+            .rev()
+            .filter(|it| !matches!(it.status, CommandStatus::Failed(..)))
+    };
+
+    let immediate = accepted().find_map(|it| match it.command {
+        TraceCommand::CaptureSubmits { .. } => Some((it.sequence?, CaptureTrigger::Immediate)),
+        _ => None,
+    });
+    let armed = accepted().find_map(|it| match it.command {
+        TraceCommand::ArmCapture { trigger, .. } => Some((it.sequence?, trigger)),
+        _ => None,
+    });
+
+    let mut waiting = [immediate, armed]
+        .into_iter()
+        .flatten()
+        .filter(|(sequence, _)| !groups.captures.contains_key(sequence))
+        .collect::<Vec<_>>();
+    waiting.sort_by_key(|(sequence, _)| *sequence);
+
+    waiting
+}
+
+fn describe_trigger(
+    trigger: &CaptureTrigger,
+    initial_message: &InitialMessage,
+    docs: &LoadedDocumentation,
+) -> String {
+    match trigger {
+        CaptureTrigger::Immediate => "immediately".to_string(),
+        CaptureTrigger::AfterSymbol { label_id } => format!(
+            "after {}",
+            initial_message
+                .symbol_name(docs, *label_id as usize)
+                .unwrap_or_else(|| format!("symbol {}", label_id))
+        ),
+        CaptureTrigger::AfterLongFrame { cycles } => format!(
+            "after a frame over {}",
+            format_time(*cycles as f64 / initial_message.tsc_frequency as f64)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tracing::panes::gfx::group_by_capture;
+    use crate::app::tracing::view_state::SpanRef;
+    use crate::proto::{CaptureTrigger, SubmitCapture};
+
+    #[test]
+    fn groups_captured_submits_by_request() {
+        let span = |span_idx| SpanRef {
+            thread_id: 1,
+            span_idx,
+        };
+        let immediate = SubmitCapture {
+            request: 3,
+            trigger: CaptureTrigger::Immediate,
+        };
+        let after_symbol = SubmitCapture {
+            request: 1,
+            trigger: CaptureTrigger::AfterSymbol { label_id: 7 },
+        };
+
+        let spans = (0..5).map(span).collect::<Vec<_>>();
+        let groups = group_by_capture(
+            [
+                (&spans[0], None),
+                (&spans[1], Some(&immediate)),
+                (&spans[2], Some(&after_symbol)),
+                (&spans[3], Some(&immediate)),
+                (&spans[4], None),
+            ]
+            .into_iter(),
+        );
+
+        let captures = groups
+            .captures
+            .iter()
+            .map(|(request, capture)| {
+                (
+                    *request,
+                    capture.trigger,
+                    capture
+                        .spans
+                        .iter()
+                        .map(|it| it.span_idx)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            captures,
+            vec![
+                (1, after_symbol.trigger, vec![2]),
+                (3, CaptureTrigger::Immediate, vec![1, 3]),
+            ]
+        );
+        assert_eq!(
+            groups
+                .other
+                .iter()
+                .map(|it| it.span_idx)
+                .collect::<Vec<_>>(),
+            vec![0, 4]
+        );
+    }
 }
@@ -0,0 +1,292 @@
+use crate::app::tracing::panes::{PaneKey, PaneResponse, TreeBehaviorArgs};
+use crate::app::tracing::utils::format_time;
+use crate::app::tracing::view_state::{ThreadState, ViewState};
+use eframe::egui;
+use eframe::egui::{vec2, ComboBox, Grid, ScrollArea, Ui};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Number of symbols listed.
+const TOP_SYMBOLS: usize = 100;
+
+/// Symbols which took the most time in the range shown on the timeline.
+pub struct HotSymbolsPane {
+    sort: SortColumn,
+    thread_filter: Option<u64>,
+    last_ranking: Option<Ranking>,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum SortColumn {
+    Total,
+    SelfTime,
+    Calls,
+}
+
+#[derive(Default, Clone, Copy)]
+struct SymbolTotals {
+    calls: u64,
+
+    /// Cycles spent in spans of the symbol, clipped to the range.
+    total: u64,
+
+    /// `total` minus the time spent in nested spans.
+    self_time: u64,
+}
+
+/// Totals of every symbol for a range, along with what they were computed from.
+struct Ranking {
+    range: (f64, f64),
+    thread_filter: Option<u64>,
+    spans_generation: u64,
+    symbols: Vec<(usize, SymbolTotals)>,
+}
+
+impl HotSymbolsPane {
+    pub fn init() -> HotSymbolsPane {
+        HotSymbolsPane {
+            sort: SortColumn::Total,
+            thread_filter: None,
+            last_ranking: None,
+        }
+    }
+
+    pub fn restore(thread_filter: Option<u64>) -> HotSymbolsPane {
+        HotSymbolsPane {
+            thread_filter,
+            ..HotSymbolsPane::init()
+        }
+    }
+
+    pub fn thread_filter(&self) -> Option<u64> {
+        self.thread_filter
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "hot symbols".to_string().into()
+    }
+
+    pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
+        let range = args.visible_range?;
+        let view_state = &mut args.view_state;
+
+        ui.allocate_space(vec2(ui.available_width(), 0.));
+
+        ComboBox::from_label("thread")
+            .selected_text(match self.thread_filter {
+                Some(thread_id) => view_state.thread_name(thread_id),
+                None => "all".to_string(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.thread_filter, None, "all");
+                for thread_id in view_state.ordered_threads() {
+                    ui.selectable_value(
+                        &mut self.thread_filter,
+                        Some(thread_id),
+                        view_state.thread_name(thread_id),
+                    );
+                }
+            });
+
+        let spans_generation = view_state.spans_generation();
+        let is_stale = match &self.last_ranking {
+            None => true,
+            Some(last) => {
+                last.range != range
+                    || last.thread_filter != self.thread_filter
+                    || last.spans_generation != spans_generation
+            }
+        };
+
+        if is_stale {
+            self.last_ranking.replace(Ranking {
+                range,
+                thread_filter: self.thread_filter,
+                spans_generation,
+                symbols: rank_symbols(view_state, self.thread_filter, range)
+                    .into_iter()
+                    .collect(),
+            });
+        }
+
+        let ranking = self.last_ranking.as_mut().unwrap();
+        match self.sort {
+            SortColumn::Total => ranking.symbols.sort_by_key(|(_, it)| Reverse(it.total)),
+            SortColumn::SelfTime => ranking.symbols.sort_by_key(|(_, it)| Reverse(it.self_time)),
+            SortColumn::Calls => ranking.symbols.sort_by_key(|(_, it)| Reverse(it.calls)),
+        }
+
+        let (low, hi) = range;
+        let tsc_frequency = view_state.initial_message.tsc_frequency as f64;
+        let time = |cycles: u64| format_time(cycles as f64 / tsc_frequency);
+        let percent = |cycles: u64| cycles as f64 * 100. / (hi - low).max(1.);
+
+        let mut pane_response = None;
+
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("hot_symbols")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("symbol");
+                    for (column, label) in [
+                        (SortColumn::Total, "total"),
+                        (SortColumn::SelfTime, "self"),
+                        (SortColumn::Calls, "calls"),
+                    ] {
+                        if ui.selectable_label(self.sort == column, label).clicked() {
+                            self.sort = column;
+                        }
+                    }
+                    ui.end_row();
+
+                    for (symbol_idx, totals) in ranking.symbols.iter().take(TOP_SYMBOLS) {
+                        let name = view_state
+                            .initial_message
+                            .symbol_name(args.docs, *symbol_idx)
+                            .unwrap_or_else(|| format!("unknown symbol {}", symbol_idx));
+
+                        if ui.link(name).clicked() {
+                            view_state.current_symbol_detail = Some(*symbol_idx);
+
+                            pane_response.replace(PaneResponse::FocusPane(
+                                PaneKey::CurrentSymbolDetailsPane,
+                            ));
+                        }
+
+                        ui.label(time(totals.total))
+                            .on_hover_text(format!("{:.1}% of range", percent(totals.total)));
+                        ui.label(time(totals.self_time))
+                            .on_hover_text(format!("{:.1}% of range", percent(totals.self_time)));
+                        ui.label(totals.calls.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+
+        pane_response
+    }
+}
+
+/// Totals of every symbol with spans overlapping `low..hi`, of one thread or all of them.
+fn rank_symbols(
+    view_state: &ViewState,
+    thread_filter: Option<u64>,
+    (low, hi): (f64, f64),
+) -> BTreeMap<usize, SymbolTotals> {
+    let mut symbols = BTreeMap::<usize, SymbolTotals>::new();
+
+    for (thread_id, thread) in &view_state.threads {
+        if thread_filter.is_some_and(|it| it != *thread_id) {
+            continue;
+        }
+
+        add_thread_totals(&mut symbols, thread, low, hi);
+    }
+
+    symbols
+}
+
+fn add_thread_totals(
+    symbols: &mut BTreeMap<usize, SymbolTotals>,
+    thread: &ThreadState,
+    low: f64,
+    hi: f64,
+) {
+    let spans = &thread.spans;
+    let clipped = |span_idx: usize| {
+        let span = &spans[span_idx];
+
+        ((span.end_time as f64).min(hi) - (span.start_time as f64).max(low)).max(0.) as u64
+    };
+
+    for (depth, level) in thread.levels.iter().enumerate() {
+        let i = level
+            .spans
+            .partition_point(|idx| (spans[*idx].end_time as f64) < low);
+        let j = level
+            .spans
+            .partition_point(|idx| (spans[*idx].start_time as f64) < hi);
+
+        for span_idx in &level.spans[i.min(j)..j] {
+            let span = &spans[*span_idx];
+            let duration = clipped(*span_idx);
+
+            let totals = symbols.entry(span.label_id as usize).or_default();
+            totals.calls += 1;
+            totals.total += duration;
+            totals.self_time += duration;
+
+            if depth == 0 {
+                continue;
+            }
+
+            // time in a nested span isn't self time of the span containing it
+            let parent_level = &thread.levels[depth - 1].spans;
+            let parent_position =
+                parent_level.partition_point(|idx| spans[*idx].start_time <= span.start_time);
+            let Some(parent_idx) = parent_position
+                .checked_sub(1)
+                .map(|position| parent_level[position])
+            else {
+                continue;
+            };
+
+            let parent = &spans[parent_idx];
+            if parent.end_time < span.end_time {
+                continue;
+            }
+
+            let parent_totals = symbols.entry(parent.label_id as usize).or_default();
+            parent_totals.self_time = parent_totals.self_time.saturating_sub(duration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tracing::panes::hot_symbols::add_thread_totals;
+    use crate::app::tracing::view_state::{FoldSpansState, SpanLevel, ThreadState};
+    use crate::spans::ThreadSpan;
+    use std::collections::BTreeMap;
+
+    fn span(label_id: u64, start_time: u64, end_time: u64, depth: u32) -> ThreadSpan {
+        ThreadSpan {
+            start_time,
+            end_time,
+            label_id,
+            depth,
+            return_value: None,
+            args: None,
+            capture: None,
+            start_extra_data: None,
+            end_extra_data: None,
+        }
+    }
+
+    #[test]
+    fn subtracts_nested_spans_from_self_time() {
+        let mut thread = ThreadState::new();
+        thread.spans = vec![span(1, 10, 20, 1), span(1, 30, 40, 1), span(0, 0, 100, 0)];
+        thread.levels = vec![
+            SpanLevel {
+                spans: vec![2],
+                folded_spans_state: FoldSpansState::new(),
+            },
+            SpanLevel {
+                spans: vec![0, 1],
+                folded_spans_state: FoldSpansState::new(),
+            },
+        ];
+
+        let mut symbols = BTreeMap::new();
+        add_thread_totals(&mut symbols, &thread, 0., 35.);
+
+        assert_eq!(symbols[&0].calls, 1);
+        assert_eq!(symbols[&0].total, 35);
+        assert_eq!(symbols[&0].self_time, 20);
+        assert_eq!(symbols[&1].calls, 2);
+        assert_eq!(symbols[&1].total, 15);
+        assert_eq!(symbols[&1].self_time, 15);
+    }
+}
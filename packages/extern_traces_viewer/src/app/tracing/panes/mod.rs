@@ -1,26 +1,37 @@
+mod bookmarks;
+mod contention;
 mod gfx;
+mod hot_symbols;
 mod render;
+mod sampling;
 mod search;
 mod span_detail;
 mod symbol_detail;
+mod threads;
 
+use crate::app::tracing::commands::PluginCommands;
+use crate::app::tracing::panes::bookmarks::BookmarksPane;
+use crate::app::tracing::panes::contention::ContentionPane;
 use crate::app::tracing::panes::gfx::GraphicsCapturePane;
+use crate::app::tracing::panes::hot_symbols::HotSymbolsPane;
+use crate::app::tracing::panes::sampling::SamplingPane;
 use crate::app::tracing::panes::search::SearchPane;
 use crate::app::tracing::panes::span_detail::SpanDetailPane;
 use crate::app::tracing::panes::symbol_detail::SymbolDetailPane;
+use crate::app::tracing::panes::threads::ThreadsPane;
 use crate::app::tracing::view_state::ViewState;
-use crate::proto::TraceCommand;
 use eframe::egui;
 use eframe::egui::{vec2, Align2, Color32, Frame, Id, Response, Sense, Stroke, TextStyle, Ui};
-use egui_tiles::{SimplificationOptions, TabState, Tabs, TileId, Tiles, Tree};
+use egui_tiles::{SimplificationOptions, TabState, Tabs, Tile, TileId, Tiles, Tree};
 use ps4libdoc::LoadedDocumentation;
-use std::sync::mpsc::Sender;
+use serde::{Deserialize, Serialize};
 
 pub struct TreeBehaviorArgs<'a> {
     pub view_state: &'a mut ViewState,
     pub docs: &'a LoadedDocumentation,
     pub last_width: Option<f32>,
-    pub commands: Option<&'a mut Sender<TraceCommand>>,
+    pub visible_range: Option<(f64, f64)>,
+    pub commands: Option<&'a mut PluginCommands>,
 }
 
 pub struct TreeBehavior<'a> {
@@ -33,6 +44,11 @@ pub enum Pane {
     SearchPane(SearchPane),
     CurrentSymbolDetailsPane(SymbolDetailPane),
     GraphicsCapturePane(GraphicsCapturePane),
+    HotSymbolsPane(HotSymbolsPane),
+    BookmarksPane(BookmarksPane),
+    ThreadsPane(ThreadsPane),
+    ContentionPane(ContentionPane),
+    SamplingPane(SamplingPane),
 }
 
 impl Pane {
@@ -42,8 +58,136 @@ impl Pane {
             Pane::SearchPane(_) => PaneKey::SearchPane,
             Pane::CurrentSymbolDetailsPane(_) => PaneKey::CurrentSymbolDetailsPane,
             Pane::GraphicsCapturePane(_) => PaneKey::GraphicsCapturePane,
+            Pane::HotSymbolsPane(_) => PaneKey::HotSymbolsPane,
+            Pane::BookmarksPane(_) => PaneKey::BookmarksPane,
+            Pane::ThreadsPane(_) => PaneKey::ThreadsPane,
+            Pane::ContentionPane(_) => PaneKey::ContentionPane,
+            Pane::SamplingPane(_) => PaneKey::SamplingPane,
         }
     }
+
+    fn save(&self) -> SavedPane {
+        match self {
+            Pane::CurrentlySelectedSpanDetail(_) => SavedPane::CurrentlySelectedSpanDetail,
+            Pane::SearchPane(pane) => SavedPane::SearchPane {
+                query: pane.query().to_string(),
+            },
+            Pane::CurrentSymbolDetailsPane(_) => SavedPane::CurrentSymbolDetailsPane,
+            Pane::GraphicsCapturePane(_) => SavedPane::GraphicsCapturePane,
+            Pane::HotSymbolsPane(pane) => SavedPane::HotSymbolsPane {
+                thread_filter: pane.thread_filter(),
+            },
+            Pane::BookmarksPane(_) => SavedPane::BookmarksPane,
+            Pane::ThreadsPane(_) => SavedPane::ThreadsPane,
+            Pane::ContentionPane(_) => SavedPane::ContentionPane,
+            Pane::SamplingPane(_) => SavedPane::SamplingPane,
+        }
+    }
+
+    fn restore(saved: &SavedPane) -> Pane {
+        match saved {
+            SavedPane::CurrentlySelectedSpanDetail => {
+                Pane::CurrentlySelectedSpanDetail(SpanDetailPane::init())
+            }
+            SavedPane::SearchPane { query } => Pane::SearchPane(SearchPane::restore(query)),
+            SavedPane::CurrentSymbolDetailsPane => {
+                Pane::CurrentSymbolDetailsPane(SymbolDetailPane {
+                    last_matching: None,
+                })
+            }
+            SavedPane::GraphicsCapturePane => {
+                Pane::GraphicsCapturePane(GraphicsCapturePane::init())
+            }
+            SavedPane::HotSymbolsPane { thread_filter } => {
+                Pane::HotSymbolsPane(HotSymbolsPane::restore(*thread_filter))
+            }
+            SavedPane::BookmarksPane => Pane::BookmarksPane(BookmarksPane::init()),
+            SavedPane::ThreadsPane => Pane::ThreadsPane(ThreadsPane {}),
+            SavedPane::ContentionPane => Pane::ContentionPane(ContentionPane::init()),
+            SavedPane::SamplingPane => Pane::SamplingPane(SamplingPane::init()),
+        }
+    }
+}
+
+/// A pane without its caches, kept in sessions.
+#[derive(Serialize, Deserialize)]
+enum SavedPane {
+    CurrentlySelectedSpanDetail,
+    SearchPane { query: String },
+    CurrentSymbolDetailsPane,
+    GraphicsCapturePane,
+    HotSymbolsPane { thread_filter: Option<u64> },
+    BookmarksPane,
+    ThreadsPane,
+    ContentionPane,
+    SamplingPane,
+}
+
+/// Layout of the detail panes along with the settings of each pane.
+#[derive(Serialize, Deserialize)]
+pub struct SavedTree {
+    root: TileId,
+    tiles: Tiles<SavedPane>,
+}
+
+impl SavedTree {
+    pub fn save(tree: &Tree<Pane>) -> Option<SavedTree> {
+        let mut tiles = Tiles::default();
+        for (tile_id, tile) in tree.tiles.iter() {
+            let tile = match tile {
+                Tile::Pane(pane) => Tile::Pane(pane.save()),
+                Tile::Container(container) => Tile::Container(container.clone()),
+            };
+
+            tiles.insert(*tile_id, tile);
+        }
+
+        Some(SavedTree {
+            root: tree.root?,
+            tiles,
+        })
+    }
+
+    pub fn restore(&self) -> Tree<Pane> {
+        let mut tiles = Tiles::default();
+        for (tile_id, tile) in self.tiles.iter() {
+            let tile = match tile {
+                Tile::Pane(pane) => Tile::Pane(Pane::restore(pane)),
+                Tile::Container(container) => Tile::Container(container.clone()),
+            };
+
+            tiles.insert(*tile_id, tile);
+        }
+
+        let mut tree = Tree::new("detail_tree", self.root, tiles);
+
+        // panes added since the session was saved
+        for pane in [
+            Pane::BookmarksPane(BookmarksPane::init()),
+            Pane::ThreadsPane(ThreadsPane {}),
+            Pane::ContentionPane(ContentionPane::init()),
+            Pane::SamplingPane(SamplingPane::init()),
+        ] {
+            let key = pane.key();
+            let is_open = tree
+                .tiles
+                .tiles()
+                .any(|tile| matches!(tile, Tile::Pane(it) if it.key() == key));
+            if is_open {
+                continue;
+            }
+
+            let Some(container) = tree.tiles.get_container(self.root) else {
+                continue;
+            };
+            let child_count = container.num_children();
+
+            let pane_id = tree.tiles.insert_pane(pane);
+            tree.move_tile_to_container(pane_id, self.root, child_count, false);
+        }
+
+        tree
+    }
 }
 
 #[derive(Eq, PartialEq)]
@@ -52,6 +196,11 @@ pub enum PaneKey {
     SearchPane,
     CurrentSymbolDetailsPane,
     GraphicsCapturePane,
+    HotSymbolsPane,
+    BookmarksPane,
+    ThreadsPane,
+    ContentionPane,
+    SamplingPane,
 }
 
 pub enum PaneResponse {
@@ -74,6 +223,11 @@ impl<'a> egui_tiles::Behavior<Pane> for TreeBehavior<'a> {
                 Pane::SearchPane(pane) => pane.pane_ui(&mut self.args, ui),
                 Pane::CurrentSymbolDetailsPane(pane) => pane.pane_ui(&mut self.args, ui),
                 Pane::GraphicsCapturePane(pane) => pane.pane_ui(&mut self.args, ui),
+                Pane::HotSymbolsPane(pane) => pane.pane_ui(&mut self.args, ui),
+                Pane::BookmarksPane(pane) => pane.pane_ui(&mut self.args, ui),
+                Pane::ThreadsPane(pane) => pane.pane_ui(&mut self.args, ui),
+                Pane::ContentionPane(pane) => pane.pane_ui(&mut self.args, ui),
+                Pane::SamplingPane(pane) => pane.pane_ui(&mut self.args, ui),
             };
         });
 
@@ -86,6 +240,11 @@ impl<'a> egui_tiles::Behavior<Pane> for TreeBehavior<'a> {
             Pane::SearchPane(pane) => pane.title(),
             Pane::CurrentSymbolDetailsPane(pane) => pane.title(),
             Pane::GraphicsCapturePane(pane) => pane.title(),
+            Pane::HotSymbolsPane(pane) => pane.title(),
+            Pane::BookmarksPane(pane) => pane.title(),
+            Pane::ThreadsPane(pane) => pane.title(),
+            Pane::ContentionPane(pane) => pane.title(),
+            Pane::SamplingPane(pane) => pane.title(),
         }
     }
 
@@ -155,12 +314,27 @@ pub fn create_tree() -> Tree<Pane> {
     }));
 
     let graphics_capture_pane =
-        tiles.insert_pane(Pane::GraphicsCapturePane(GraphicsCapturePane {}));
+        tiles.insert_pane(Pane::GraphicsCapturePane(GraphicsCapturePane::init()));
+
+    let hot_symbols_pane = tiles.insert_pane(Pane::HotSymbolsPane(HotSymbolsPane::init()));
+
+    let bookmarks_pane = tiles.insert_pane(Pane::BookmarksPane(BookmarksPane::init()));
+
+    let threads_pane = tiles.insert_pane(Pane::ThreadsPane(ThreadsPane {}));
+
+    let contention_pane = tiles.insert_pane(Pane::ContentionPane(ContentionPane::init()));
+
+    let sampling_pane = tiles.insert_pane(Pane::SamplingPane(SamplingPane::init()));
 
     let root = tiles.insert_container(Tabs::new(vec![
         currently_selected_span_detail_pane,
         symbol_pane,
         search_pane,
+        hot_symbols_pane,
+        bookmarks_pane,
+        threads_pane,
+        contention_pane,
+        sampling_pane,
         graphics_capture_pane,
     ]));
 
@@ -0,0 +1,150 @@
+use crate::app::tracing::panes::{PaneResponse, TreeBehaviorArgs};
+use crate::app::tracing::utils::format_time;
+use crate::proto::{SamplingFilter, TraceCommand};
+use crate::sampling::describe_rate;
+use eframe::egui;
+use eframe::egui::{vec2, CollapsingHeader, DragValue, Grid, ScrollArea, Ui};
+
+/// Symbols offered when adding one to the filter.
+const MATCHING_SYMBOLS: usize = 8;
+
+/// Sampling rates sent to the plugin of a live trace, and the rates applied over the trace.
+pub struct SamplingPane {
+    /// Filter being edited, sent to the plugin once applied.
+    draft: Option<SamplingFilter>,
+    query: String,
+}
+
+impl SamplingPane {
+    pub fn init() -> SamplingPane {
+        SamplingPane {
+            draft: None,
+            query: String::new(),
+        }
+    }
+
+    pub fn title(&self) -> egui::WidgetText {
+        "sampling".to_string().into()
+    }
+
+    pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
+        let view_state = &args.view_state;
+        let initial_message = &view_state.initial_message;
+        let symbol_name = |symbol_idx: u64| {
+            initial_message
+                .symbol_name(args.docs, symbol_idx as usize)
+                .unwrap_or_else(|| format!("symbol {}", symbol_idx))
+        };
+
+        ui.allocate_space(vec2(ui.available_width(), 0.));
+
+        ScrollArea::vertical().show(ui, |ui| {
+            if let Some(commands) = &mut args.commands {
+                let draft = self
+                    .draft
+                    .get_or_insert_with(|| commands.sampling().unwrap_or_default());
+
+                if !commands.supports(&TraceCommand::SetSampling(draft.clone())) {
+                    ui.weak("the plugin doesn't support sampling, it may be an older build");
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut draft.default_rate, 1, "trace other symbols");
+                        ui.selectable_value(&mut draft.default_rate, 0, "mute other symbols");
+                    });
+
+                    let mut removed = None;
+                    Grid::new("sampling_rates").num_columns(4).show(ui, |ui| {
+                        for (label_id, rate) in &mut draft.rates {
+                            ui.label(symbol_name(*label_id));
+                            ui.add(DragValue::new(rate).range(0..=100_000))
+                                .on_hover_text("0 mutes the symbol, n traces 1 in n calls");
+                            ui.weak(describe_rate(*rate));
+                            if ui.small_button("remove").clicked() {
+                                removed = Some(*label_id);
+                            }
+                            ui.end_row();
+                        }
+                    });
+
+                    if let Some(label_id) = removed {
+                        draft.rates.remove(&label_id);
+                    }
+
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.query)
+                            .hint_text("add symbol by name, nid or label id"),
+                    );
+
+                    let query = self.query.trim().to_lowercase();
+                    if !query.is_empty() {
+                        let matching = (0..initial_message.symbols.len() as u64)
+                            .filter(|label_id| !draft.rates.contains_key(label_id))
+                            .filter(|label_id| {
+                                query.parse::<u64>().ok() == Some(*label_id)
+                                    || initial_message.symbols[*label_id as usize].name
+                                        == self.query.trim()
+                                    || symbol_name(*label_id).to_lowercase().contains(&query)
+                            })
+                            .take(MATCHING_SYMBOLS)
+                            .collect::<Vec<_>>();
+
+                        for label_id in matching {
+                            if ui.button(symbol_name(label_id)).clicked() {
+                                // the opposite of the other symbols is what's usually wanted
+                                let rate = if draft.default_rate == 0 { 1 } else { 0 };
+                                draft.rates.insert(label_id, rate);
+                                self.query.clear();
+                            }
+                        }
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("apply").clicked() {
+                            commands.send(TraceCommand::SetSampling(draft.clone()));
+                        }
+
+                        if ui.button("reset").clicked() {
+                            *draft = SamplingFilter::default();
+                        }
+                    });
+                }
+
+                ui.separator();
+            }
+
+            let updates = view_state.sampling.updates();
+            if updates.is_empty() {
+                ui.weak("every call was traced");
+                return;
+            }
+
+            let tsc_frequency = initial_message.tsc_frequency as f64;
+            let anchor_timestamp = initial_message.anchor_timestamp;
+
+            for (idx, update) in updates.iter().enumerate() {
+                let title = format!(
+                    "from {}: {} symbols set, others {}",
+                    format_time(
+                        update.time.saturating_sub(anchor_timestamp) as f64 / tsc_frequency
+                    ),
+                    update.filter.rates.len(),
+                    describe_rate(update.filter.default_rate)
+                );
+
+                CollapsingHeader::new(title)
+                    .id_source(("sampling_update", idx))
+                    .show(ui, |ui| {
+                        for (label_id, rate) in &update.filter.rates {
+                            ui.label(format!(
+                                "{}: {}",
+                                symbol_name(*label_id),
+                                describe_rate(*rate)
+                            ));
+                        }
+                    });
+            }
+        });
+
+        None
+    }
+}
@@ -1,18 +1,59 @@
 use crate::app::tracing::panes::{PaneKey, PaneResponse, TreeBehaviorArgs};
+use crate::app::tracing::span_query::SpanQuery;
+use crate::app::tracing::utils::format_time;
+use crate::app::tracing::view_state::SearchResults;
 use eframe::egui;
-use eframe::egui::{vec2, ScrollArea, Ui};
+use eframe::egui::{vec2, CollapsingHeader, Key, ScrollArea, TextStyle, Ui};
+
+const QUERY_HELP: &str = "terms separated by spaces, all of which must match:
+  foo, name:foo       symbol name contains foo
+  /regex/             symbol name matches a regex
+  module:foo          module name contains foo
+  library:foo         library name contains foo
+  thread:123
+  duration>1ms        also duration<, with ns, us, ms or s
+  after:1s            also before:, since the start of the trace
+  has:extra           also has:error";
 
 pub struct SearchPane {
     current_text: String,
+    error: Option<String>,
+
+    /// Symbols matching the name, module and library terms of the query, also those without
+    /// loaded spans.
+    symbols: Vec<usize>,
+
+    /// Generation of the loaded spans when the results were computed. Results only cover loaded
+    /// spans, so they're stale once more arrive or chunks are swapped.
+    searched_spans: Option<u64>,
+
+    /// Runs the query the next time the pane is shown, for queries restored from a session.
+    search_on_show: bool,
 }
 
 impl SearchPane {
     pub fn init() -> SearchPane {
         SearchPane {
             current_text: "".to_string(),
+            error: None,
+            symbols: Vec::new(),
+            searched_spans: None,
+            search_on_show: false,
         }
     }
 
+    pub fn restore(query: &str) -> SearchPane {
+        SearchPane {
+            current_text: query.to_string(),
+            search_on_show: !query.is_empty(),
+            ..SearchPane::init()
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.current_text
+    }
+
     pub fn title(&self) -> egui::WidgetText {
         "search".to_string().into()
     }
@@ -20,55 +61,158 @@ impl SearchPane {
     pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
         let mut pane_response = None;
 
-        ScrollArea::vertical().show(ui, |ui| {
-            ui.allocate_space(vec2(ui.available_width(), 0.));
+        ui.allocate_space(vec2(ui.available_width(), 0.));
 
-            ui.add(
-                egui::TextEdit::singleline(&mut self.current_text)
-                    .desired_width(ui.available_width()),
-            );
+        let text_response = ui.add(
+            egui::TextEdit::singleline(&mut self.current_text)
+                .hint_text("query, hover for syntax")
+                .desired_width(ui.available_width()),
+        );
+        text_response.clone().on_hover_text(QUERY_HELP);
 
-            for (symbol_idx, symbol) in args.view_state.initial_message.symbols.iter().enumerate() {
-                let library_name = &args
-                    .view_state
-                    .initial_message
-                    .libraries
-                    .get(&(symbol.library_id as u16))
-                    .map(|it| it.name.as_str());
+        let submitted = text_response.lost_focus() && ui.input(|it| it.key_pressed(Key::Enter));
 
-                let module_name = &args
-                    .view_state
-                    .initial_message
-                    .modules
-                    .get(&(symbol.module_id as u16))
-                    .map(|it| it.name.as_str());
-
-                let symbol_name = (|| Some(((*library_name)?, (*module_name)?)))()
-                    .and_then(|(library_name, module_name)| {
-                        args.docs
-                            .lookup(module_name, library_name, symbol.name.as_ref())
-                            .and_then(|it| it.name.clone())
-                    })
-                    .unwrap_or_else(|| format!("{}", symbol.name.as_str()));
-
-                let text = format!(
-                    "{}::{}::{}",
-                    library_name.unwrap_or("unknown"),
-                    module_name.unwrap_or("unknown"),
-                    symbol_name
-                );
-
-                if !text.contains(self.current_text.as_str()) {
-                    continue;
+        let spans_generation = args.view_state.spans_generation();
+        let mut run_search = submitted || std::mem::take(&mut self.search_on_show);
+
+        ui.horizontal(|ui| {
+            run_search |= ui.button("search").clicked();
+
+            if ui.button("clear").clicked() {
+                self.current_text.clear();
+                self.error = None;
+                self.symbols.clear();
+                self.searched_spans = None;
+                args.view_state.search_results = SearchResults::default();
+            }
+
+            if self.searched_spans.is_some_and(|it| it != spans_generation) {
+                run_search |= ui
+                    .button("refresh")
+                    .on_hover_text("spans were loaded since searching")
+                    .clicked();
+            }
+        });
+
+        if run_search {
+            match SpanQuery::parse(&self.current_text) {
+                Ok(query) => {
+                    let results = query.search(
+                        &args.view_state.initial_message,
+                        args.docs,
+                        &args.view_state.threads,
+                    );
+
+                    args.view_state.search_results = SearchResults::new(results);
+                    self.symbols =
+                        query.matching_symbols(&args.view_state.initial_message, args.docs);
+                    self.error = None;
+                    self.searched_spans = Some(spans_generation);
                 }
+                Err(err) => self.error = Some(err),
+            }
+        }
+
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.style().visuals.error_fg_color, error);
+        }
 
-                let link_response = ui.link(text);
+        // nothing to list until a search ran
+        self.searched_spans?;
 
-                if link_response.clicked() {
-                    args.view_state.current_symbol_detail = Some(symbol_idx);
+        CollapsingHeader::new(format!("symbols ({})", self.symbols.len()))
+            .id_source("search_symbols")
+            .show(ui, |ui| {
+                ScrollArea::vertical()
+                    .id_source("search_symbols")
+                    .max_height(ui.available_height() / 2.)
+                    .show(ui, |ui| {
+                        for symbol_idx in &self.symbols {
+                            let text = args
+                                .view_state
+                                .initial_message
+                                .symbol_name(args.docs, *symbol_idx)
+                                .unwrap_or_else(|| "unknown".to_string());
 
-                    pane_response
-                        .replace(PaneResponse::FocusPane(PaneKey::CurrentSymbolDetailsPane));
+                            if ui.link(text).clicked() {
+                                args.view_state.current_symbol_detail = Some(*symbol_idx);
+
+                                pane_response.replace(PaneResponse::FocusPane(
+                                    PaneKey::CurrentSymbolDetailsPane,
+                                ));
+                            }
+                        }
+                    });
+            });
+
+        if args.view_state.has_unloaded_chunks() {
+            ui.weak("only spans of loaded chunks are searched, zoom out or pan to load others");
+        }
+
+        let mut step = None;
+        ui.horizontal(|ui| {
+            let results = &args.view_state.search_results;
+            match results.current {
+                Some(current) => ui.label(format!("{} of {}", current + 1, results.spans.len())),
+                None => ui.label(format!("{} results", results.spans.len())),
+            };
+
+            if ui.button("previous").clicked() {
+                step = Some(false);
+            }
+
+            if ui.button("next").clicked() {
+                step = Some(true);
+            }
+        });
+
+        if let Some(forward) = step {
+            if let Some(current) = args.view_state.search_results.step(forward) {
+                select_result(args, current);
+            }
+        }
+
+        let text_style = TextStyle::Body;
+        let row_height = ui.text_style_height(&text_style);
+        let result_count = args.view_state.search_results.spans.len();
+        ScrollArea::vertical().show_rows(ui, row_height, result_count, |ui, row_range| {
+            ui.allocate_space(vec2(ui.available_width(), 0.));
+
+            for idx in row_range {
+                let span_ref = &args.view_state.search_results.spans[idx];
+                let span = args
+                    .view_state
+                    .threads
+                    .get(&span_ref.thread_id)
+                    .and_then(|it| it.span(span_ref.span_idx));
+
+                let text = match span {
+                    Some(span) => format!(
+                        "{} (thread {}, {})",
+                        args.view_state
+                            .initial_message
+                            .symbol_name(args.docs, span.label_id as usize)
+                            .unwrap_or_else(|| "unknown".to_string()),
+                        span_ref.thread_id,
+                        format_time(
+                            (span.end_time - span.start_time) as f64
+                                / args.view_state.initial_message.tsc_frequency as f64
+                        ),
+                    ),
+                    None => format!(
+                        "span {} of thread {} (not loaded)",
+                        span_ref.span_idx, span_ref.thread_id
+                    ),
+                };
+
+                let is_current = args.view_state.search_results.current == Some(idx);
+                if ui.selectable_label(is_current, text).clicked() {
+                    args.view_state.search_results.current = Some(idx);
+                    select_result(args, idx);
+
+                    pane_response.replace(PaneResponse::FocusPane(
+                        PaneKey::CurrentlySelectedSpanDetail,
+                    ));
                 }
             }
         });
@@ -76,3 +220,29 @@ impl SearchPane {
         pane_response
     }
 }
+
+/// Selects the search result at `idx` and moves the timeline to it.
+fn select_result(args: &mut TreeBehaviorArgs, idx: usize) {
+    let view_state = &mut args.view_state;
+    let Some(span_ref) = view_state.search_results.spans.get(idx).cloned() else {
+        return;
+    };
+
+    let span = view_state
+        .threads
+        .get(&span_ref.thread_id)
+        .and_then(|it| it.span(span_ref.span_idx))
+        .cloned();
+    view_state.selected_span.replace(span_ref);
+
+    let Some(span) = span else {
+        return;
+    };
+
+    view_state.current_symbol_detail.replace(span.label_id as _);
+    if let Some(last_width) = args.last_width {
+        view_state
+            .timeline_position_state
+            .pan_to(&span, last_width as _);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::app::tracing::panes::render::render_frame;
 use crate::app::tracing::panes::{PaneResponse, TreeBehaviorArgs};
 use crate::app::tracing::utils::{format_time, render_symbol_info};
+use crate::call_format::format_call;
 use crate::gfx_debug::{DebugHandle, ExtraData, GraphicsContext};
+use crate::sce_error::SceError;
 use anyhow::bail;
 use eframe::egui;
 use eframe::egui::load::SizedTexture;
@@ -9,6 +11,9 @@ use eframe::egui::{
     vec2, Align, Image, ImageSource, Layout, TextureHandle, TextureOptions, Ui, Widget,
 };
 
+/// Registers holding the integer arguments of a call, in order.
+const ARG_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+
 pub struct SpanDetailPane {
     last_image: Option<TextureHandle>,
     debug_handle: DebugHandle,
@@ -38,7 +43,7 @@ impl SpanDetailPane {
 
             let selected_span = view_state.selected_span.as_ref()?;
             let thread = view_state.threads.get(&selected_span.thread_id)?;
-            let span = &thread.spans[selected_span.span_idx];
+            let span = thread.span(selected_span.span_idx)?;
 
             ui.allocate_space(vec2(ui.available_width(), 0.));
 
@@ -54,6 +59,10 @@ impl SpanDetailPane {
 
             render_symbol_info(&view_state.initial_message, args.docs, symbol, ui);
 
+            if let Some(call) = format_call(&view_state.initial_message, args.docs, span) {
+                ui.monospace(call);
+            }
+
             ui.horizontal(|ui| {
                 ui.label("duration");
                 let duration_cycles = span.end_time - span.start_time;
@@ -70,6 +79,33 @@ impl SpanDetailPane {
                 ui.label(format_time(duration_seconds));
             });
 
+            if let Some(return_value) = span.return_value {
+                ui.horizontal(|ui| {
+                    ui.label("returned");
+                    ui.label(format!("{:#x}", return_value));
+                });
+
+                if let Some(error) = SceError::from_span(&view_state.initial_message, span) {
+                    ui.colored_label(ui.style().visuals.error_fg_color, error.to_string());
+                }
+            }
+
+            if let Some(args) = &span.args {
+                ui.label("arguments");
+                for (idx, (register, value)) in
+                    ARG_REGISTERS.iter().zip(args.registers.iter()).enumerate()
+                {
+                    ui.horizontal(|ui| {
+                        ui.monospace(*register);
+                        ui.label(format!("{:#x}", value));
+
+                        if let Some(data) = args.data.get(&idx) {
+                            ui.weak(format!("{} bytes captured", data.len()));
+                        }
+                    });
+                }
+            }
+
             if let Some(it) = &self.last_image {
                 Image::new(ImageSource::Texture(SizedTexture::from_handle(it)))
                     .max_width(ui.available_width())
@@ -81,7 +117,7 @@ impl SpanDetailPane {
                 if let Some(last_width) = args.last_width {
                     view_state
                         .timeline_position_state
-                        .pan_to(&span, last_width as _);
+                        .pan_to(span, last_width as _);
                 }
             }
 
@@ -1,11 +1,135 @@
 use crate::app::tracing::panes::{PaneKey, PaneResponse, TreeBehaviorArgs};
-use crate::app::tracing::utils::render_symbol_info;
-use crate::app::tracing::view_state::SpanRef;
+use crate::app::tracing::utils::{format_time, render_symbol_info};
+use crate::app::tracing::view_state::{SpanRef, ViewState};
+use crate::call_format::arg_data_lengths;
+use crate::proto::TraceCommand;
+use crate::sampling::describe_rate;
+use crate::stats::DurationSummary;
 use eframe::egui;
-use eframe::egui::{vec2, ScrollArea, TextStyle, Ui};
+use eframe::egui::{vec2, Grid, ScrollArea, TextStyle, Ui};
+use egui_plot::{Bar, BarChart, Plot};
+use std::ops::Range;
+
+const HISTOGRAM_BUCKETS: usize = 32;
 
 pub struct SymbolDetailPane {
-    pub last_matching: Option<(usize, Vec<SpanRef>)>,
+    pub last_matching: Option<SymbolSpans>,
+}
+
+/// Loaded spans of a symbol along with their statistics.
+pub struct SymbolSpans {
+    symbol_idx: usize,
+    spans_generation: u64,
+
+    spans: Vec<SpanRef>,
+
+    /// Positions in `spans` ordered by duration.
+    by_duration: Vec<usize>,
+
+    summary: Option<DurationSummary>,
+    histogram: Histogram,
+}
+
+/// Durations bucketed on a log scale, as ranges of positions in `SymbolSpans::by_duration`.
+struct Histogram {
+    /// log10 of the lower edge of the first bucket, in seconds.
+    log_min: f64,
+    log_step: f64,
+    buckets: Vec<Range<usize>>,
+}
+
+impl SymbolSpans {
+    fn collect(view_state: &ViewState, symbol_idx: usize) -> SymbolSpans {
+        let spans = view_state
+            .threads
+            .iter()
+            .flat_map(|(thread_id, spans)| {
+                spans
+                    .spans
+                    .iter()
+                    .enumerate()
+                    .map(|(span_idx, span)| (*thread_id, spans.spans_offset + span_idx, span))
+            })
+            .filter(|(_thread_id, _span_idx, span)| (span.label_id as usize) == symbol_idx)
+            .map(|(thread_id, span_idx, span)| {
+                (
+                    SpanRef {
+                        span_idx,
+                        thread_id,
+                    },
+                    span.end_time - span.start_time,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut by_duration = (0..spans.len()).collect::<Vec<_>>();
+        by_duration.sort_by_key(|idx| spans[*idx].1);
+        let durations = by_duration
+            .iter()
+            .map(|idx| spans[*idx].1)
+            .collect::<Vec<_>>();
+
+        let tsc_frequency = view_state.initial_message.tsc_frequency as f64;
+
+        SymbolSpans {
+            symbol_idx,
+            spans_generation: view_state.spans_generation(),
+            spans: spans.into_iter().map(|(span_ref, _)| span_ref).collect(),
+            by_duration,
+            summary: DurationSummary::from_sorted(&durations),
+            histogram: Histogram::new(&durations, tsc_frequency),
+        }
+    }
+}
+
+impl Histogram {
+    fn new(durations: &[u64], tsc_frequency: f64) -> Histogram {
+        let log_seconds = |cycles: u64| (cycles.max(1) as f64 / tsc_frequency).log10();
+
+        let (Some(min), Some(max)) = (durations.first(), durations.last()) else {
+            return Histogram {
+                log_min: 0.,
+                log_step: 1.,
+                buckets: Vec::new(),
+            };
+        };
+
+        let log_min = log_seconds(*min);
+        let log_step = ((log_seconds(*max) - log_min) / HISTOGRAM_BUCKETS as f64).max(0.01);
+
+        let mut buckets = Vec::with_capacity(HISTOGRAM_BUCKETS);
+        let mut start = 0;
+        for bucket_idx in 0..HISTOGRAM_BUCKETS {
+            let end = if bucket_idx == HISTOGRAM_BUCKETS - 1 {
+                durations.len()
+            } else {
+                let upper = log_min + log_step * (bucket_idx + 1) as f64;
+                durations.partition_point(|it| log_seconds(*it) < upper)
+            };
+
+            buckets.push(start..end);
+            start = end;
+        }
+
+        Histogram {
+            log_min,
+            log_step,
+            buckets,
+        }
+    }
+
+    fn bucket_at(&self, log_seconds: f64) -> Option<usize> {
+        let bucket_idx = ((log_seconds - self.log_min) / self.log_step).floor();
+        if bucket_idx < 0. {
+            return None;
+        }
+
+        let bucket_idx = bucket_idx as usize;
+        self.buckets
+            .get(bucket_idx)
+            .filter(|it| !it.is_empty())
+            .map(|_| bucket_idx)
+    }
 }
 
 impl SymbolDetailPane {
@@ -17,36 +141,20 @@ impl SymbolDetailPane {
     pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
         let symbol_idx = args.view_state.current_symbol_detail?;
 
-        let should_replace = if self.last_matching.is_none() {
-            true
-        } else {
-            let last = self.last_matching.as_ref().unwrap().0;
-            last != symbol_idx
+        let should_replace = match &self.last_matching {
+            None => true,
+            Some(last) => {
+                last.symbol_idx != symbol_idx
+                    || last.spans_generation != args.view_state.spans_generation()
+            }
         };
 
         if should_replace {
-            self.last_matching.replace((
-                symbol_idx,
-                args.view_state
-                    .threads
-                    .iter()
-                    .flat_map(|(thread_id, spans)| {
-                        spans
-                            .spans
-                            .iter()
-                            .enumerate()
-                            .map(|(span_idx, span)| (*thread_id, span_idx, span))
-                    })
-                    .filter(|(_thread_id, _span_idx, span)| (span.label_id as usize) == symbol_idx)
-                    .map(|(thread_id, span_idx, _)| SpanRef {
-                        span_idx,
-                        thread_id,
-                    })
-                    .collect(),
-            ));
+            self.last_matching
+                .replace(SymbolSpans::collect(args.view_state, symbol_idx));
         };
 
-        let matching = &self.last_matching.as_ref().unwrap().1;
+        let matching = self.last_matching.as_ref().unwrap();
 
         render_symbol_info(
             &args.view_state.initial_message,
@@ -55,10 +163,134 @@ impl SymbolDetailPane {
             ui,
         );
 
+        if let Some(commands) = &mut args.commands {
+            let mut enabled = commands.is_capturing_args(symbol_idx as u64);
+            let command = TraceCommand::SetArgCapture {
+                label_id: symbol_idx as u64,
+                enabled: !enabled,
+                arg_data: arg_data_lengths(&args.view_state.initial_message, args.docs, symbol_idx),
+            };
+
+            if commands.supports(&command)
+                && ui
+                    .checkbox(&mut enabled, "capture arguments")
+                    .on_hover_text(
+                        "send the argument registers with each call, and the strings and \
+                        structs they point to",
+                    )
+                    .changed()
+            {
+                commands.send(command);
+            }
+        }
+
+        let tsc_frequency = args.view_state.initial_message.tsc_frequency as f64;
+        let time = |cycles: u64| format_time(cycles as f64 / tsc_frequency);
+
+        // calls left out by the plugin aren't counted below
+        let anchor_timestamp = args.view_state.initial_message.anchor_timestamp;
+        for (change_time, rate) in args.view_state.sampling.changes(symbol_idx as u64) {
+            ui.colored_label(
+                ui.style().visuals.warn_fg_color,
+                format!(
+                    "from {}: {}",
+                    time(change_time.saturating_sub(anchor_timestamp)),
+                    describe_rate(rate)
+                ),
+            );
+        }
+
+        if let Some(summary) = &matching.summary {
+            let wall_time = args
+                .view_state
+                .timeline_position_state
+                .timestamp_range()
+                .map_or(0, |(min, max)| max - min);
+
+            Grid::new("symbol_stats").num_columns(2).show(ui, |ui| {
+                ui.label("calls");
+                ui.label(summary.count.to_string());
+                ui.end_row();
+
+                ui.label("total");
+                ui.label(time(summary.total));
+                ui.end_row();
+
+                if wall_time > 0 {
+                    ui.label("of wall time");
+                    ui.label(format!(
+                        "{:.2}%",
+                        summary.total as f64 * 100. / wall_time as f64
+                    ));
+                    ui.end_row();
+                }
+
+                for (label, value) in [
+                    ("min", summary.min),
+                    ("mean", summary.mean),
+                    ("median", summary.p50),
+                    ("p95", summary.p95),
+                    ("max", summary.max),
+                ] {
+                    ui.label(label);
+                    ui.label(time(value));
+                    ui.end_row();
+                }
+            });
+        }
+
+        let mut clicked_bucket = None;
+        if !matching.histogram.buckets.is_empty() {
+            let histogram = &matching.histogram;
+            let bars = histogram
+                .buckets
+                .iter()
+                .enumerate()
+                .map(|(bucket_idx, bucket)| {
+                    let center = histogram.log_min + histogram.log_step * (bucket_idx as f64 + 0.5);
+
+                    Bar::new(center, bucket.len() as f64).width(histogram.log_step)
+                })
+                .collect();
+
+            let plot_response = Plot::new(("symbol_histogram", symbol_idx))
+                .height(120.)
+                .allow_zoom(false)
+                .allow_drag(false)
+                .allow_scroll(false)
+                .x_axis_formatter(|mark, _range| format_time(10f64.powf(mark.value)))
+                .label_formatter(|_name, point| {
+                    format!("{}\nclick to select", format_time(10f64.powf(point.x)))
+                })
+                .show(ui, |plot_ui| {
+                    plot_ui.bar_chart(BarChart::new(bars));
+
+                    if !plot_ui.response().clicked() {
+                        return None;
+                    }
+
+                    histogram.bucket_at(plot_ui.pointer_coordinate()?.x)
+                });
+
+            clicked_bucket = plot_response.inner;
+        }
+
         let mut pane_response = None;
 
+        if let Some(bucket_idx) = clicked_bucket {
+            // the span with the median duration of the bucket
+            let bucket = &matching.histogram.buckets[bucket_idx];
+            let span_ref = &matching.spans[matching.by_duration[(bucket.start + bucket.end) / 2]];
+            select_span(args, span_ref.clone());
+
+            pane_response.replace(PaneResponse::FocusPane(
+                PaneKey::CurrentlySelectedSpanDetail,
+            ));
+        }
+
         let text_style = TextStyle::Body;
         let row_height = ui.text_style_height(&text_style);
+        let matching = &matching.spans;
         ScrollArea::vertical().show_rows(ui, row_height, matching.len(), |ui, row_range| {
             ui.allocate_space(vec2(ui.available_width(), 0.));
 
@@ -78,3 +310,46 @@ impl SymbolDetailPane {
         pane_response
     }
 }
+
+/// Selects a span and moves the timeline to it.
+fn select_span(args: &mut TreeBehaviorArgs, span_ref: SpanRef) {
+    let span = args
+        .view_state
+        .threads
+        .get(&span_ref.thread_id)
+        .and_then(|it| it.span(span_ref.span_idx))
+        .cloned();
+    args.view_state.selected_span.replace(span_ref);
+
+    if let (Some(span), Some(last_width)) = (span, args.last_width) {
+        args.view_state
+            .timeline_position_state
+            .pan_to(&span, last_width as _);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tracing::panes::symbol_detail::{Histogram, HISTOGRAM_BUCKETS};
+
+    #[test]
+    fn buckets_durations_on_a_log_scale() {
+        // one second per cycle, so durations range from 1s to 1000s
+        let durations = [1, 1, 10, 100, 1000];
+        let histogram = Histogram::new(&durations, 1.);
+
+        assert_eq!(histogram.buckets.len(), HISTOGRAM_BUCKETS);
+        assert_eq!(histogram.buckets.first(), Some(&(0..2)));
+        assert_eq!(histogram.buckets.last(), Some(&(4..5)));
+        assert_eq!(
+            histogram.buckets.iter().map(|it| it.len()).sum::<usize>(),
+            durations.len()
+        );
+
+        assert_eq!(histogram.bucket_at(0.), Some(0));
+        assert_eq!(histogram.bucket_at(3.), None);
+        assert_eq!(histogram.bucket_at(2.99), Some(HISTOGRAM_BUCKETS - 1));
+        assert_eq!(histogram.bucket_at(-1.), None);
+        assert_eq!(histogram.bucket_at(0.5), None);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::app::tracing::panes::{PaneResponse, TreeBehaviorArgs};
+use crate::app::tracing::utils::format_time;
+use eframe::egui;
+use eframe::egui::{vec2, ScrollArea, Ui};
+
+/// Names, order and visibility of the thread rows on the timeline.
+pub struct ThreadsPane {}
+
+impl ThreadsPane {
+    pub fn title(&self) -> egui::WidgetText {
+        "threads".to_string().into()
+    }
+
+    pub fn pane_ui(&mut self, args: &mut TreeBehaviorArgs, ui: &mut Ui) -> Option<PaneResponse> {
+        let view_state = &mut args.view_state;
+
+        ui.allocate_space(vec2(ui.available_width(), 0.));
+
+        let tsc_frequency = view_state.initial_message.tsc_frequency as f64;
+        let anchor_timestamp = view_state.initial_message.anchor_timestamp;
+        let time = |cycles: u64| {
+            format_time(cycles.saturating_sub(anchor_timestamp) as f64 / tsc_frequency)
+        };
+
+        let mut moved = None;
+
+        ScrollArea::vertical().show(ui, |ui| {
+            for thread_id in view_state.ordered_threads() {
+                let layout = &mut view_state.thread_layout;
+                let traced_name = view_state
+                    .thread_names
+                    .get(thread_id)
+                    .and_then(|it| it.name.clone())
+                    .unwrap_or_else(|| format!("thread {}", thread_id));
+
+                ui.horizontal(|ui| {
+                    let mut visible = !layout.hidden.contains(&thread_id);
+                    if ui
+                        .checkbox(&mut visible, "")
+                        .on_hover_text("show")
+                        .changed()
+                    {
+                        if visible {
+                            layout.hidden.remove(&thread_id);
+                        } else {
+                            layout.hidden.insert(thread_id);
+                        }
+                    }
+
+                    let is_pinned = layout.pinned.contains(&thread_id);
+                    if ui
+                        .selectable_label(is_pinned, "pin")
+                        .on_hover_text("keep above the other threads")
+                        .clicked()
+                    {
+                        if is_pinned {
+                            layout.pinned.remove(&thread_id);
+                        } else {
+                            layout.pinned.insert(thread_id);
+                        }
+                    }
+
+                    if ui.small_button("up").clicked() {
+                        moved = Some((thread_id, -1));
+                    }
+
+                    if ui.small_button("down").clicked() {
+                        moved = Some((thread_id, 1));
+                    }
+
+                    let mut name = layout.names.get(&thread_id).cloned().unwrap_or_default();
+                    let response = ui.add(
+                        egui::TextEdit::singleline(&mut name)
+                            .hint_text(&traced_name)
+                            .desired_width(160.),
+                    );
+                    if response.changed() {
+                        if name.is_empty() {
+                            layout.names.remove(&thread_id);
+                        } else {
+                            layout.names.insert(thread_id, name);
+                        }
+                    }
+                });
+
+                let Some(thread) = view_state.threads.get(&thread_id) else {
+                    continue;
+                };
+
+                let mut details = vec![format!("id {}", thread_id)];
+                if let Some(created) = view_state
+                    .thread_names
+                    .get(thread_id)
+                    .and_then(|it| it.created.as_ref())
+                {
+                    details.push(format!(
+                        "created at {} by {}",
+                        time(created.time),
+                        view_state.thread_name(created.creator)
+                    ));
+                }
+
+                if let Some(exited) = view_state
+                    .thread_names
+                    .get(thread_id)
+                    .and_then(|it| it.exited)
+                {
+                    details.push(format!("exited at {}", time(exited)));
+                }
+
+                let last_event = match thread.chunks.last() {
+                    Some(chunk) => Some(chunk.end_time),
+                    // spans are ordered by end time
+                    None => thread.spans.last().map(|it| it.end_time),
+                };
+                if let Some(last_event) = last_event {
+                    details.push(format!("last event at {}", time(last_event)));
+                }
+
+                ui.weak(details.join(", "));
+            }
+        });
+
+        if let Some((thread_id, offset)) = moved {
+            view_state.move_thread(thread_id, offset);
+        }
+
+        None
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A file which the raw bytes of a live trace stream are written to as they are received.
+pub struct Recording {
+    path: PathBuf,
+    is_temporary: bool,
+}
+
+impl Recording {
+    pub fn temporary() -> Recording {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |it| it.as_secs());
+
+        Recording {
+            path: std::env::temp_dir().join(format!("extern_traces_{}.bin", timestamp)),
+            is_temporary: true,
+        }
+    }
+
+    pub fn at(path: PathBuf) -> Recording {
+        Recording {
+            path,
+            is_temporary: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if self.is_temporary {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Writes every byte read from `inner` to `sink`.
+pub struct TeeReader<R, W> {
+    inner: R,
+    sink: W,
+}
+
+impl<R, W> TeeReader<R, W> {
+    pub fn new(inner: R, sink: W) -> TeeReader<R, W> {
+        TeeReader { inner, sink }
+    }
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.sink.write_all(&buf[..bytes_read])?;
+
+        Ok(bytes_read)
+    }
+}
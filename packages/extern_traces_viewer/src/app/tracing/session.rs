@@ -0,0 +1,213 @@
+use crate::app::tracing::panes::{Pane, SavedTree};
+use crate::app::tracing::view_state::{Annotation, SpanRef, ThreadLayout, ViewState};
+use crate::trace_file::TraceFile;
+use egui_tiles::Tree;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Sessions are written at most this often, as the timeline moves every frame while scrolling.
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Viewer state of a trace file, so reopening it picks up where it was left.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Time range shown on the timeline, unless the whole trace was shown.
+    view_range: Option<(f64, f64)>,
+    selected_span: Option<SpanRef>,
+    current_symbol_detail: Option<usize>,
+    layout: Option<SavedTree>,
+
+    /// Also kept in the trace file when it has an annotations section, so they travel with it.
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+
+    #[serde(default)]
+    thread_layout: ThreadLayout,
+}
+
+impl Session {
+    fn capture(
+        view_state: &ViewState,
+        tree: &Tree<Pane>,
+        visible_range: Option<(f64, f64)>,
+    ) -> Session {
+        Session {
+            view_range: view_range(view_state, visible_range),
+            selected_span: view_state.selected_span.clone(),
+            current_symbol_detail: view_state.current_symbol_detail,
+            layout: SavedTree::save(tree),
+            annotations: view_state.annotations.clone(),
+            thread_layout: view_state.thread_layout.clone(),
+        }
+    }
+
+    fn apply(self, view_state: &mut ViewState, tree: &mut Tree<Pane>) {
+        if let Some((start, end)) = self.view_range {
+            view_state.timeline_position_state.show_range(start, end);
+        }
+
+        view_state.selected_span = self.selected_span;
+        view_state.current_symbol_detail = self
+            .current_symbol_detail
+            .filter(|it| *it < view_state.initial_message.symbols.len());
+
+        view_state.annotations = self.annotations;
+        view_state.thread_layout = self.thread_layout;
+
+        if let Some(layout) = self.layout {
+            *tree = layout.restore();
+        }
+    }
+}
+
+fn view_range(view_state: &ViewState, visible_range: Option<(f64, f64)>) -> Option<(f64, f64)> {
+    visible_range.filter(|_| !view_state.timeline_position_state.is_full_range())
+}
+
+/// Hash of everything kept in a session, cheap enough to compare every frame. Only the layout is
+/// serialized, it's a handful of tiles.
+fn change_marker(
+    view_state: &ViewState,
+    tree: &Tree<Pane>,
+    visible_range: Option<(f64, f64)>,
+) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    view_range(view_state, visible_range)
+        .map(|(start, end)| (start.to_bits(), end.to_bits()))
+        .hash(&mut hasher);
+    view_state.selected_span.hash(&mut hasher);
+    view_state.current_symbol_detail.hash(&mut hasher);
+    view_state.annotations.hash(&mut hasher);
+    view_state.thread_layout.hash(&mut hasher);
+    serde_json::to_vec(&SavedTree::save(tree))?.hash(&mut hasher);
+
+    Ok(hasher.finish())
+}
+
+/// Session kept next to a trace file, as `<trace file>.session.json`.
+pub struct SessionFile {
+    path: PathBuf,
+
+    /// The trace file, when it has an annotations section.
+    trace_file: Option<TraceFile>,
+
+    /// Read from the file, applied once the trace has started loading.
+    pending_restore: Option<Session>,
+
+    /// `change_marker` of the session last written, or restored.
+    last_marker: Option<u64>,
+    last_written: Option<String>,
+    last_written_annotations: Option<String>,
+    last_write_time: Option<Instant>,
+}
+
+impl SessionFile {
+    pub fn open(trace_path: &Path) -> io::Result<SessionFile> {
+        let mut path = trace_path.as_os_str().to_owned();
+        path.push(".session.json");
+        let path = PathBuf::from(path);
+
+        let mut pending_restore = match fs::read(&path) {
+            Ok(contents) => Some(serde_json::from_slice::<Session>(&contents)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        // annotations in the trace file win, it may have been annotated somewhere else
+        let trace_file = TraceFile::open(trace_path).ok();
+        let trace_annotations = match &trace_file {
+            Some(trace_file) => trace_file.annotations()?,
+            None => None,
+        };
+
+        if let Some(data) = trace_annotations.as_ref().filter(|it| !it.is_empty()) {
+            pending_restore
+                .get_or_insert_with(Session::default)
+                .annotations = serde_json::from_slice(data)?;
+        }
+
+        Ok(SessionFile {
+            path,
+            trace_file: trace_file.filter(|_| trace_annotations.is_some()),
+            pending_restore,
+            last_marker: None,
+            last_written: None,
+            last_written_annotations: None,
+            last_write_time: None,
+        })
+    }
+
+    /// Applies the saved session, the first time this is called.
+    pub fn restore(&mut self, view_state: &mut ViewState, tree: &mut Tree<Pane>) {
+        if let Some(session) = self.pending_restore.take() {
+            session.apply(view_state, tree);
+        }
+    }
+
+    /// Writes the session if it changed since it was last written, at most every
+    /// `WRITE_INTERVAL`.
+    pub fn save(
+        &mut self,
+        view_state: &ViewState,
+        tree: &Tree<Pane>,
+        visible_range: Option<(f64, f64)>,
+    ) -> io::Result<()> {
+        if self
+            .last_write_time
+            .is_some_and(|it| it.elapsed() < WRITE_INTERVAL)
+        {
+            return Ok(());
+        }
+
+        self.flush(view_state, tree, visible_range)
+    }
+
+    /// Writes the session if it changed since it was last written, like when the trace is
+    /// closed.
+    pub fn flush(
+        &mut self,
+        view_state: &ViewState,
+        tree: &Tree<Pane>,
+        visible_range: Option<(f64, f64)>,
+    ) -> io::Result<()> {
+        let marker = change_marker(view_state, tree, visible_range)?;
+        if self.last_marker == Some(marker) {
+            return Ok(());
+        }
+
+        let contents =
+            serde_json::to_string_pretty(&Session::capture(view_state, tree, visible_range))?;
+        let annotations = serde_json::to_string(&view_state.annotations)?;
+
+        // the first capture is what was just restored, there's nothing new to write
+        let Some(last_written) = &self.last_written else {
+            self.last_marker = Some(marker);
+            self.last_written = Some(contents);
+            self.last_written_annotations = Some(annotations);
+            return Ok(());
+        };
+
+        if *last_written != contents {
+            self.last_write_time = Some(Instant::now());
+            fs::write(&self.path, &contents)?;
+            self.last_written = Some(contents);
+        }
+
+        if let Some(trace_file) = &mut self.trace_file {
+            if self.last_written_annotations.as_ref() != Some(&annotations) {
+                trace_file.write_annotations(annotations.as_bytes())?;
+                self.last_written_annotations = Some(annotations);
+            }
+        }
+
+        self.last_marker = Some(marker);
+
+        Ok(())
+    }
+}
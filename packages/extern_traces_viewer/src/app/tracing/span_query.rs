@@ -0,0 +1,305 @@
+use crate::app::tracing::view_state::{SpanRef, ThreadState};
+use crate::proto::InitialMessage;
+use crate::sce_error::SceError;
+use crate::spans::ThreadSpan;
+use ps4libdoc::LoadedDocumentation;
+use regex::{Regex, RegexBuilder};
+use std::collections::BTreeMap;
+
+/// A search over spans. Queries are whitespace separated terms which all have to match:
+///
+/// * `foo` or `name:foo`: symbol name contains `foo`, ignoring case
+/// * `/foo.*bar/` or `name:/foo.*bar/`: symbol name matches a regex
+/// * `module:foo`, `library:foo`: module or library name contains `foo`
+/// * `thread:123`
+/// * `duration>1ms`, `duration<10us`: durations in `ns`, `us`, `ms` or `s`
+/// * `after:1.5s`, `before:2s`: start time since the start of the trace
+/// * `has:extra`, `has:error`: spans with extra data or returning an error
+pub struct SpanQuery {
+    terms: Vec<Term>,
+}
+
+enum Term {
+    Name(NameMatcher),
+    Module(String),
+    Library(String),
+    Thread(u64),
+    MinDuration(f64),
+    MaxDuration(f64),
+    After(f64),
+    Before(f64),
+    HasExtraData,
+    HasError,
+}
+
+enum NameMatcher {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl NameMatcher {
+    fn parse(value: &str) -> Result<NameMatcher, String> {
+        match value
+            .strip_prefix('/')
+            .and_then(|it| it.strip_suffix('/'))
+            .filter(|it| !it.is_empty())
+        {
+            Some(pattern) => Ok(NameMatcher::Regex(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| err.to_string())?,
+            )),
+            None => Ok(NameMatcher::Contains(value.to_lowercase())),
+        }
+    }
+
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Contains(value) => name.to_lowercase().contains(value),
+            NameMatcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl SpanQuery {
+    pub fn parse(query: &str) -> Result<SpanQuery, String> {
+        let terms = query
+            .split_whitespace()
+            .map(Term::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(SpanQuery { terms })
+    }
+
+    /// Matching loaded spans of all threads, ordered by start time.
+    pub fn search(
+        &self,
+        initial_message: &InitialMessage,
+        docs: &LoadedDocumentation,
+        threads: &BTreeMap<u64, ThreadState>,
+    ) -> Vec<SpanRef> {
+        // names are resolved once per symbol rather than once per span
+        let symbols = (0..initial_message.symbols.len())
+            .map(|symbol_idx| self.matches_symbol(initial_message, docs, symbol_idx))
+            .collect::<Vec<_>>();
+
+        let seconds = |cycles: u64| cycles as f64 / initial_message.tsc_frequency as f64;
+
+        let mut results = threads
+            .iter()
+            .flat_map(|(thread_id, thread)| {
+                thread
+                    .spans
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, span)| (*thread_id, thread.spans_offset + idx, span))
+            })
+            .filter(|(thread_id, _, span)| {
+                symbols
+                    .get(span.label_id as usize)
+                    .copied()
+                    .unwrap_or(false)
+                    && self
+                        .terms
+                        .iter()
+                        .all(|term| term.matches_span(*thread_id, span, initial_message, seconds))
+            })
+            .map(|(thread_id, span_idx, span)| {
+                (
+                    span.start_time,
+                    SpanRef {
+                        thread_id,
+                        span_idx,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        results.sort_by_key(|(start_time, _)| *start_time);
+
+        results.into_iter().map(|(_, span_ref)| span_ref).collect()
+    }
+
+    /// Symbols matching the name, module and library terms, whether or not they have loaded
+    /// spans.
+    pub fn matching_symbols(
+        &self,
+        initial_message: &InitialMessage,
+        docs: &LoadedDocumentation,
+    ) -> Vec<usize> {
+        (0..initial_message.symbols.len())
+            .filter(|symbol_idx| self.matches_symbol(initial_message, docs, *symbol_idx))
+            .collect()
+    }
+
+    fn matches_symbol(
+        &self,
+        initial_message: &InitialMessage,
+        docs: &LoadedDocumentation,
+        symbol_idx: usize,
+    ) -> bool {
+        let symbol = &initial_message.symbols[symbol_idx];
+        let module = initial_message
+            .modules
+            .get(&(symbol.module_id as _))
+            .map_or("", |it| it.name.as_str());
+        let library = initial_message
+            .libraries
+            .get(&(symbol.library_id as _))
+            .map_or("", |it| it.name.as_str());
+        let name = initial_message
+            .documented_name(docs, symbol_idx)
+            .unwrap_or(symbol.name.as_str());
+
+        self.terms.iter().all(|term| match term {
+            Term::Name(matcher) => matcher.is_match(name),
+            Term::Module(value) => module.to_lowercase().contains(value),
+            Term::Library(value) => library.to_lowercase().contains(value),
+            _ => true,
+        })
+    }
+}
+
+impl Term {
+    fn parse(term: &str) -> Result<Term, String> {
+        if let Some(value) = term.strip_prefix("duration>") {
+            return Ok(Term::MinDuration(parse_seconds(value)?));
+        }
+
+        if let Some(value) = term.strip_prefix("duration<") {
+            return Ok(Term::MaxDuration(parse_seconds(value)?));
+        }
+
+        let Some((key, value)) = term.split_once(':') else {
+            return Ok(Term::Name(NameMatcher::parse(term)?));
+        };
+
+        match key {
+            "name" => Ok(Term::Name(NameMatcher::parse(value)?)),
+            "module" => Ok(Term::Module(value.to_lowercase())),
+            "library" => Ok(Term::Library(value.to_lowercase())),
+            "thread" => Ok(Term::Thread(
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid thread id \"{}\"", value))?,
+            )),
+            "after" => Ok(Term::After(parse_seconds(value)?)),
+            "before" => Ok(Term::Before(parse_seconds(value)?)),
+            "has" => match value {
+                "extra" => Ok(Term::HasExtraData),
+                "error" => Ok(Term::HasError),
+                _ => Err(format!("unknown has:{}, expected extra or error", value)),
+            },
+            // symbol names like `sce::Gnm::submit` contain colons too
+            _ => Ok(Term::Name(NameMatcher::parse(term)?)),
+        }
+    }
+
+    fn matches_span(
+        &self,
+        thread_id: u64,
+        span: &ThreadSpan,
+        initial_message: &InitialMessage,
+        seconds: impl Fn(u64) -> f64,
+    ) -> bool {
+        let duration = || seconds(span.end_time - span.start_time);
+        let start = || {
+            seconds(
+                span.start_time
+                    .saturating_sub(initial_message.anchor_timestamp),
+            )
+        };
+
+        match self {
+            Term::Thread(value) => thread_id == *value,
+            Term::MinDuration(value) => duration() > *value,
+            Term::MaxDuration(value) => duration() < *value,
+            Term::After(value) => start() >= *value,
+            Term::Before(value) => start() < *value,
+            Term::HasExtraData => span.start_extra_data.is_some() || span.end_extra_data.is_some(),
+            Term::HasError => SceError::from_span(initial_message, span).is_some(),
+            Term::Name(..) | Term::Module(..) | Term::Library(..) => true,
+        }
+    }
+}
+
+/// Parses a time like `1.5ms` into seconds.
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .ok_or_else(|| format!("missing unit in \"{}\", expected ns, us, ms or s", value))?;
+    let (number, unit) = value.split_at(split);
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid number in \"{}\"", value))?;
+    let scale = match unit {
+        "ns" => 1e-9,
+        "us" | "µs" => 1e-6,
+        "ms" => 1e-3,
+        "s" => 1.,
+        _ => {
+            return Err(format!(
+                "unknown unit \"{}\", expected ns, us, ms or s",
+                unit
+            ))
+        }
+    };
+
+    Ok(number * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tracing::span_query::{parse_seconds, NameMatcher, SpanQuery};
+    use crate::proto::{InitialMessage, SymbolInfo};
+    use ps4libdoc::LoadedDocumentation;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn parses_queries() {
+        assert_eq!(parse_seconds("1.5ms"), Ok(0.0015));
+        assert_eq!(parse_seconds("2s"), Ok(2.));
+        assert!(parse_seconds("10").is_err());
+        assert!(parse_seconds("10min").is_err());
+
+        assert!(SpanQuery::parse("sceKernel thread:12 duration>1ms has:extra").is_ok());
+        assert!(SpanQuery::parse("thread:abc").is_err());
+        assert!(SpanQuery::parse("has:nothing").is_err());
+        assert!(SpanQuery::parse("/(/").is_err());
+
+        let matcher = NameMatcher::parse("/^scekernel(open|close)$/").unwrap();
+        assert!(matcher.is_match("sceKernelOpen"));
+        assert!(!matcher.is_match("sceKernelOpenEventFlag"));
+
+        let matcher = NameMatcher::parse("gnm").unwrap();
+        assert!(matcher.is_match("sceGnmSubmitDone"));
+    }
+
+    #[test]
+    fn lists_symbols_without_spans() {
+        let symbol = |name: &str| SymbolInfo {
+            name: name.to_string(),
+            library_id: 0,
+            module_id: 0,
+        };
+        let initial_message = InitialMessage {
+            tsc_frequency: 1_000_000,
+            anchor_seconds: 0,
+            anchor_nanoseconds: 0,
+            anchor_timestamp: 0,
+            modules: BTreeMap::new(),
+            libraries: BTreeMap::new(),
+            symbols: vec![symbol("sceKernelOpen"), symbol("sceGnmSubmitDone")],
+        };
+        let docs = LoadedDocumentation::bundled().unwrap();
+
+        // span terms don't narrow down symbols
+        let query = SpanQuery::parse("kernel duration>1s").unwrap();
+        assert_eq!(query.matching_symbols(&initial_message, &docs), vec![0]);
+        assert!(query
+            .search(&initial_message, &docs, &BTreeMap::new())
+            .is_empty());
+    }
+}
@@ -1,5 +1,5 @@
-use crate::app::tracing::view_state::ThreadSpan;
 use crate::proto::InitialMessage;
+use crate::spans::ThreadSpan;
 use std::time::SystemTime;
 
 pub struct TimelinePositionState {
@@ -29,6 +29,11 @@ impl TimelinePositionState {
         self.timestamp_range.add_value(timestamp);
     }
 
+    /// Earliest and latest timestamps seen in the trace.
+    pub fn timestamp_range(&self) -> Option<(u64, u64)> {
+        self.timestamp_range.values
+    }
+
     pub fn translate_x(&mut self, cycles_delta: f64, current_position: DisplayPosition) {
         if cycles_delta == 0. {
             return;
@@ -60,15 +65,38 @@ impl TimelinePositionState {
     }
 
     pub fn pan_to(&mut self, thread_span: &ThreadSpan, width: f64) {
+        self.zoom_to(thread_span.start_time, thread_span.end_time, width)
+    }
+
+    /// Shows `start..end` across the whole width of the timeline.
+    pub fn zoom_to(&mut self, start: u64, end: u64, width: f64) {
         self.view_range = ViewRange::Slice(DisplayPosition {
-            offset: thread_span.start_time as f64,
-            cycles_per_pixel: (thread_span.end_time - thread_span.start_time) as f64 / width,
+            offset: start as f64,
+            cycles_per_pixel: (end - start) as f64 / width,
         })
     }
 
+    /// Shows `start..end` across the whole width of the timeline, whatever width it ends up
+    /// being drawn at.
+    pub fn show_range(&mut self, start: f64, end: f64) {
+        self.view_range = ViewRange::Range { start, end };
+    }
+
+    /// Whether the whole trace is shown, rather than a range the user moved to.
+    pub fn is_full_range(&self) -> bool {
+        matches!(self.view_range, ViewRange::Full)
+    }
+
     pub fn position(&self, range: f64) -> DisplayPosition {
-        if let ViewRange::Slice(position) = self.view_range {
-            return position;
+        match self.view_range {
+            ViewRange::Full => {}
+            ViewRange::Slice(position) => return position,
+            ViewRange::Range { start, end } => {
+                return DisplayPosition {
+                    offset: start,
+                    cycles_per_pixel: (end - start) / range,
+                }
+            }
         }
 
         let (min, max) = self.timestamp_range.values.unwrap_or((0, 0));
@@ -112,6 +140,7 @@ impl TimestampRange {
 enum ViewRange {
     Full,
     Slice(DisplayPosition),
+    Range { start: f64, end: f64 },
 }
 
 #[derive(Clone, Copy)]
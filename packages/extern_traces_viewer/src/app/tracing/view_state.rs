@@ -1,35 +1,129 @@
+use crate::app::tracing::chunk_loader::{ChunkRequest, LoadedChunks};
 use crate::app::tracing::timeline_position::TimelinePositionState;
-use crate::proto::{InitialMessage, SpanEnd, SpanEvent, SpanStart};
-use std::collections::BTreeMap;
+use crate::frames::FrameMarkers;
+use crate::proto::{CountersUpdate, InitialMessage, SpanEvent};
+use crate::sampling::SamplingHistory;
+use crate::spans::{ReorderCounts, SpanAssembler, ThreadSpan};
+use crate::sync::{SyncCalls, SyncObject, SyncOp};
+use crate::threads::ThreadNames;
+use crate::trace_file::{ChunkInfo, TraceIndex};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::ops::Range;
 
-#[derive(Clone)]
-pub struct ThreadSpan {
-    pub start_time: u64,
-    pub end_time: u64,
-    pub label_id: u64,
-    pub start_extra_data: Option<Vec<u8>>,
-    pub end_extra_data: Option<Vec<u8>>,
-}
+/// Upper bound on the number of spans kept in memory when viewing an indexed trace file.
+const MAX_LOADED_SPANS: u64 = 4_000_000;
 
-impl ThreadSpan {
-    pub fn from_events(start: SpanStart, end: SpanEnd) -> ThreadSpan {
-        ThreadSpan {
-            start_time: start.time,
-            end_time: end.time,
-            label_id: start.label_id,
-            start_extra_data: start.extra_data,
-            end_extra_data: end.extra_data,
-        }
-    }
-}
+/// Seconds a finished span is held back for reordering while tracing live, after which spans of
+/// other threads are assumed to have caught up.
+const REORDER_DELAY: f64 = 0.25;
 
 pub struct ThreadState {
+    /// Spans in the order they ended, so nested spans come before the spans containing them.
     pub spans: Vec<ThreadSpan>,
-    pub currently_started: Option<SpanStart>,
+
+    /// Index of `spans[0]` among all spans of the thread. Only the spans of `loaded_chunks` are
+    /// kept in `spans` when viewing an indexed trace file.
+    pub spans_offset: usize,
+    pub chunks: Vec<ChunkInfo>,
+    pub loaded_chunks: Range<usize>,
+
+    pub assembler: SpanAssembler,
+
+    /// Spans which arrived out of order, for the loaded spans.
+    pub reorder_counts: ReorderCounts,
+
+    /// Spans of each nesting depth, drawn as a row each.
+    pub levels: Vec<SpanLevel>,
+
+    /// Windows in which messages of the thread were dropped by the plugin, ordered by time.
+    pub dropped_packets: Vec<DroppedPackets>,
+}
+
+pub struct SpanLevel {
+    /// Indices into `ThreadState::spans` of the spans at this depth, ordered by time. Spans at the
+    /// same depth never overlap.
+    pub spans: Vec<usize>,
     pub folded_spans_state: FoldSpansState,
 }
 
+pub struct DroppedPackets {
+    pub count: u64,
+    pub time_range: Range<u64>,
+}
+
+impl ThreadState {
+    pub fn new() -> ThreadState {
+        ThreadState {
+            spans: Vec::new(),
+            spans_offset: 0,
+            chunks: Vec::new(),
+            loaded_chunks: 0..0,
+            assembler: SpanAssembler::new(),
+            reorder_counts: ReorderCounts::default(),
+            levels: Vec::new(),
+            dropped_packets: Vec::new(),
+        }
+    }
+
+    fn push_span(&mut self, span: ThreadSpan) {
+        let depth = span.depth as usize;
+        if self.levels.len() <= depth {
+            self.levels.resize_with(depth + 1, || SpanLevel {
+                spans: Vec::new(),
+                folded_spans_state: FoldSpansState::new(),
+            });
+        }
+
+        self.levels[depth].spans.push(self.spans.len());
+        self.spans.push(span);
+    }
+
+    /// Number of rows needed to draw the thread.
+    pub fn row_count(&self) -> usize {
+        self.levels.len().max(1)
+    }
+
+    pub fn span(&self, span_idx: usize) -> Option<&ThreadSpan> {
+        self.spans.get(span_idx.checked_sub(self.spans_offset)?)
+    }
+
+    pub fn total_spans(&self) -> usize {
+        match self.chunks.last() {
+            Some(chunk) => (chunk.first_span_idx + chunk.span_count) as usize,
+            None => self.spans.len(),
+        }
+    }
+
+    pub fn dropped_packets_total(&self) -> u64 {
+        self.dropped_packets.iter().map(|it| it.count).sum()
+    }
+
+    /// Dropped packet windows overlapping the `low..hi` time range.
+    pub fn dropped_packets_in_range(&self, low: f64, hi: f64) -> &[DroppedPackets] {
+        let i = self
+            .dropped_packets
+            .partition_point(|it| (it.time_range.end as f64) < low);
+        let j = self
+            .dropped_packets
+            .partition_point(|it| (it.time_range.start as f64) < hi);
+
+        &self.dropped_packets[i.min(j)..j]
+    }
+
+    /// Chunks overlapping the `low..hi` time range.
+    pub fn chunks_in_range(&self, low: f64, hi: f64) -> Range<usize> {
+        let i = self
+            .chunks
+            .partition_point(|chunk| (chunk.end_time as f64) < low);
+        let j = self
+            .chunks
+            .partition_point(|chunk| (chunk.start_time as f64) < hi);
+
+        i.min(j)..j
+    }
+}
+
 pub struct FoldSpansState {
     last_calculated_value: Option<(Range<usize>, Vec<(usize, usize)>)>,
 }
@@ -43,9 +137,12 @@ impl FoldSpansState {
 }
 
 impl FoldSpansState {
+    /// Merges runs of spans from `level` shorter than `min_cycles`. Returns ranges of positions in
+    /// `level`.
     pub fn fold(
         &mut self,
         range: Range<usize>,
+        level: &[usize],
         thread_spans: &[ThreadSpan],
         min_cycles: u64,
     ) -> &[(usize, usize)] {
@@ -64,7 +161,7 @@ impl FoldSpansState {
 
         let mut idx = range.start;
         while idx < range.end {
-            let span = &thread_spans[idx];
+            let span = &thread_spans[level[idx]];
 
             let mut next_idx = idx + 1;
             loop {
@@ -72,7 +169,7 @@ impl FoldSpansState {
                     break;
                 }
 
-                let next_span = &thread_spans[next_idx];
+                let next_span = &thread_spans[level[next_idx]];
                 if (next_span.end_time - span.start_time) >= min_cycles {
                     break;
                 }
@@ -90,12 +187,123 @@ impl FoldSpansState {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Hash, Serialize, Deserialize)]
 pub struct SpanRef {
     pub thread_id: u64,
     pub span_idx: usize,
 }
 
+/// A named point in time or time range, drawn on the timeline.
+#[derive(Clone, Hash, Serialize, Deserialize)]
+pub struct Annotation {
+    pub name: String,
+    pub start_time: u64,
+
+    /// End of a range annotation. Markers only have a start.
+    pub end_time: Option<u64>,
+}
+
+/// How thread rows were arranged on the timeline.
+#[derive(Clone, Default, Hash, Serialize, Deserialize)]
+pub struct ThreadLayout {
+    /// Threads in the order they were moved to. Threads missing from it follow, ordered by id.
+    pub order: Vec<u64>,
+    pub hidden: BTreeSet<u64>,
+
+    /// Drawn above the other threads, outside of the scrolling rows.
+    pub pinned: BTreeSet<u64>,
+
+    /// Names given in the viewer, which win over the names from the trace.
+    pub names: BTreeMap<u64, String>,
+}
+
+/// Spans matching the query of the search pane, highlighted on the timeline.
+#[derive(Default)]
+pub struct SearchResults {
+    /// Ordered by start time.
+    pub spans: Vec<SpanRef>,
+
+    /// Position in `spans` of the result last stepped to.
+    pub current: Option<usize>,
+
+    matching: HashSet<(u64, usize)>,
+}
+
+impl SearchResults {
+    pub fn new(spans: Vec<SpanRef>) -> SearchResults {
+        SearchResults {
+            matching: spans.iter().map(|it| (it.thread_id, it.span_idx)).collect(),
+            spans,
+            current: None,
+        }
+    }
+
+    pub fn contains(&self, thread_id: u64, span_idx: usize) -> bool {
+        self.matching.contains(&(thread_id, span_idx))
+    }
+
+    /// Steps forward or backward through the results, wrapping around at either end. Returns the
+    /// new position.
+    pub fn step(&mut self, forward: bool) -> Option<usize> {
+        let len = self.spans.len();
+        if len == 0 {
+            return None;
+        }
+
+        let current = match (self.current, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(current), true) => (current + 1) % len,
+            (Some(current), false) => (current + len - 1) % len,
+        };
+        self.current = Some(current);
+
+        Some(current)
+    }
+}
+
+/// Signalling calls on sync objects among the loaded spans, for finding the span which woke a
+/// waiting span.
+#[derive(Default)]
+pub struct SyncSignals {
+    /// Start times and spans of each object, ordered by start time.
+    by_object: BTreeMap<SyncObject, Vec<(u64, SpanRef)>>,
+}
+
+impl SyncSignals {
+    fn add(&mut self, object: SyncObject, time: u64, span_ref: SpanRef) {
+        let signals = self.by_object.entry(object).or_default();
+        let idx = signals.partition_point(|(it, _)| *it <= time);
+        signals.insert(idx, (time, span_ref));
+    }
+
+    fn remove_thread(&mut self, thread_id: u64) {
+        for signals in self.by_object.values_mut() {
+            signals.retain(|(_, it)| it.thread_id != thread_id);
+        }
+    }
+
+    /// The last signal of `object` from another thread while `thread_id` waited on it from
+    /// `start` to `end`.
+    pub fn waker(
+        &self,
+        object: SyncObject,
+        thread_id: u64,
+        start: u64,
+        end: u64,
+    ) -> Option<&SpanRef> {
+        let signals = self.by_object.get(&object)?;
+        let i = signals.partition_point(|(it, _)| *it < start);
+        let j = signals.partition_point(|(it, _)| *it <= end);
+
+        signals[i..j]
+            .iter()
+            .rev()
+            .map(|(_, it)| it)
+            .find(|it| it.thread_id != thread_id)
+    }
+}
+
 pub enum ViewStateContainer {
     Empty,
     Initialized(ViewState),
@@ -107,6 +315,18 @@ pub struct ViewState {
     pub selected_span: Option<SpanRef>,
     pub current_symbol_detail: Option<usize>,
     pub extra_data_messages: Vec<SpanRef>,
+    pub search_results: SearchResults,
+    pub frames: FrameMarkers,
+    pub annotations: Vec<Annotation>,
+    pub thread_names: ThreadNames,
+    pub thread_layout: ThreadLayout,
+    pub sync_calls: SyncCalls,
+    pub sync_signals: SyncSignals,
+    pub sampling: SamplingHistory,
+
+    /// Changes whenever spans are added or chunks are swapped, for invalidating anything
+    /// computed from the loaded spans.
+    spans_generation: u64,
 
     pub timeline_position_state: TimelinePositionState,
 }
@@ -119,68 +339,356 @@ impl ViewStateContainer {
     pub fn initialize(&mut self, initial_message: InitialMessage) {
         *self = ViewStateContainer::Initialized(ViewState {
             timeline_position_state: TimelinePositionState::new(&initial_message),
+            frames: FrameMarkers::new(&initial_message),
+            thread_names: ThreadNames::new(&initial_message),
+            sync_calls: SyncCalls::new(&initial_message),
+            sync_signals: SyncSignals::default(),
+            sampling: SamplingHistory::default(),
+            spans_generation: 0,
             initial_message,
             threads: BTreeMap::new(),
             selected_span: None,
             current_symbol_detail: None,
             extra_data_messages: vec![],
+            search_results: SearchResults::default(),
+            annotations: Vec::new(),
+            thread_layout: ThreadLayout::default(),
         })
     }
 }
 
 impl ViewState {
     pub fn update_span(&mut self, event: SpanEvent) {
-        match event {
-            SpanEvent::Start(start) => {
-                self.timeline_position_state.add_timestamp_range(start.time);
-
-                let thread = self
-                    .threads
-                    .entry(start.thread_id)
-                    .or_insert_with(|| ThreadState {
-                        spans: Vec::new(),
-                        currently_started: None,
-                        folded_spans_state: FoldSpansState::new(),
-                    });
-
-                if let Some(..) = thread.currently_started.replace(start) {
-                    println!("two spans started");
-                };
+        let thread_id = event.thread_id();
+        self.timeline_position_state
+            .add_timestamp_range(event.time());
+
+        if let SpanEvent::Start(start) = &event {
+            if self.frames.is_flip(start.label_id) {
+                self.frames.add_flip(start.time);
             }
-            SpanEvent::End(end) => {
-                self.timeline_position_state.add_timestamp_range(end.time);
-
-                let state = self.threads.get_mut(&end.thread_id).unwrap();
-                if let Some(start) = state.currently_started.take() {
-                    if let Some(last_span) = state.spans.last() {
-                        if start.time < last_span.end_time {
-                            println!(
-                                "out-of-order span detected on thread {}: start time {} is before last span end time {}",
-                                end.thread_id,
-                                start.time,
-                                last_span.end_time
-                            );
-
-                            return;
-                        }
-                    }
+        }
+
+        self.thread_names.observe(&event);
+
+        // ends can arrive first when joining a capture mid call or after dropped packets, the
+        // assembler drops them
+        let state = self
+            .threads
+            .entry(thread_id)
+            .or_insert_with(ThreadState::new);
+
+        let span = state.assembler.push(event);
+        state.reorder_counts = state.assembler.counts();
 
-                    let span_ref = SpanRef {
-                        span_idx: state.spans.len(),
-                        thread_id: end.thread_id,
-                    };
-                    let span = ThreadSpan::from_events(start, end);
+        if let Some(span) = span {
+            self.push_span(thread_id, span);
+        }
+    }
+
+    /// Releases spans held back for reordering, once no more events will arrive.
+    pub fn flush_pending_spans(&mut self) {
+        self.release_spans_ended_by(u64::MAX);
+    }
+
+    /// Releases spans held back for reordering which ended more than `REORDER_DELAY` before the
+    /// latest event, so threads making few calls still show their spans while tracing live.
+    pub fn release_late_spans(&mut self) {
+        let Some((_, latest)) = self.timeline_position_state.timestamp_range() else {
+            return;
+        };
+
+        let delay = (REORDER_DELAY * self.initial_message.tsc_frequency as f64) as u64;
+        self.release_spans_ended_by(latest.saturating_sub(delay));
+    }
+
+    fn release_spans_ended_by(&mut self, time: u64) {
+        let thread_ids = self.threads.keys().copied().collect::<Vec<_>>();
+        for thread_id in thread_ids {
+            let spans = self
+                .threads
+                .get_mut(&thread_id)
+                .unwrap()
+                .assembler
+                .release_ended_by(time)
+                .collect::<Vec<_>>();
+            for span in spans {
+                self.push_span(thread_id, span);
+            }
+        }
+    }
+
+    fn push_span(&mut self, thread_id: u64, span: ThreadSpan) {
+        let Some(state) = self.threads.get_mut(&thread_id) else {
+            return;
+        };
+
+        let span_ref = SpanRef {
+            span_idx: state.spans_offset + state.spans.len(),
+            thread_id,
+        };
+        match self.sync_calls.call(&span) {
+            Some((object, SyncOp::Signal)) => {
+                self.sync_signals.add(object, span.start_time, span_ref)
+            }
+            // the handle isn't worth listing with the other extra data
+            Some((_, SyncOp::Wait)) => {}
+            None => {
+                if span.start_extra_data.is_some() || span.end_extra_data.is_some() {
+                    self.extra_data_messages.push(span_ref);
+                }
+            }
+        }
+
+        state.push_span(span);
+        self.spans_generation += 1;
+    }
+
+    pub fn update_counters(&mut self, counters: CountersUpdate) {
+        let thread = self
+            .threads
+            .entry(counters.thread_id)
+            .or_insert_with(ThreadState::new);
+
+        thread.dropped_packets.push(DroppedPackets {
+            count: counters.dropped_packets_delta,
+            time_range: counters.last_time..counters.time,
+        });
+    }
+
+    pub fn reorder_counts(&self) -> ReorderCounts {
+        self.threads
+            .values()
+            .fold(ReorderCounts::default(), |total, thread| ReorderCounts {
+                corrected: total.corrected + thread.reorder_counts.corrected,
+                unrecoverable: total.unrecoverable + thread.reorder_counts.unrecoverable,
+            })
+    }
+
+    pub fn dropped_packets_total(&self) -> u64 {
+        self.threads
+            .values()
+            .map(|it| it.dropped_packets_total())
+            .sum()
+    }
+
+    /// Sets up threads from the index of a trace file. Spans are loaded later through
+    /// `chunk_requests` and `apply_loaded_chunks`.
+    pub fn attach_index(&mut self, index: &TraceIndex) {
+        for (thread_id, chunks) in &index.threads {
+            for chunk in chunks {
+                self.timeline_position_state
+                    .add_timestamp_range(chunk.start_time);
+                self.timeline_position_state
+                    .add_timestamp_range(chunk.end_time);
+            }
+
+            let thread = self
+                .threads
+                .entry(*thread_id)
+                .or_insert_with(ThreadState::new);
+            thread.chunks = chunks.clone();
+        }
+    }
+
+    /// Whether spans of an indexed trace file are left out of `threads` because their chunks
+    /// aren't loaded.
+    pub fn has_unloaded_chunks(&self) -> bool {
+        self.threads
+            .values()
+            .any(|thread| thread.loaded_chunks != (0..thread.chunks.len()))
+    }
+
+    /// Chunks which need to be loaded to show the `low..hi` time range. Nothing is requested
+    /// when the range covers too many spans.
+    pub fn chunk_requests(&self, low: f64, hi: f64) -> Vec<ChunkRequest> {
+        let desired = self
+            .threads
+            .iter()
+            .filter(|(_, thread)| !thread.chunks.is_empty())
+            .map(|(thread_id, thread)| {
+                // load a chunk on either side to avoid gaps while panning
+                let range = thread.chunks_in_range(low, hi);
+                let range = range.start.saturating_sub(1)..(range.end + 1).min(thread.chunks.len());
+
+                (*thread_id, range)
+            })
+            .collect::<Vec<_>>();
+
+        let total_spans = desired
+            .iter()
+            .map(|(thread_id, range)| {
+                self.threads[thread_id].chunks[range.clone()]
+                    .iter()
+                    .map(|chunk| chunk.span_count)
+                    .sum::<u64>()
+            })
+            .sum::<u64>();
+
+        if total_spans > MAX_LOADED_SPANS {
+            return Vec::new();
+        }
+
+        desired
+            .into_iter()
+            .filter(|(thread_id, range)| self.threads[thread_id].loaded_chunks != *range)
+            .map(|(thread_id, chunks)| ChunkRequest { thread_id, chunks })
+            .collect()
+    }
+
+    pub fn apply_loaded_chunks(&mut self, loaded: LoadedChunks) {
+        let Some(thread) = self.threads.get_mut(&loaded.thread_id) else {
+            return;
+        };
+
+        thread.spans_offset = thread
+            .chunks
+            .get(loaded.chunks.start)
+            .map_or(0, |chunk| chunk.first_span_idx as usize);
+        thread.spans.clear();
+        thread.loaded_chunks = loaded.chunks;
+        self.spans_generation += 1;
+        thread.reorder_counts = loaded.reorder_counts;
+
+        // levels are kept so rows don't jump around while scrolling
+        for level in &mut thread.levels {
+            level.spans.clear();
+            level.folded_spans_state = FoldSpansState::new();
+        }
+
+        for span in loaded.spans {
+            thread.push_span(span);
+        }
+
+        self.extra_data_messages
+            .retain(|it| it.thread_id != loaded.thread_id);
+        self.sync_signals.remove_thread(loaded.thread_id);
+        for (idx, span) in thread.spans.iter().enumerate() {
+            let span_ref = SpanRef {
+                thread_id: loaded.thread_id,
+                span_idx: thread.spans_offset + idx,
+            };
+
+            match self.sync_calls.call(span) {
+                Some((object, SyncOp::Signal)) => {
+                    self.sync_signals.add(object, span.start_time, span_ref)
+                }
+                Some((_, SyncOp::Wait)) => {}
+                None => {
                     if span.start_extra_data.is_some() || span.end_extra_data.is_some() {
                         self.extra_data_messages.push(span_ref);
                     }
-
-                    state.spans.push(span);
-                };
+                }
             }
         }
     }
 
+    pub fn thread_name(&self, thread_id: u64) -> String {
+        if let Some(name) = self.thread_layout.names.get(&thread_id) {
+            return name.clone();
+        }
+
+        match self
+            .thread_names
+            .get(thread_id)
+            .and_then(|it| it.name.as_ref())
+        {
+            Some(name) => name.clone(),
+            None => format!("thread {}", thread_id),
+        }
+    }
+
+    /// Threads in the order they're drawn, hidden threads included.
+    pub fn ordered_threads(&self) -> Vec<u64> {
+        let mut ordered = self
+            .thread_layout
+            .order
+            .iter()
+            .copied()
+            .filter(|it| self.threads.contains_key(it))
+            .collect::<Vec<_>>();
+
+        let placed = ordered.iter().copied().collect::<BTreeSet<_>>();
+        ordered.extend(self.threads.keys().filter(|it| !placed.contains(it)));
+
+        ordered
+    }
+
+    /// Moves a thread up or down by `offset` rows.
+    pub fn move_thread(&mut self, thread_id: u64, offset: isize) {
+        let mut ordered = self.ordered_threads();
+        let Some(idx) = ordered.iter().position(|it| *it == thread_id) else {
+            return;
+        };
+
+        let target = idx
+            .saturating_add_signed(offset)
+            .min(ordered.len().saturating_sub(1));
+        let thread_id = ordered.remove(idx);
+        ordered.insert(target, thread_id);
+
+        self.thread_layout.order = ordered;
+    }
+
     pub fn total_spans(&self) -> usize {
-        self.threads.values().map(|v| v.spans.len()).sum()
+        self.threads.values().map(|v| v.total_spans()).sum()
+    }
+
+    pub fn spans_generation(&self) -> u64 {
+        self.spans_generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app::tracing::view_state::ViewStateContainer;
+    use crate::proto::{InitialMessage, SpanEnd, SpanEvent, SpanStart};
+    use std::collections::BTreeMap;
+
+    fn start(thread_id: u64, time: u64) -> SpanEvent {
+        SpanEvent::Start(SpanStart {
+            thread_id,
+            time,
+            label_id: 0,
+            extra_data: None,
+            args: None,
+            capture: None,
+        })
+    }
+
+    fn end(thread_id: u64, time: u64) -> SpanEvent {
+        SpanEvent::End(SpanEnd {
+            thread_id,
+            time,
+            extra_data: None,
+            return_value: None,
+        })
+    }
+
+    #[test]
+    fn drops_ends_of_threads_without_starts() {
+        let mut container = ViewStateContainer::new();
+        container.initialize(InitialMessage {
+            tsc_frequency: 1_000_000,
+            anchor_seconds: 0,
+            anchor_nanoseconds: 0,
+            anchor_timestamp: 0,
+            modules: BTreeMap::new(),
+            libraries: BTreeMap::new(),
+            symbols: Vec::new(),
+        });
+        let ViewStateContainer::Initialized(state) = &mut container else {
+            unreachable!();
+        };
+
+        // joined mid call, the end of a call which started before the capture comes first
+        state.update_span(end(7, 5));
+        state.update_span(start(7, 10));
+        state.update_span(end(7, 20));
+        state.flush_pending_spans();
+
+        let spans = &state.threads[&7].spans;
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start_time, spans[0].end_time), (10, 20));
     }
 }
@@ -0,0 +1,410 @@
+use crate::proto::{CallArgs, InitialMessage, ARG_DATA_STRING};
+use crate::sce_error::SceError;
+use crate::spans::ThreadSpan;
+use ps4libdoc::{
+    FunctionSignature, LoadedDocumentation, NamedValue, Parameter, SignatureDatabase, StructField,
+    TypeDefinition,
+};
+
+/// Most bytes the plugin captures behind an argument register.
+const MAX_ARG_DATA_LENGTH: u64 = 1024;
+
+/// Formats a span as a call using the prototype of its symbol, like
+/// `sceKernelOpen(path="/app0/data.bin", flags=O_RDONLY, mode=0) -> fd 5`. Pointers are shown
+/// as addresses unless the plugin captured the string or struct they point to.
+pub fn format_call(
+    initial_message: &InitialMessage,
+    docs: &LoadedDocumentation,
+    span: &ThreadSpan,
+) -> Option<String> {
+    let name = initial_message.documented_name(docs, span.label_id as usize)?;
+    let signature = docs.signature(name)?;
+    let error = SceError::from_span(initial_message, span);
+
+    Some(format_signature_call(
+        docs.signatures(),
+        name,
+        signature,
+        span,
+        error,
+    ))
+}
+
+fn format_signature_call(
+    signatures: &SignatureDatabase,
+    name: &str,
+    signature: &FunctionSignature,
+    span: &ThreadSpan,
+    error: Option<SceError>,
+) -> String {
+    let params = match &span.args {
+        Some(args) => format_params(signatures, &signature.params, args),
+        None => "..".to_string(),
+    };
+    let mut call = format!("{}({})", name, params);
+
+    if let (Some(returns), Some(return_value)) = (&signature.returns, span.return_value) {
+        call.push_str(" -> ");
+
+        match error {
+            Some(error) => call.push_str(&error.to_string()),
+            None => {
+                if let Some(name) = &returns.name {
+                    call.push_str(name);
+                    call.push(' ');
+                }
+
+                call.push_str(&format_value(signatures, &returns.type_name, return_value));
+            }
+        }
+    }
+
+    call
+}
+
+/// Lengths of the data to capture behind each argument register of a symbol, for
+/// `TraceCommand::SetArgCapture`. Strings are captured for `const char*` parameters and whole
+/// structs for `const` pointers to them, other pointers point to what the call writes.
+pub fn arg_data_lengths(
+    initial_message: &InitialMessage,
+    docs: &LoadedDocumentation,
+    symbol_idx: usize,
+) -> [u32; 6] {
+    initial_message
+        .documented_name(docs, symbol_idx)
+        .and_then(|name| docs.signature(name))
+        .map_or([0; 6], |signature| {
+            signature_arg_data_lengths(docs.signatures(), signature)
+        })
+}
+
+fn signature_arg_data_lengths(
+    signatures: &SignatureDatabase,
+    signature: &FunctionSignature,
+) -> [u32; 6] {
+    let mut lengths = [0; 6];
+    for (register, param) in param_registers(&signature.params) {
+        let (Some(register), Some(pointee)) = (register, const_pointee(&param.type_name)) else {
+            continue;
+        };
+
+        lengths[register] = match signatures.type_definition(pointee) {
+            _ if pointee == "char" => ARG_DATA_STRING,
+            Some(TypeDefinition::Struct { size, .. }) if *size <= MAX_ARG_DATA_LENGTH => {
+                *size as u32
+            }
+            _ => 0,
+        };
+    }
+
+    lengths
+}
+
+/// The integer argument register of each parameter. Floating point parameters are passed in
+/// vector registers and parameters past the sixth on the stack, neither of which are captured.
+fn param_registers(params: &[Parameter]) -> impl Iterator<Item = (Option<usize>, &Parameter)> {
+    let mut registers = 0..6;
+
+    params.iter().map(move |param| {
+        let register = if is_floating_point(&param.type_name) {
+            None
+        } else {
+            registers.next()
+        };
+
+        (register, param)
+    })
+}
+
+/// The type a `const` pointer points to, like `char` for `const char*`.
+fn const_pointee(type_name: &str) -> Option<&str> {
+    let pointee = type_name.trim().strip_prefix("const ")?.strip_suffix('*')?;
+
+    Some(pointee.trim())
+}
+
+/// Formats parameters from the argument registers of a call and the data they point to.
+fn format_params(signatures: &SignatureDatabase, params: &[Parameter], args: &CallArgs) -> String {
+    param_registers(params)
+        .map(|(register, param)| {
+            let value = match register {
+                Some(register) => format_arg(
+                    signatures,
+                    &param.type_name,
+                    args.registers[register],
+                    args.data.get(&register),
+                ),
+                None => "?".to_string(),
+            };
+
+            match &param.name {
+                Some(name) => format!("{}={}", name, value),
+                None => value,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_floating_point(type_name: &str) -> bool {
+    matches!(type_name.trim(), "float" | "double")
+}
+
+/// Formats an argument register, using the data it points to when the plugin captured it.
+fn format_arg(
+    signatures: &SignatureDatabase,
+    type_name: &str,
+    value: u64,
+    data: Option<&Vec<u8>>,
+) -> String {
+    let (Some(pointee), Some(data)) = (const_pointee(type_name), data) else {
+        return format_value(signatures, type_name, value);
+    };
+
+    match signatures.type_definition(pointee) {
+        _ if pointee == "char" => format!("{:?}", String::from_utf8_lossy(data)),
+        Some(TypeDefinition::Struct { fields, .. }) => format_struct(signatures, fields, data),
+        _ => format_value(signatures, type_name, value),
+    }
+}
+
+/// Formats the fields of a struct from its bytes, like `{tv_sec=1, tv_nsec=500}`.
+fn format_struct(signatures: &SignatureDatabase, fields: &[StructField], data: &[u8]) -> String {
+    let fields = fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            // enums and flags are read as ints, but may be narrower fields like `mode_t`
+            let end = fields
+                .get(idx + 1)
+                .map_or(data.len() as u64, |next| next.offset);
+            let size = type_size(signatures, &field.type_name)
+                .map(|size| size.min(end.saturating_sub(field.offset)));
+
+            let bytes = size
+                .and_then(|size| data.get(field.offset as usize..(field.offset + size) as usize));
+            let value = match bytes {
+                Some(bytes) => match signatures.type_definition(field.type_name.trim()) {
+                    Some(TypeDefinition::Struct { fields, .. }) => {
+                        format_struct(signatures, fields, bytes)
+                    }
+                    _ => {
+                        let mut value = [0u8; 8];
+                        value[..bytes.len().min(8)].copy_from_slice(&bytes[..bytes.len().min(8)]);
+                        format_value(signatures, &field.type_name, u64::from_le_bytes(value))
+                    }
+                },
+                None => "?".to_string(),
+            };
+
+            format!("{}={}", field.name, value)
+        })
+        .collect::<Vec<_>>();
+
+    format!("{{{}}}", fields.join(", "))
+}
+
+/// Size in bytes of a value of the C type `type_name`, if it is known.
+fn type_size(signatures: &SignatureDatabase, type_name: &str) -> Option<u64> {
+    let type_name = type_name.trim();
+    let type_name = type_name.strip_prefix("const ").unwrap_or(type_name).trim();
+
+    if type_name.ends_with('*') {
+        return Some(8);
+    }
+
+    match type_name {
+        "bool" | "char" | "int8_t" | "unsigned char" | "uint8_t" => Some(1),
+        "short" | "int16_t" | "unsigned short" | "uint16_t" => Some(2),
+        "int" | "int32_t" | "unsigned int" | "uint32_t" | "float" => Some(4),
+        "long" | "int64_t" | "off_t" | "ssize_t" | "unsigned long" | "uint64_t" | "size_t"
+        | "double" => Some(8),
+        _ => match signatures.type_definition(type_name)? {
+            TypeDefinition::Enum { .. } | TypeDefinition::Flags { .. } => Some(4),
+            TypeDefinition::Struct { size, .. } => Some(*size),
+        },
+    }
+}
+
+/// Formats a register holding a value of the C type `type_name`.
+pub fn format_value(signatures: &SignatureDatabase, type_name: &str, value: u64) -> String {
+    let type_name = type_name.trim();
+    let type_name = type_name.strip_prefix("const ").unwrap_or(type_name).trim();
+
+    if type_name.ends_with('*') {
+        return if value == 0 {
+            "NULL".to_string()
+        } else {
+            format!("{:#x}", value)
+        };
+    }
+
+    // the upper bits of registers holding values narrower than 64 bits are undefined
+    match type_name {
+        "bool" => (value as u8 != 0).to_string(),
+        "char" | "int8_t" => (value as i8).to_string(),
+        "unsigned char" | "uint8_t" => (value as u8).to_string(),
+        "short" | "int16_t" => (value as i16).to_string(),
+        "unsigned short" | "uint16_t" => (value as u16).to_string(),
+        "int" | "int32_t" => (value as i32).to_string(),
+        "unsigned int" | "uint32_t" => (value as u32).to_string(),
+        "long" | "int64_t" | "off_t" | "ssize_t" => (value as i64).to_string(),
+        "unsigned long" | "uint64_t" | "size_t" => value.to_string(),
+        _ => match signatures.type_definition(type_name) {
+            Some(TypeDefinition::Enum { values }) => {
+                let value = value as u32 as u64;
+
+                values
+                    .iter()
+                    .find(|it| it.value == value)
+                    .map_or_else(|| format!("{:#x}", value), |it| it.name.clone())
+            }
+            Some(TypeDefinition::Flags { values }) => format_flags(values, value as u32 as u64),
+            _ => format!("{:#x}", value),
+        },
+    }
+}
+
+fn format_flags(values: &[NamedValue], value: u64) -> String {
+    let mut names = Vec::new();
+    let mut remaining = value;
+    for flag in values {
+        if flag.value != 0 && value & flag.value == flag.value {
+            names.push(flag.name.clone());
+            remaining &= !flag.value;
+        }
+    }
+
+    if remaining != 0 {
+        names.push(format!("{:#x}", remaining));
+    }
+
+    if names.is_empty() {
+        return values
+            .iter()
+            .find(|it| it.value == 0)
+            .map_or_else(|| "0".to_string(), |it| it.name.clone());
+    }
+
+    names.join("|")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::call_format::{format_signature_call, format_value, signature_arg_data_lengths};
+    use crate::proto::{TraceEvent, ARG_DATA_STRING};
+    use crate::spans::{SpanAssembler, ThreadSpan};
+    use ps4libdoc::{NamedValue, SignatureDatabase, TypeDefinition};
+
+    #[test]
+    fn formats_values() {
+        let named = |name: &str, value: u64| NamedValue {
+            name: name.to_string(),
+            value,
+        };
+
+        let mut signatures = SignatureDatabase::default();
+        signatures.types.insert(
+            "OpenFlags".to_string(),
+            TypeDefinition::Flags {
+                values: vec![
+                    named("O_RDONLY", 0),
+                    named("O_WRONLY", 1),
+                    named("O_CREAT", 0x200),
+                ],
+            },
+        );
+        signatures.types.insert(
+            "Whence".to_string(),
+            TypeDefinition::Enum {
+                values: vec![named("SEEK_SET", 0), named("SEEK_END", 2)],
+            },
+        );
+
+        assert_eq!(format_value(&signatures, "int", 0xffff_ffff), "-1");
+        assert_eq!(format_value(&signatures, "int", 0xdead_0000_0005), "5");
+        assert_eq!(format_value(&signatures, "const char*", 0), "NULL");
+        assert_eq!(format_value(&signatures, "void*", 0x1000), "0x1000");
+        assert_eq!(format_value(&signatures, "OpenFlags", 0), "O_RDONLY");
+        assert_eq!(
+            format_value(&signatures, "OpenFlags", 0x201),
+            "O_WRONLY|O_CREAT"
+        );
+        assert_eq!(
+            format_value(&signatures, "OpenFlags", 0x1200),
+            "O_CREAT|0x1000"
+        );
+        assert_eq!(format_value(&signatures, "Whence", 2), "SEEK_END");
+        assert_eq!(format_value(&signatures, "Whence", 7), "0x7");
+        assert_eq!(format_value(&signatures, "Unknown", 7), "0x7");
+    }
+
+    /// A span decoded from the messages of a call which captured data behind its arguments,
+    /// given as register index and data.
+    fn call_span(label_id: u64, registers: [u64; 6], arg_data: &[(u64, &[u8])]) -> ThreadSpan {
+        let mut data = Vec::new();
+        let mut message = Vec::new();
+        for (register, value) in arg_data {
+            message.extend_from_slice(&register.to_le_bytes());
+            message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            message.extend_from_slice(value);
+        }
+
+        // SpanStartArgsData
+        for value in [14, 1, 10, label_id].into_iter().chain(registers) {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&(message.len() as u64).to_le_bytes());
+        data.extend_from_slice(&message);
+
+        // SpanEndReturnValue
+        for value in [5u64, 1, 20, 5] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut stream = data.as_slice();
+        let mut assembler = SpanAssembler::new();
+        for _ in 0..2 {
+            let Ok(TraceEvent::Span(event)) = TraceEvent::read(&mut stream) else {
+                panic!("expected a span event");
+            };
+            assembler.push(event);
+        }
+
+        let span = assembler.drain().next().unwrap();
+        span
+    }
+
+    #[test]
+    fn formats_captured_arguments() {
+        let signatures = SignatureDatabase::bundled().unwrap();
+
+        let open = signatures.function("sceKernelOpen").unwrap();
+        assert_eq!(
+            signature_arg_data_lengths(&signatures, open),
+            [ARG_DATA_STRING, 0, 0, 0, 0, 0]
+        );
+
+        let span = call_span(0, [0x2000, 0, 0, 0, 0, 0], &[(0, b"/app0/data.bin")]);
+        assert_eq!(
+            format_signature_call(&signatures, "sceKernelOpen", open, &span, None),
+            "sceKernelOpen(path=\"/app0/data.bin\", flags=O_RDONLY, mode=0) -> fd 5"
+        );
+
+        let nanosleep = signatures.function("sceKernelNanosleep").unwrap();
+        assert_eq!(
+            signature_arg_data_lengths(&signatures, nanosleep),
+            [16, 0, 0, 0, 0, 0]
+        );
+
+        let timespec = [1u64, 500]
+            .iter()
+            .flat_map(|it| it.to_le_bytes())
+            .collect::<Vec<_>>();
+        let span = call_span(0, [0x2000, 0x3000, 0, 0, 0, 0], &[(0, &timespec)]);
+        assert_eq!(
+            format_signature_call(&signatures, "sceKernelNanosleep", nanosleep, &span, None),
+            "sceKernelNanosleep(rqtp={tv_sec=1, tv_nsec=500}, rmtp=0x3000) -> 5"
+        );
+    }
+}
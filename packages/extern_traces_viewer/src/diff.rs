@@ -0,0 +1,282 @@
+use crate::proto::InitialMessage;
+use crate::stats::{DurationSummary, TraceStats};
+use crate::trace_file::TraceFile;
+use ps4libdoc::LoadedDocumentation;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+/// Identifies a symbol across traces. Label ids are positions in the symbol table sent by one
+/// run, so the same function can have a different label id in another run.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SymbolKey {
+    pub module: String,
+    pub library: String,
+    pub nid: String,
+}
+
+impl SymbolKey {
+    pub fn of(initial_message: &InitialMessage, symbol_idx: usize) -> Option<SymbolKey> {
+        let symbol = initial_message.symbols.get(symbol_idx)?;
+        let module = initial_message.modules.get(&(symbol.module_id as _))?;
+        let library = initial_message.libraries.get(&(symbol.library_id as _))?;
+
+        Some(SymbolKey {
+            module: module.name.clone(),
+            library: library.name.clone(),
+            nid: symbol.name.clone(),
+        })
+    }
+}
+
+/// Per symbol and per frame durations of one trace.
+pub struct TraceSummary {
+    pub initial_message: InitialMessage,
+    pub symbols: BTreeMap<SymbolKey, SymbolSummary>,
+    pub frames: Option<DurationSummary>,
+}
+
+pub struct SymbolSummary {
+    pub symbol_idx: usize,
+    pub durations: DurationSummary,
+}
+
+impl TraceSummary {
+    pub fn read(path: &Path) -> io::Result<TraceSummary> {
+        let (initial_message, stats) = TraceStats::read(TraceFile::open(path)?.stream()?)?;
+
+        let symbols = stats
+            .summaries()
+            .into_iter()
+            .filter_map(|(symbol_idx, durations)| {
+                let symbol_idx = symbol_idx as usize;
+
+                Some((
+                    SymbolKey::of(&initial_message, symbol_idx)?,
+                    SymbolSummary {
+                        symbol_idx,
+                        durations,
+                    },
+                ))
+            })
+            .collect();
+
+        Ok(TraceSummary {
+            frames: stats.frame_summary(),
+            initial_message,
+            symbols,
+        })
+    }
+
+    pub fn seconds(&self, cycles: u64) -> f64 {
+        cycles as f64 / self.initial_message.tsc_frequency as f64
+    }
+}
+
+/// Comparison of a base trace with a changed one.
+pub struct TraceDiff {
+    pub base: TraceSummary,
+    pub changed: TraceSummary,
+
+    /// Symbols called in either trace, with the largest change in total duration first.
+    pub symbols: Vec<SymbolKey>,
+}
+
+impl TraceDiff {
+    pub fn new(base: TraceSummary, changed: TraceSummary) -> TraceDiff {
+        let mut symbols = base
+            .symbols
+            .keys()
+            .chain(changed.symbols.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        symbols.sort();
+        symbols.dedup();
+
+        symbols.sort_by(|a, b| {
+            let a = total_delta(&base, &changed, a).abs();
+            let b = total_delta(&base, &changed, b).abs();
+
+            b.total_cmp(&a)
+        });
+
+        TraceDiff {
+            base,
+            changed,
+            symbols,
+        }
+    }
+
+    pub fn read(base: &Path, changed: &Path) -> io::Result<TraceDiff> {
+        Ok(TraceDiff::new(
+            TraceSummary::read(base)?,
+            TraceSummary::read(changed)?,
+        ))
+    }
+
+    /// Total seconds spent in a symbol by the changed trace minus the base trace.
+    pub fn total_delta(&self, key: &SymbolKey) -> f64 {
+        total_delta(&self.base, &self.changed, key)
+    }
+
+    /// Name of a symbol from whichever trace has it.
+    pub fn symbol_name(&self, docs: &LoadedDocumentation, key: &SymbolKey) -> String {
+        [&self.base, &self.changed]
+            .into_iter()
+            .find_map(|trace| {
+                let symbol = trace.symbols.get(key)?;
+
+                trace.initial_message.symbol_name(docs, symbol.symbol_idx)
+            })
+            .unwrap_or_else(|| format!("{}::{}::{}", key.module, key.library, key.nid))
+    }
+}
+
+fn total_delta(base: &TraceSummary, changed: &TraceSummary, key: &SymbolKey) -> f64 {
+    let total = |trace: &TraceSummary| {
+        trace
+            .symbols
+            .get(key)
+            .map_or(0., |it| trace.seconds(it.durations.total))
+    };
+
+    total(changed) - total(base)
+}
+
+/// Prints how per symbol call counts and durations changed between two trace files as tab
+/// separated values. Frame times are printed first as a row named `<frames>`.
+pub fn print_diff(base: &Path, changed: &Path, limit: Option<usize>) -> anyhow::Result<()> {
+    let docs = LoadedDocumentation::bundled()?;
+    let diff = TraceDiff::read(base, changed)?;
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "symbol\tbase_count\tchanged_count\tbase_total_us\tchanged_total_us\tdelta_total_us\tbase_mean_us\tchanged_mean_us"
+    )?;
+
+    let mut write_row =
+        |name: &str, base: Option<&DurationSummary>, changed: Option<&DurationSummary>| {
+            let count = |summary: Option<&DurationSummary>| summary.map_or(0, |it| it.count);
+            let micros = |trace: &TraceSummary,
+                          summary: Option<&DurationSummary>,
+                          value: fn(&DurationSummary) -> u64| {
+                summary.map_or(0., |it| trace.seconds(value(it)) * 1_000_000.)
+            };
+
+            let base_total = micros(&diff.base, base, |it| it.total);
+            let changed_total = micros(&diff.changed, changed, |it| it.total);
+
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}",
+                name,
+                count(base),
+                count(changed),
+                base_total,
+                changed_total,
+                changed_total - base_total,
+                micros(&diff.base, base, |it| it.mean),
+                micros(&diff.changed, changed, |it| it.mean),
+            )
+        };
+
+    if diff.base.frames.is_some() || diff.changed.frames.is_some() {
+        write_row(
+            "<frames>",
+            diff.base.frames.as_ref(),
+            diff.changed.frames.as_ref(),
+        )?;
+    }
+
+    for key in diff.symbols.iter().take(limit.unwrap_or(usize::MAX)) {
+        write_row(
+            &diff.symbol_name(&docs, key),
+            diff.base.symbols.get(key).map(|it| &it.durations),
+            diff.changed.symbols.get(key).map(|it| &it.durations),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{SymbolKey, SymbolSummary, TraceDiff, TraceSummary};
+    use crate::proto::{InitialMessage, LibraryInfo, ModuleInfo, SymbolInfo};
+    use crate::stats::DurationSummary;
+    use std::collections::BTreeMap;
+
+    fn trace(names: &[&str], totals: &[(usize, u64)]) -> TraceSummary {
+        let initial_message = InitialMessage {
+            tsc_frequency: 1,
+            anchor_seconds: 0,
+            anchor_nanoseconds: 0,
+            anchor_timestamp: 0,
+            modules: BTreeMap::from([(
+                0,
+                ModuleInfo {
+                    id: 0,
+                    version_major: 1,
+                    version_minor: 1,
+                    name: "libkernel".to_string(),
+                },
+            )]),
+            libraries: BTreeMap::from([(
+                0,
+                LibraryInfo {
+                    id: 0,
+                    version: 1,
+                    name: "libkernel".to_string(),
+                },
+            )]),
+            symbols: names
+                .iter()
+                .map(|name| SymbolInfo {
+                    name: name.to_string(),
+                    library_id: 0,
+                    module_id: 0,
+                })
+                .collect(),
+        };
+
+        let symbols = totals
+            .iter()
+            .map(|(symbol_idx, total)| {
+                (
+                    SymbolKey::of(&initial_message, *symbol_idx).unwrap(),
+                    SymbolSummary {
+                        symbol_idx: *symbol_idx,
+                        durations: DurationSummary::from_sorted(&[*total]).unwrap(),
+                    },
+                )
+            })
+            .collect();
+
+        TraceSummary {
+            initial_message,
+            symbols,
+            frames: None,
+        }
+    }
+
+    #[test]
+    fn matches_symbols_by_nid() {
+        // the same symbols with different label ids
+        let base = trace(&["aaa", "bbb", "ccc"], &[(0, 10), (1, 20), (2, 5)]);
+        let changed = trace(&["bbb", "aaa", "ddd"], &[(0, 20), (1, 40), (2, 1)]);
+
+        let diff = TraceDiff::new(base, changed);
+        let nids = diff
+            .symbols
+            .iter()
+            .map(|it| it.nid.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(nids, vec!["aaa", "ccc", "ddd", "bbb"]);
+        assert_eq!(diff.total_delta(&diff.symbols[0]), 30.);
+        assert_eq!(diff.total_delta(&diff.symbols[1]), -5.);
+        assert_eq!(diff.total_delta(&diff.symbols[3]), 0.);
+    }
+}
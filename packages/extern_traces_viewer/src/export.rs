@@ -0,0 +1,263 @@
+use crate::proto::{InitialMessage, TraceEvent, TraceEventReader};
+use crate::spans::{SpanAssembler, ThreadSpan};
+use crate::threads::ThreadNames;
+use crate::trace_file::TraceFile;
+use ps4libdoc::LoadedDocumentation;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Writes the trace file at `path` as Chrome Trace Event JSON to `output`. The result can be
+/// opened in Perfetto or chrome://tracing.
+pub fn export_chrome_trace(path: &Path, output: &Path) -> anyhow::Result<()> {
+    let docs = LoadedDocumentation::bundled()?;
+    let stream = TraceFile::open(path)?.stream()?;
+    let mut out = BufWriter::new(File::create(output)?);
+
+    write_chrome_trace(stream, &docs, &mut out)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Converts a trace stream into a Chrome Trace Event JSON document with a track per thread.
+/// Timestamps are wall clock time derived from the anchor of the initial message.
+pub fn write_chrome_trace(
+    mut stream: impl Read,
+    docs: &LoadedDocumentation,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let initial_message = InitialMessage::read(&mut stream)?;
+    if initial_message.tsc_frequency == 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "trace has no tsc frequency to convert times with",
+        ));
+    }
+
+    let mut thread_names = ThreadNames::new(&initial_message);
+    let mut writer = ChromeTraceWriter {
+        out,
+        initial_message: &initial_message,
+        docs,
+        names: BTreeMap::new(),
+    };
+    let mut assemblers = BTreeMap::<u64, SpanAssembler>::new();
+
+    write!(
+        writer.out,
+        "{{\"displayTimeUnit\":\"ns\",\"traceEvents\":[\n\
+         {{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":1,\"args\":{{\"name\":\"extern_traces\"}}}}"
+    )?;
+
+    let mut reader = TraceEventReader::new(stream);
+    loop {
+        let event = match reader.read() {
+            Ok(TraceEvent::Span(event)) => event,
+            Ok(..) => continue,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+
+        thread_names.observe(&event);
+
+        let thread_id = event.thread_id();
+        let assembler = assemblers
+            .entry(thread_id)
+            .or_insert_with(SpanAssembler::new);
+        if let Some(span) = assembler.push(event) {
+            writer.write_span(thread_id, &span)?;
+        }
+    }
+
+    for (thread_id, assembler) in &mut assemblers {
+        for span in assembler.drain() {
+            writer.write_span(*thread_id, &span)?;
+        }
+    }
+
+    // threads are often named after their first spans, so tracks are named once all are read
+    for thread_id in assemblers.keys() {
+        let name = thread_names.get(*thread_id).and_then(|it| it.name.as_ref());
+        writer.write_thread_name(*thread_id, name)?;
+    }
+
+    writeln!(writer.out, "\n]}}")?;
+
+    Ok(())
+}
+
+struct ChromeTraceWriter<'a, W> {
+    out: W,
+    initial_message: &'a InitialMessage,
+    docs: &'a LoadedDocumentation,
+
+    /// Escaped names of symbols which were already resolved.
+    names: BTreeMap<u64, String>,
+}
+
+impl<'a, W: Write> ChromeTraceWriter<'a, W> {
+    fn write_thread_name(&mut self, thread_id: u64, name: Option<&String>) -> io::Result<()> {
+        let name = match name {
+            Some(name) => escape_json(name),
+            None => format!("thread {}", thread_id),
+        };
+
+        write!(
+            self.out,
+            ",\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+            thread_id, name
+        )
+    }
+
+    fn write_span(&mut self, thread_id: u64, span: &ThreadSpan) -> io::Result<()> {
+        let name = self.names.entry(span.label_id).or_insert_with(|| {
+            let name = self
+                .initial_message
+                .symbol_name(self.docs, span.label_id as usize)
+                .unwrap_or_else(|| format!("unknown symbol {}", span.label_id));
+
+            escape_json(&name)
+        });
+
+        let start = wall_clock_nanos(self.initial_message, span.start_time);
+        let end = wall_clock_nanos(self.initial_message, span.end_time);
+
+        write!(
+            self.out,
+            ",\n{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":{}}}",
+            name,
+            thread_id,
+            format_micros(start),
+            format_micros(end - start),
+        )
+    }
+}
+
+/// Nanoseconds since the unix epoch at which the tsc read `time`.
+fn wall_clock_nanos(initial_message: &InitialMessage, time: u64) -> i128 {
+    let anchor = initial_message.anchor_seconds as i128 * 1_000_000_000
+        + initial_message.anchor_nanoseconds as i128;
+    let cycles = time as i128 - initial_message.anchor_timestamp as i128;
+
+    anchor + cycles * 1_000_000_000 / initial_message.tsc_frequency as i128
+}
+
+/// Formats nanoseconds as microseconds without losing precision to floats.
+fn format_micros(nanos: i128) -> String {
+    let sign = if nanos < 0 { "-" } else { "" };
+    let nanos = nanos.unsigned_abs();
+
+    format!("{}{}.{:03}", sign, nanos / 1000, nanos % 1000)
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::export::{escape_json, format_micros, write_chrome_trace};
+    use ps4libdoc::LoadedDocumentation;
+    use std::io::ErrorKind;
+
+    /// Initial message without modules or libraries, whose symbols are looked up by name.
+    fn initial_message(tsc_frequency: u64, symbols: &[&str]) -> Vec<u8> {
+        let mut stream = Vec::new();
+
+        // tsc_frequency, anchor_seconds, anchor_nanoseconds, anchor_timestamp
+        for value in [tsc_frequency, 0, 0, 0] {
+            stream.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // module and library counts
+        for _ in 0..2 {
+            stream.extend_from_slice(&0u32.to_le_bytes());
+        }
+
+        // symbols, with their library and module ids
+        stream.extend_from_slice(&(symbols.len() as u32).to_le_bytes());
+        for symbol in symbols {
+            stream.extend_from_slice(&(symbol.len() as u32).to_le_bytes());
+            stream.extend_from_slice(symbol.as_bytes());
+            stream.extend_from_slice(&[0, 0]);
+        }
+
+        stream
+    }
+
+    fn export(stream: &[u8]) -> String {
+        let docs = LoadedDocumentation::bundled().unwrap();
+        let mut out = Vec::new();
+        write_chrome_trace(stream, &docs, &mut out).unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn formats_json_values() {
+        assert_eq!(format_micros(1_234_567), "1234.567");
+        assert_eq!(format_micros(-1_500), "-1.500");
+        assert_eq!(escape_json("a\"b\\c\n"), "a\\\"b\\\\c\\u000a");
+    }
+
+    #[test]
+    fn exports_compact_spans() {
+        let mut stream = initial_message(1_000_000, &[]);
+
+        // ThreadContext of thread 7 and a TimeSync to 10
+        for value in [12u64, 7] {
+            stream.extend_from_slice(&value.to_le_bytes());
+        }
+        stream.push(0x80);
+        stream.extend_from_slice(&10u64.to_le_bytes());
+
+        // start at 10 and end at 20
+        stream.extend_from_slice(&[0x81, 0, 3, 0x82, 10, 0]);
+
+        assert!(export(&stream).contains(
+            "{\"name\":\"unknown symbol 3\",\"ph\":\"X\",\"pid\":1,\"tid\":7,\"ts\":10.000,\"dur\":10.000}"
+        ));
+    }
+
+    #[test]
+    fn names_renamed_threads() {
+        // scePthreadRename
+        let mut stream = initial_message(1_000_000, &["GBUY7ywdULE"]);
+
+        // thread 7 renames itself, then thread 8 makes an untraced call
+        let name = b"render \"main\"";
+        for value in [3, 7, 10, 0, 8 + name.len() as u64, 7] {
+            stream.extend_from_slice(&value.to_le_bytes());
+        }
+        stream.extend_from_slice(name);
+        for value in [1u64, 7, 20, 0, 8, 30, 0, 1, 8, 40] {
+            stream.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let out = export(&stream);
+        assert!(out.contains("\"tid\":7,\"args\":{\"name\":\"render \\\"main\\\"\"}"));
+        assert!(out.contains("\"tid\":8,\"args\":{\"name\":\"thread 8\"}"));
+    }
+
+    #[test]
+    fn rejects_traces_without_a_frequency() {
+        let docs = LoadedDocumentation::bundled().unwrap();
+        let stream = initial_message(0, &[]);
+        let result = write_chrome_trace(stream.as_slice(), &docs, &mut Vec::new());
+
+        assert!(matches!(result, Err(err) if err.kind() == ErrorKind::InvalidData));
+    }
+}
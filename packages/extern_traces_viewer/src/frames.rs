@@ -0,0 +1,103 @@
+use crate::proto::InitialMessage;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+/// Encoded names of the calls which present a frame. The start of each call marks the end of a
+/// frame.
+const FLIP_NIDS: [&str; 3] = [
+    // sceGnmSubmitAndFlipCommandBuffers
+    "xbxNatawohc",
+    // sceGnmSubmitAndFlipCommandBuffersForWorkload
+    "Ga6r7H6Y0RI",
+    // sceVideoOutSubmitFlip
+    "U46NwOiJpys",
+];
+
+/// Times at which frames were presented.
+pub struct FrameMarkers {
+    flip_labels: BTreeSet<u64>,
+
+    /// Ordered start times of flip calls.
+    flips: Vec<u64>,
+}
+
+impl FrameMarkers {
+    pub fn new(initial_message: &InitialMessage) -> FrameMarkers {
+        FrameMarkers {
+            flip_labels: flip_labels(initial_message),
+            flips: Vec::new(),
+        }
+    }
+
+    pub fn is_flip(&self, label_id: u64) -> bool {
+        self.flip_labels.contains(&label_id)
+    }
+
+    pub fn add_flip(&mut self, time: u64) {
+        // flips of different threads can arrive slightly out of order
+        let idx = self.flips.partition_point(|it| *it <= time);
+        self.flips.insert(idx, time);
+    }
+
+    pub fn extend(&mut self, flips: impl IntoIterator<Item = u64>) {
+        self.flips.extend(flips);
+        self.flips.sort_unstable();
+    }
+
+    pub fn len(&self) -> usize {
+        self.flips.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Time range of the frame at `idx`, from one flip to the next.
+    pub fn frame(&self, idx: usize) -> Option<Range<u64>> {
+        Some(*self.flips.get(idx)?..*self.flips.get(idx + 1)?)
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.flips.windows(2).map(|it| it[0]..it[1])
+    }
+}
+
+/// Labels of the symbols which present a frame.
+fn flip_labels(initial_message: &InitialMessage) -> BTreeSet<u64> {
+    initial_message
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| FLIP_NIDS.contains(&symbol.name.as_str()))
+        .map(|(symbol_idx, _)| symbol_idx as u64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frames::FrameMarkers;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn orders_frames() {
+        let mut markers = FrameMarkers {
+            flip_labels: BTreeSet::from([3]),
+            flips: Vec::new(),
+        };
+        assert!(markers.is_flip(3));
+        assert!(markers.is_empty());
+
+        markers.add_flip(100);
+        markers.add_flip(300);
+        markers.add_flip(200);
+        markers.extend([50]);
+
+        assert_eq!(markers.len(), 3);
+        assert_eq!(
+            markers.frames().collect::<Vec<_>>(),
+            vec![50..100, 100..200, 200..300]
+        );
+        assert_eq!(markers.frame(1), Some(100..200));
+        assert_eq!(markers.frame(3), None);
+    }
+}
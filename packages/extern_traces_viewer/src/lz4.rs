@@ -0,0 +1,93 @@
+use std::io;
+use std::io::ErrorKind;
+
+/// Decompresses an LZ4 block, as sent by plugins with compression enabled.
+pub fn decompress_block(input: &[u8], uncompressed_length: usize) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(ErrorKind::InvalidData, "invalid compressed block");
+
+    // a byte of input expands to at most 255 bytes, don't trust the length beyond that
+    let mut output = Vec::with_capacity(uncompressed_length.min(input.len().saturating_mul(255)));
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+
+        let literal_length =
+            read_length(input, &mut pos, (token >> 4) as usize).ok_or_else(invalid)?;
+        let literals = input.get(pos..pos + literal_length).ok_or_else(invalid)?;
+        output.extend_from_slice(literals);
+        pos += literal_length;
+
+        // the last sequence only has literals
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = input.get(pos..pos + 2).ok_or_else(invalid)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+
+        let match_length =
+            read_length(input, &mut pos, (token & 0xf) as usize).ok_or_else(invalid)? + 4;
+        if offset == 0 || offset > output.len() {
+            return Err(invalid());
+        }
+
+        // matches may overlap the bytes they produce, so they're copied a byte at a time
+        let start = output.len() - offset;
+        for idx in 0..match_length {
+            output.push(output[start + idx]);
+        }
+
+        if output.len() > uncompressed_length {
+            return Err(invalid());
+        }
+    }
+
+    if output.len() != uncompressed_length {
+        return Err(invalid());
+    }
+
+    Ok(output)
+}
+
+/// Length from the bits of a token, followed by extra bytes when the bits are all set.
+fn read_length(input: &[u8], pos: &mut usize, token_length: usize) -> Option<usize> {
+    let mut length = token_length;
+    if token_length == 15 {
+        loop {
+            let byte = *input.get(*pos)?;
+            *pos += 1;
+            length += byte as usize;
+
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+
+    Some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lz4::decompress_block;
+
+    #[test]
+    fn decompresses_overlapping_matches() {
+        let block = [
+            // "abc" then a 16 byte match one back
+            0x3c, b'a', b'b', b'c', 0x01, 0x00, // "xyz", ending the block
+            0x30, b'x', b'y', b'z',
+        ];
+
+        let mut expected = b"abc".to_vec();
+        expected.extend_from_slice(&[b'c'; 16]);
+        expected.extend_from_slice(b"xyz");
+
+        assert_eq!(decompress_block(&block, 22).unwrap(), expected);
+        assert!(decompress_block(&block, 21).is_err());
+        assert!(decompress_block(&block[..5], 22).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::app::tracing::recording::Recording;
 use crate::app::tracing::TracingScene;
 use crate::app::Scene;
 use clap::{Parser, Subcommand};
@@ -5,8 +6,20 @@ use std::net::SocketAddr;
 use std::path::PathBuf;
 
 mod app;
+mod call_format;
+mod diff;
+mod export;
+mod frames;
 mod gfx_debug;
+mod lz4;
 mod proto;
+mod sampling;
+mod sce_error;
+mod spans;
+mod stats;
+mod sync;
+mod threads;
+mod trace_file;
 
 #[derive(Debug, Parser)]
 struct Cli {
@@ -20,12 +33,72 @@ enum Commands {
     LoadFile { path: PathBuf },
 
     #[command(name = "listen")]
-    ListenNetwork { addr: SocketAddr },
+    ListenNetwork {
+        addr: SocketAddr,
+
+        /// write received traces to this file instead of a temporary file
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// print per symbol duration statistics of a trace file as tab separated values
+    #[command(name = "stats")]
+    Stats {
+        path: PathBuf,
+
+        /// also print a row for each thread a symbol was called on
+        #[arg(long)]
+        per_thread: bool,
+
+        /// only print the symbols with the highest total duration
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// print how per symbol call counts and durations changed between two trace files as tab
+    /// separated values
+    #[command(name = "diff")]
+    Diff {
+        base: PathBuf,
+        changed: PathBuf,
+
+        /// only print the symbols whose total duration changed the most
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// convert a trace file to chrome trace event json, viewable in perfetto
+    #[command(name = "export")]
+    Export { path: PathBuf, output: PathBuf },
 }
 
 fn main() -> Result<(), eframe::Error> {
     let args = Cli::parse();
 
+    let headless_result = match &args.command {
+        Some(Commands::Stats {
+            path,
+            per_thread,
+            limit,
+        }) => Some(stats::print_stats(path, *per_thread, *limit)),
+        Some(Commands::Diff {
+            base,
+            changed,
+            limit,
+        }) => Some(diff::print_diff(base, changed, *limit)),
+        Some(Commands::Export { path, output }) => Some(export::export_chrome_trace(path, output)),
+        _ => None,
+    };
+
+    if let Some(result) = headless_result {
+        if let Err(err) = result {
+            eprintln!("error: {:#}", err);
+            std::process::exit(1);
+        }
+
+        return Ok(());
+    }
+
     let options = eframe::NativeOptions {
         ..Default::default()
     };
@@ -41,8 +114,13 @@ fn main() -> Result<(), eframe::Error> {
                 Some(Commands::LoadFile { path }) => {
                     Scene::Tracing(TracingScene::from_file_path(ctx, path))
                 }
-                Some(Commands::ListenNetwork { addr }) => {
-                    Scene::Tracing(TracingScene::from_network(ctx, addr))
+                Some(Commands::ListenNetwork { addr, output }) => {
+                    let recording = output.map_or_else(Recording::temporary, Recording::at);
+
+                    Scene::Tracing(TracingScene::from_network(ctx, addr, recording))
+                }
+                Some(Commands::Stats { .. } | Commands::Diff { .. } | Commands::Export { .. }) => {
+                    unreachable!()
                 }
             };
 
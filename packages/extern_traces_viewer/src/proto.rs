@@ -1,17 +1,381 @@
+use crate::lz4::decompress_block;
+use ps4libdoc::LoadedDocumentation;
 use std::collections::BTreeMap;
 use std::io;
-use std::io::{ErrorKind, Read};
+use std::io::{Cursor, ErrorKind, Read};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[repr(u8)]
+/// Version of the command protocol spoken by this viewer.
+pub const COMMAND_PROTOCOL_VERSION: u64 = 4;
+
+/// First byte of a framed command. Plugins which don't announce a command protocol only
+/// understand a lone `0x0`, capturing the next submit, and stop listening on any other byte.
+const COMMAND_FRAME_MARKER: u8 = 0xff;
+
+/// Length in `SetArgCapture` of data which is a nul terminated string rather than a fixed
+/// number of bytes.
+pub const ARG_DATA_STRING: u32 = u32::MAX;
+
+/// Commands sent from the viewer to the plugin.
+#[derive(Debug, Clone)]
 pub enum TraceCommand {
-    CaptureFrame = 0x0,
+    /// Captures the command buffers of the next `count` submits.
+    CaptureSubmits { count: u32 },
+
+    /// Sends the argument registers with each call of a symbol, and for each register with a
+    /// non zero length in `arg_data` the data it points to. Plugins before protocol 4 only send
+    /// the registers.
+    SetArgCapture {
+        label_id: u64,
+        enabled: bool,
+        arg_data: [u32; 6],
+    },
+
+    /// Stops writing spans started while paused.
+    SetPaused { paused: bool },
+
+    /// Replaces the sampling rates of every symbol.
+    SetSampling(SamplingFilter),
+
+    /// Captures the next `count` submits once the trigger fires, replacing any capture still
+    /// waiting for its trigger.
+    ArmCapture { count: u32, trigger: CaptureTrigger },
+}
+
+impl TraceCommand {
+    fn id(&self) -> u16 {
+        match self {
+            TraceCommand::CaptureSubmits { .. } => 0,
+            TraceCommand::SetArgCapture { .. } => 1,
+            TraceCommand::SetPaused { .. } => 2,
+            TraceCommand::SetSampling(..) => 3,
+            TraceCommand::ArmCapture { .. } => 4,
+        }
+    }
+
+    /// Command protocol version which introduced the command.
+    pub fn protocol_version(&self) -> u64 {
+        match self {
+            TraceCommand::SetSampling(..) => 2,
+            TraceCommand::ArmCapture { .. } => 3,
+            _ => 1,
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            TraceCommand::CaptureSubmits { count } => count.to_le_bytes().to_vec(),
+            TraceCommand::SetArgCapture {
+                label_id,
+                enabled,
+                arg_data,
+            } => {
+                let mut payload = label_id.to_le_bytes().to_vec();
+                payload.push(*enabled as u8);
+                for length in arg_data {
+                    payload.extend_from_slice(&length.to_le_bytes());
+                }
+
+                payload
+            }
+            TraceCommand::SetPaused { paused } => vec![*paused as u8],
+            TraceCommand::SetSampling(filter) => {
+                let mut payload = filter.default_rate.to_le_bytes().to_vec();
+                payload.extend_from_slice(&(filter.rates.len() as u32).to_le_bytes());
+                for (label_id, rate) in &filter.rates {
+                    payload.extend_from_slice(&label_id.to_le_bytes());
+                    payload.extend_from_slice(&rate.to_le_bytes());
+                }
+
+                payload
+            }
+            TraceCommand::ArmCapture { count, trigger } => {
+                let mut payload = count.to_le_bytes().to_vec();
+                payload.extend_from_slice(&trigger.kind().to_le_bytes());
+                payload.extend_from_slice(&trigger.value().to_le_bytes());
+                payload
+            }
+        }
+    }
+
+    /// A framed command. The frame is the marker byte, the length of the rest of the frame, the
+    /// sequence number the plugin answers with, the command id and its payload.
+    pub fn encode(&self, sequence: u32) -> Vec<u8> {
+        let payload = self.payload();
+
+        let mut frame = vec![COMMAND_FRAME_MARKER];
+        frame.extend_from_slice(&(4 + 2 + payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&sequence.to_le_bytes());
+        frame.extend_from_slice(&self.id().to_le_bytes());
+        frame.extend_from_slice(&payload);
+
+        frame
+    }
+
+    /// The command for plugins without a command protocol, if they understand it.
+    pub fn encode_legacy(&self) -> Option<Vec<u8>> {
+        match self {
+            TraceCommand::CaptureSubmits { count: 1 } => Some(vec![0x0]),
+            _ => None,
+        }
+    }
+}
+
+/// What made the plugin capture a submit.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CaptureTrigger {
+    /// Captured as soon as the request arrived.
+    Immediate,
+
+    /// Captured once a call of the symbol returned.
+    AfterSymbol { label_id: u64 },
+
+    /// Captured once a frame, measured between flips, took longer than `cycles` tsc cycles.
+    AfterLongFrame { cycles: u64 },
+}
+
+impl CaptureTrigger {
+    fn kind(&self) -> u32 {
+        match self {
+            CaptureTrigger::Immediate => 0,
+            CaptureTrigger::AfterSymbol { .. } => 1,
+            CaptureTrigger::AfterLongFrame { .. } => 2,
+        }
+    }
+
+    fn value(&self) -> u64 {
+        match self {
+            CaptureTrigger::Immediate => 0,
+            CaptureTrigger::AfterSymbol { label_id } => *label_id,
+            CaptureTrigger::AfterLongFrame { cycles } => *cycles,
+        }
+    }
+
+    fn from_raw(kind: u64, value: u64) -> Option<CaptureTrigger> {
+        match kind {
+            0 => Some(CaptureTrigger::Immediate),
+            1 => Some(CaptureTrigger::AfterSymbol { label_id: value }),
+            2 => Some(CaptureTrigger::AfterLongFrame { cycles: value }),
+            _ => None,
+        }
+    }
+}
+
+/// The capture request a submit was captured for.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SubmitCapture {
+    /// Sequence of the command which asked for the capture.
+    pub request: u32,
+    pub trigger: CaptureTrigger,
 }
 
 pub enum TraceEvent {
     Start(InitialMessage),
     Span(SpanEvent),
     CountersUpdate(CountersUpdate),
+
+    /// Version of the command protocol the plugin understands, sent once before any span.
+    CommandProtocol(u64),
+    CommandResponse(CommandResponse),
+
+    /// Sampling rates applied by the plugin.
+    SamplingUpdate(SamplingUpdate),
+
+    /// Messages the plugin compressed together, already decompressed. Always holds whole
+    /// messages, `TraceEventReader` reads them in place of the block.
+    Compressed(Vec<u8>),
+
+    /// Thread of the compact spans which follow, sent before the buffer of each thread.
+    ThreadContext(u64),
+
+    /// A span event in the compact encoding. `CompactSpanDecoder` turns them into `Span`s.
+    CompactSpan(CompactSpanEvent),
+
+    /// A traced thread exited, its last message.
+    ThreadExit(ThreadExit),
+}
+
+#[derive(Debug, Clone)]
+pub struct ThreadExit {
+    pub thread_id: u64,
+    pub time: u64,
+}
+
+/// Most bytes of argument data sent with a call, the plugin captures at most 1 KiB behind each
+/// of the six argument registers.
+const MAX_ARG_DATA_LENGTH: u64 = 16 * 1024;
+
+/// Largest block of compressed messages the plugin sends, before and after compressing. Lengths
+/// are checked against it before anything is allocated for the block.
+const MAX_COMPRESSED_BLOCK_LENGTH: u64 = 64 * 1024;
+
+/// First byte of compact messages. Tags of full messages are little endian and small, so their
+/// first byte never has the high bit set.
+const COMPACT_TAG_BASE: u8 = 0x80;
+
+/// Span events with a one byte tag and varints, without their thread and with times relative to
+/// the previous compact span of the thread.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CompactSpanEvent {
+    /// Time the next span of the thread is relative to. Sent before the first span of a thread
+    /// and after spans were dropped.
+    TimeSync {
+        time: u64,
+    },
+    Start {
+        time_delta: u64,
+        label_id: u64,
+    },
+    End {
+        time_delta: u64,
+        return_value: u64,
+    },
+}
+
+impl CompactSpanEvent {
+    fn read(tag: u8, stream: &mut impl Read) -> io::Result<CompactSpanEvent> {
+        match tag {
+            0x80 => {
+                let mut data = [0u8; 8];
+                stream.read_exact(&mut data)?;
+
+                Ok(CompactSpanEvent::TimeSync {
+                    time: u64::from_le_bytes(data),
+                })
+            }
+            0x81 => Ok(CompactSpanEvent::Start {
+                time_delta: read_varint(stream)?,
+                label_id: read_varint(stream)?,
+            }),
+            0x82 => Ok(CompactSpanEvent::End {
+                time_delta: read_varint(stream)?,
+                return_value: read_varint(stream)?,
+            }),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "unknown compact message tag {:#x}, the trace may be from an incompatible plugin build",
+                    tag
+                ),
+            )),
+        }
+    }
+}
+
+/// Turns compact spans back into span events, following the thread they were sent for and the
+/// time of the last span of each thread.
+#[derive(Default)]
+pub struct CompactSpanDecoder {
+    thread_id: Option<u64>,
+    times: BTreeMap<u64, u64>,
+}
+
+impl CompactSpanDecoder {
+    /// The event with compact spans decoded, or `None` for messages which only carry what
+    /// decoding needs.
+    pub fn decode(&mut self, event: TraceEvent) -> io::Result<Option<TraceEvent>> {
+        let compact = match event {
+            TraceEvent::ThreadContext(thread_id) => {
+                self.thread_id = Some(thread_id);
+                return Ok(None);
+            }
+            TraceEvent::CompactSpan(compact) => compact,
+            event => return Ok(Some(event)),
+        };
+
+        let thread_id = self.thread_id.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                "compact span sent before its thread",
+            )
+        })?;
+
+        let span = match compact {
+            CompactSpanEvent::TimeSync { time } => {
+                self.times.insert(thread_id, time);
+                return Ok(None);
+            }
+            CompactSpanEvent::Start {
+                time_delta,
+                label_id,
+            } => SpanEvent::Start(SpanStart {
+                thread_id,
+                time: self.advance(thread_id, time_delta)?,
+                label_id,
+                extra_data: None,
+                args: None,
+                capture: None,
+            }),
+            CompactSpanEvent::End {
+                time_delta,
+                return_value,
+            } => SpanEvent::End(SpanEnd {
+                thread_id,
+                time: self.advance(thread_id, time_delta)?,
+                extra_data: None,
+                return_value: Some(return_value),
+            }),
+        };
+
+        Ok(Some(TraceEvent::Span(span)))
+    }
+
+    fn advance(&mut self, thread_id: u64, time_delta: u64) -> io::Result<u64> {
+        let time = self.times.get_mut(&thread_id).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                "compact span sent before the time it's relative to",
+            )
+        })?;
+        *time = time
+            .checked_add(time_delta)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "compact span time overflows"))?;
+
+        Ok(*time)
+    }
+}
+
+/// How often calls of each symbol are traced. A rate of `n` traces one in every `n` calls, `0`
+/// traces none of them.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SamplingFilter {
+    /// Rate of the symbols without their own.
+    pub default_rate: u32,
+    pub rates: BTreeMap<u64, u32>,
+}
+
+impl SamplingFilter {
+    pub fn rate(&self, label_id: u64) -> u32 {
+        self.rates
+            .get(&label_id)
+            .copied()
+            .unwrap_or(self.default_rate)
+    }
+}
+
+impl Default for SamplingFilter {
+    fn default() -> Self {
+        SamplingFilter {
+            default_rate: 1,
+            rates: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SamplingUpdate {
+    /// When the plugin started applying the filter.
+    pub time: u64,
+    pub filter: SamplingFilter,
+}
+
+/// Answer of the plugin to a framed command.
+#[derive(Debug, Clone)]
+pub struct CommandResponse {
+    pub sequence: u32,
+
+    /// Why the command wasn't applied, `None` when it was.
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +397,43 @@ impl InitialMessage {
         UNIX_EPOCH + duration_since_epoch
     }
 
+    /// Display name of a symbol. Uses the documented name when there is one, falling back to the
+    /// module, library and encoded name of the symbol.
+    pub fn symbol_name(&self, docs: &LoadedDocumentation, symbol_idx: usize) -> Option<String> {
+        let symbol = self.symbols.get(symbol_idx)?;
+
+        if let Some(name) = self.documented_name(docs, symbol_idx) {
+            return Some(name.to_string());
+        }
+
+        let (Some(module), Some(library)) = (
+            self.modules.get(&(symbol.module_id as _)),
+            self.libraries.get(&(symbol.library_id as _)),
+        ) else {
+            return Some(symbol.name.clone());
+        };
+
+        Some(format!(
+            "{}::{}::{}",
+            module.name, library.name, symbol.name
+        ))
+    }
+
+    /// Name of a symbol from the documentation, if it is documented.
+    pub fn documented_name<'a>(
+        &self,
+        docs: &'a LoadedDocumentation,
+        symbol_idx: usize,
+    ) -> Option<&'a str> {
+        let symbol = self.symbols.get(symbol_idx)?;
+        let module = self.modules.get(&(symbol.module_id as _))?;
+        let library = self.libraries.get(&(symbol.library_id as _))?;
+
+        docs.lookup(&module.name, &library.name, &symbol.name)?
+            .name
+            .as_deref()
+    }
+
     pub fn read(mut stream: impl Read) -> io::Result<InitialMessage> {
         let mut initial_message_buf = [0u8; 8 * 4];
         stream.read_exact(&mut initial_message_buf)?;
@@ -94,12 +495,55 @@ pub enum SpanEvent {
     End(SpanEnd),
 }
 
+impl SpanEvent {
+    pub fn thread_id(&self) -> u64 {
+        match self {
+            SpanEvent::Start(start) => start.thread_id,
+            SpanEvent::End(end) => end.thread_id,
+        }
+    }
+
+    pub fn time(&self) -> u64 {
+        match self {
+            SpanEvent::Start(start) => start.time,
+            SpanEvent::End(end) => end.time,
+        }
+    }
+
+    /// The event as a `SpanStart` or `SpanEndReturnValue` message, for spans decoded from the
+    /// compact encoding which have neither extra data nor arguments.
+    pub fn encode(&self) -> Vec<u8> {
+        let values = match self {
+            SpanEvent::Start(start) => [0, start.thread_id, start.time, start.label_id],
+            SpanEvent::End(end) => [5, end.thread_id, end.time, end.return_value.unwrap_or(0)],
+        };
+
+        values.iter().flat_map(|it| it.to_le_bytes()).collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SpanStart {
     pub thread_id: u64,
     pub time: u64,
     pub label_id: u64,
     pub extra_data: Option<Vec<u8>>,
+
+    /// Sent when argument capture is enabled for the title.
+    pub args: Option<Box<CallArgs>>,
+
+    /// Set for submits captured at the request of the viewer.
+    pub capture: Option<Box<SubmitCapture>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallArgs {
+    /// Integer argument registers.
+    pub registers: [u64; 6],
+
+    /// Data the registers point to by register index, for registers the viewer asked the
+    /// plugin to follow. Strings don't include their nul terminator.
+    pub data: BTreeMap<usize, Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -107,6 +551,9 @@ pub struct SpanEnd {
     pub thread_id: u64,
     pub time: u64,
     pub extra_data: Option<Vec<u8>>,
+
+    /// Value of the return register, sent by plugins which support it.
+    pub return_value: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -186,9 +633,33 @@ impl SymbolInfo {
 }
 
 impl TraceEvent {
+    /// The thread and time of events which belong to a thread.
+    pub fn thread_time(&self) -> Option<(u64, u64)> {
+        match self {
+            TraceEvent::Start(_) => None,
+            TraceEvent::Span(span) => Some((span.thread_id(), span.time())),
+            TraceEvent::CountersUpdate(counters) => Some((counters.thread_id, counters.time)),
+            TraceEvent::ThreadExit(exit) => Some((exit.thread_id, exit.time)),
+            TraceEvent::CommandProtocol(..)
+            | TraceEvent::CommandResponse(..)
+            | TraceEvent::SamplingUpdate(..)
+            | TraceEvent::Compressed(..)
+            | TraceEvent::ThreadContext(..)
+            | TraceEvent::CompactSpan(..) => None,
+        }
+    }
+
     pub fn read(mut stream: impl Read) -> io::Result<TraceEvent> {
         let mut message_tag = [0u8; 8];
-        stream.read_exact(&mut message_tag)?;
+        stream.read_exact(&mut message_tag[..1])?;
+        if message_tag[0] >= COMPACT_TAG_BASE {
+            return Ok(TraceEvent::CompactSpan(CompactSpanEvent::read(
+                message_tag[0],
+                &mut stream,
+            )?));
+        }
+
+        stream.read_exact(&mut message_tag[1..])?;
         let message_tag = u64::from_le_bytes(message_tag);
 
         match message_tag {
@@ -204,6 +675,8 @@ impl TraceEvent {
                     time,
                     label_id,
                     extra_data: None,
+                    args: None,
+                    capture: None,
                 })))
             }
             1 => {
@@ -216,6 +689,7 @@ impl TraceEvent {
                     thread_id,
                     time,
                     extra_data: None,
+                    return_value: None,
                 })))
             }
             2 => {
@@ -250,6 +724,8 @@ impl TraceEvent {
                     time,
                     label_id,
                     extra_data: Some(extra_data),
+                    args: None,
+                    capture: None,
                 })))
             }
             4 => {
@@ -267,9 +743,293 @@ impl TraceEvent {
                     thread_id,
                     time,
                     extra_data: Some(extra_data),
+                    return_value: None,
+                })))
+            }
+            5 => {
+                // SpanEndReturnValue
+                let mut data = [0u8; 24];
+                stream.read_exact(&mut data)?;
+                let thread_id = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let time = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                let return_value = u64::from_le_bytes(data[16..24].try_into().unwrap());
+
+                Ok(TraceEvent::Span(SpanEvent::End(SpanEnd {
+                    thread_id,
+                    time,
+                    extra_data: None,
+                    return_value: Some(return_value),
+                })))
+            }
+            6 => {
+                // SpanStartArgs
+                let mut data = [0u8; 72];
+                stream.read_exact(&mut data)?;
+                let thread_id = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let time = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                let label_id = u64::from_le_bytes(data[16..24].try_into().unwrap());
+
+                let mut args = [0u64; 6];
+                for (idx, arg) in args.iter_mut().enumerate() {
+                    let offset = 24 + idx * 8;
+                    *arg = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+                }
+
+                Ok(TraceEvent::Span(SpanEvent::Start(SpanStart {
+                    thread_id,
+                    time,
+                    label_id,
+                    extra_data: None,
+                    args: Some(Box::new(CallArgs {
+                        registers: args,
+                        data: BTreeMap::new(),
+                    })),
+                    capture: None,
+                })))
+            }
+            7 => {
+                // CommandProtocol
+                let mut data = [0u8; 8];
+                stream.read_exact(&mut data)?;
+
+                Ok(TraceEvent::CommandProtocol(u64::from_le_bytes(data)))
+            }
+            8 => {
+                // CommandResponse
+                let mut data = [0u8; 24];
+                stream.read_exact(&mut data)?;
+                let sequence = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let status = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                let message_length = u64::from_le_bytes(data[16..24].try_into().unwrap());
+
+                let mut message = vec![0u8; message_length as usize];
+                stream.read_exact(&mut message)?;
+
+                let error = if status == 0 {
+                    None
+                } else {
+                    Some(String::from_utf8_lossy(&message).into_owned())
+                };
+
+                Ok(TraceEvent::CommandResponse(CommandResponse {
+                    sequence: sequence as u32,
+                    error,
+                }))
+            }
+            9 => {
+                // SamplingUpdate
+                let mut data = [0u8; 24];
+                stream.read_exact(&mut data)?;
+                let time = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let default_rate = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                let rate_count = u64::from_le_bytes(data[16..24].try_into().unwrap());
+
+                let mut rates = BTreeMap::new();
+                let mut entry = [0u8; 16];
+                for _ in 0..rate_count {
+                    stream.read_exact(&mut entry)?;
+                    let label_id = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+                    let rate = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+                    rates.insert(label_id, rate as u32);
+                }
+
+                Ok(TraceEvent::SamplingUpdate(SamplingUpdate {
+                    time,
+                    filter: SamplingFilter {
+                        default_rate: default_rate as u32,
+                        rates,
+                    },
+                }))
+            }
+            10 => {
+                // SpanStartCapture
+                let mut data = [0u8; 56];
+                stream.read_exact(&mut data)?;
+                let thread_id = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let time = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                let label_id = u64::from_le_bytes(data[16..24].try_into().unwrap());
+                let request = u64::from_le_bytes(data[24..32].try_into().unwrap());
+                let trigger = u64::from_le_bytes(data[32..40].try_into().unwrap());
+                let trigger_value = u64::from_le_bytes(data[40..48].try_into().unwrap());
+                let extra_data_length = u64::from_le_bytes(data[48..56].try_into().unwrap());
+
+                let trigger =
+                    CaptureTrigger::from_raw(trigger, trigger_value).ok_or_else(|| {
+                        io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("unknown capture trigger {}", trigger),
+                        )
+                    })?;
+
+                let mut extra_data = vec![0u8; extra_data_length as usize];
+                stream.read_exact(&mut extra_data)?;
+
+                Ok(TraceEvent::Span(SpanEvent::Start(SpanStart {
+                    thread_id,
+                    time,
+                    label_id,
+                    extra_data: Some(extra_data),
+                    args: None,
+                    capture: Some(Box::new(SubmitCapture {
+                        request: request as u32,
+                        trigger,
+                    })),
                 })))
             }
-            _ => Err(ErrorKind::InvalidData.into()),
+            11 => {
+                // CompressedMessages
+                let mut data = [0u8; 16];
+                stream.read_exact(&mut data)?;
+                let uncompressed_length = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let compressed_length = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                if uncompressed_length > MAX_COMPRESSED_BLOCK_LENGTH
+                    || compressed_length > MAX_COMPRESSED_BLOCK_LENGTH
+                {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "compressed block of {} bytes, {} once decompressed, is too large",
+                            compressed_length, uncompressed_length
+                        ),
+                    ));
+                }
+
+                let mut compressed = vec![0u8; compressed_length as usize];
+                stream.read_exact(&mut compressed)?;
+
+                Ok(TraceEvent::Compressed(decompress_block(
+                    &compressed,
+                    uncompressed_length as usize,
+                )?))
+            }
+            12 => {
+                // ThreadContext
+                let mut data = [0u8; 8];
+                stream.read_exact(&mut data)?;
+
+                Ok(TraceEvent::ThreadContext(u64::from_le_bytes(data)))
+            }
+            13 => {
+                // ThreadExit
+                let mut data = [0u8; 16];
+                stream.read_exact(&mut data)?;
+                let thread_id = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let time = u64::from_le_bytes(data[8..16].try_into().unwrap());
+
+                Ok(TraceEvent::ThreadExit(ThreadExit { thread_id, time }))
+            }
+            14 => {
+                // SpanStartArgsData
+                let mut data = [0u8; 80];
+                stream.read_exact(&mut data)?;
+                let thread_id = u64::from_le_bytes(data[0..8].try_into().unwrap());
+                let time = u64::from_le_bytes(data[8..16].try_into().unwrap());
+                let label_id = u64::from_le_bytes(data[16..24].try_into().unwrap());
+                let data_length = u64::from_le_bytes(data[72..80].try_into().unwrap());
+
+                let mut args = CallArgs::default();
+                for (idx, arg) in args.registers.iter_mut().enumerate() {
+                    let offset = 24 + idx * 8;
+                    *arg = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+                }
+
+                if data_length > MAX_ARG_DATA_LENGTH {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!("argument data of {} bytes is too long", data_length),
+                    ));
+                }
+
+                let mut arg_data = vec![0u8; data_length as usize];
+                stream.read_exact(&mut arg_data)?;
+
+                // the register index and length of each argument, followed by its data
+                let mut arg_data = arg_data.as_slice();
+                while !arg_data.is_empty() {
+                    let mut header = [0u8; 16];
+                    arg_data.read_exact(&mut header)?;
+                    let register = u64::from_le_bytes(header[0..8].try_into().unwrap());
+                    let length = u64::from_le_bytes(header[8..16].try_into().unwrap());
+
+                    if register >= 6 || length > arg_data.len() as u64 {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            "argument data doesn't match its registers",
+                        ));
+                    }
+
+                    let (value, rest) = arg_data.split_at(length as usize);
+                    args.data.insert(register as usize, value.to_vec());
+                    arg_data = rest;
+                }
+
+                Ok(TraceEvent::Span(SpanEvent::Start(SpanStart {
+                    thread_id,
+                    time,
+                    label_id,
+                    extra_data: None,
+                    args: Some(Box::new(args)),
+                    capture: None,
+                })))
+            }
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "unknown message tag {:#x}, the trace may be from an incompatible plugin build",
+                    message_tag
+                ),
+            )),
+        }
+    }
+}
+
+/// Reads the events of a stream, reading the messages of compressed blocks in place of the
+/// blocks and decoding compact spans.
+pub struct TraceEventReader<R> {
+    stream: R,
+    block: Cursor<Vec<u8>>,
+    compact_spans: CompactSpanDecoder,
+}
+
+impl<R: Read> TraceEventReader<R> {
+    pub fn new(stream: R) -> TraceEventReader<R> {
+        TraceEventReader {
+            stream,
+            block: Cursor::new(Vec::new()),
+            compact_spans: CompactSpanDecoder::default(),
+        }
+    }
+
+    pub fn read(&mut self) -> io::Result<TraceEvent> {
+        loop {
+            let message = self.read_message()?;
+            if let Some(event) = self.compact_spans.decode(message)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn read_message(&mut self) -> io::Result<TraceEvent> {
+        loop {
+            if self.block.position() < self.block.get_ref().len() as u64 {
+                return match TraceEvent::read(&mut self.block) {
+                    Ok(TraceEvent::Compressed(..)) => Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "compressed block inside a compressed block",
+                    )),
+                    // the end of the stream is only expected between messages
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "compressed block ends in the middle of a message",
+                    )),
+                    result => result,
+                };
+            }
+
+            match TraceEvent::read(&mut self.stream)? {
+                TraceEvent::Compressed(block) => self.block = Cursor::new(block),
+                event => return Ok(event),
+            }
         }
     }
 }
@@ -282,8 +1042,48 @@ fn read_string(stream: &mut impl Read) -> io::Result<String> {
         .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid UTF-8"))
 }
 
+/// 7 bits a byte, least significant first.
+fn read_varint(stream: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte)?;
+
+        if shift > 63 {
+            return Err(io::Error::new(ErrorKind::InvalidData, "varint too long"));
+        }
+
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+
+        shift += 7;
+    }
+}
+
 fn read_u32(stream: &mut impl Read) -> io::Result<u32> {
     let mut buf = [0u8; 4];
     stream.read_exact(&mut buf)?;
     Ok(u32::from_le_bytes(buf))
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::TraceEvent;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn rejects_oversized_compressed_blocks() {
+        for (uncompressed_length, compressed_length) in [(1u64 << 40, 16u64), (16, 1 << 40)] {
+            let mut data = Vec::new();
+            for value in [11, uncompressed_length, compressed_length] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+
+            let result = TraceEvent::read(data.as_slice());
+            assert!(matches!(result, Err(err) if err.kind() == ErrorKind::InvalidData));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::proto::SamplingUpdate;
+
+/// Sampling rates the plugin applied over the course of a trace. Symbols are traced in full until
+/// the first update.
+#[derive(Default)]
+pub struct SamplingHistory {
+    /// Ordered by time.
+    updates: Vec<SamplingUpdate>,
+}
+
+impl SamplingHistory {
+    pub fn push(&mut self, update: SamplingUpdate) {
+        let idx = self.updates.partition_point(|it| it.time <= update.time);
+        self.updates.insert(idx, update);
+    }
+
+    pub fn updates(&self) -> &[SamplingUpdate] {
+        &self.updates
+    }
+
+    /// Times the rate of a symbol changed, along with its new rate.
+    pub fn changes(&self, label_id: u64) -> Vec<(u64, u32)> {
+        let mut changes = Vec::new();
+        let mut last_rate = 1;
+        for update in &self.updates {
+            let rate = update.filter.rate(label_id);
+            if rate != last_rate {
+                changes.push((update.time, rate));
+                last_rate = rate;
+            }
+        }
+
+        changes
+    }
+}
+
+/// How often calls are traced at a rate.
+pub fn describe_rate(rate: u32) -> String {
+    match rate {
+        0 => "muted".to_string(),
+        1 => "every call".to_string(),
+        rate => format!("1 in {} calls", rate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::{SamplingFilter, SamplingUpdate};
+    use crate::sampling::SamplingHistory;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn lists_rate_changes_of_a_symbol() {
+        let mut history = SamplingHistory::default();
+
+        history.push(SamplingUpdate {
+            time: 30,
+            filter: SamplingFilter::default(),
+        });
+        history.push(SamplingUpdate {
+            time: 10,
+            filter: SamplingFilter {
+                default_rate: 1,
+                rates: BTreeMap::from([(4, 0), (5, 10)]),
+            },
+        });
+        history.push(SamplingUpdate {
+            time: 20,
+            filter: SamplingFilter {
+                default_rate: 0,
+                rates: BTreeMap::from([(5, 10)]),
+            },
+        });
+
+        assert_eq!(history.changes(4), vec![(10, 0), (30, 1)]);
+        assert_eq!(history.changes(5), vec![(10, 10), (30, 1)]);
+        assert_eq!(history.changes(6), vec![(20, 0), (30, 1)]);
+        assert_eq!(history.changes(7).len(), 2);
+    }
+}
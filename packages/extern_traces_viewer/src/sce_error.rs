@@ -0,0 +1,181 @@
+use crate::proto::InitialMessage;
+use crate::spans::ThreadSpan;
+use std::fmt;
+
+/// An `SCE_*` error code returned by a system library.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SceError {
+    pub code: u32,
+}
+
+/// Facility of errors which wrap a freebsd errno value in their low bits.
+const KERNEL_FACILITY: u32 = 0x8002;
+
+const ERRNO_NAMES: [&str; 93] = [
+    "UNKNOWN",
+    "EPERM",
+    "ENOENT",
+    "ESRCH",
+    "EINTR",
+    "EIO",
+    "ENXIO",
+    "E2BIG",
+    "ENOEXEC",
+    "EBADF",
+    "ECHILD",
+    "EDEADLK",
+    "ENOMEM",
+    "EACCES",
+    "EFAULT",
+    "ENOTBLK",
+    "EBUSY",
+    "EEXIST",
+    "EXDEV",
+    "ENODEV",
+    "ENOTDIR",
+    "EISDIR",
+    "EINVAL",
+    "ENFILE",
+    "EMFILE",
+    "ENOTTY",
+    "ETXTBSY",
+    "EFBIG",
+    "ENOSPC",
+    "ESPIPE",
+    "EROFS",
+    "EMLINK",
+    "EPIPE",
+    "EDOM",
+    "ERANGE",
+    "EAGAIN",
+    "EINPROGRESS",
+    "EALREADY",
+    "ENOTSOCK",
+    "EDESTADDRREQ",
+    "EMSGSIZE",
+    "EPROTOTYPE",
+    "ENOPROTOOPT",
+    "EPROTONOSUPPORT",
+    "ESOCKTNOSUPPORT",
+    "EOPNOTSUPP",
+    "EPFNOSUPPORT",
+    "EAFNOSUPPORT",
+    "EADDRINUSE",
+    "EADDRNOTAVAIL",
+    "ENETDOWN",
+    "ENETUNREACH",
+    "ENETRESET",
+    "ECONNABORTED",
+    "ECONNRESET",
+    "ENOBUFS",
+    "EISCONN",
+    "ENOTCONN",
+    "ESHUTDOWN",
+    "ETOOMANYREFS",
+    "ETIMEDOUT",
+    "ECONNREFUSED",
+    "ELOOP",
+    "ENAMETOOLONG",
+    "EHOSTDOWN",
+    "EHOSTUNREACH",
+    "ENOTEMPTY",
+    "EPROCLIM",
+    "EUSERS",
+    "EDQUOT",
+    "ESTALE",
+    "EREMOTE",
+    "EBADRPC",
+    "ERPCMISMATCH",
+    "EPROGUNAVAIL",
+    "EPROGMISMATCH",
+    "EPROCUNAVAIL",
+    "ENOLCK",
+    "ENOSYS",
+    "EFTYPE",
+    "EAUTH",
+    "ENEEDAUTH",
+    "EIDRM",
+    "ENOMSG",
+    "EOVERFLOW",
+    "ECANCELED",
+    "EILSEQ",
+    "ENOATTR",
+    "EDOOFUS",
+    "EBADMSG",
+    "EMULTIHOP",
+    "ENOLINK",
+    "EPROTO",
+];
+
+impl SceError {
+    /// Interprets a return register as an `int` error code. Error codes are negative 32 bit
+    /// values of the form `0x80FFxxxx`, where `FF` is the facility of the library.
+    pub fn decode(return_value: u64) -> Option<SceError> {
+        let high = (return_value >> 32) as u32;
+        let code = return_value as u32;
+
+        // the upper half of the register is either zero or sign extended for int returns
+        if high != 0 && high != u32::MAX {
+            return None;
+        }
+
+        if code >> 24 != 0x80 {
+            return None;
+        }
+
+        Some(SceError { code })
+    }
+
+    pub fn facility(&self) -> u32 {
+        self.code >> 16
+    }
+
+    pub fn name(&self) -> Option<String> {
+        if self.facility() != KERNEL_FACILITY {
+            return None;
+        }
+
+        let errno = ERRNO_NAMES.get((self.code & 0xffff) as usize)?;
+
+        Some(format!("SCE_KERNEL_ERROR_{}", errno))
+    }
+
+    /// The error returned by a span, if it is a call into a system library which failed.
+    pub fn from_span(initial_message: &InitialMessage, span: &ThreadSpan) -> Option<SceError> {
+        let return_value = span.return_value?;
+        let symbol = initial_message.symbols.get(span.label_id as usize)?;
+        let library = initial_message.libraries.get(&(symbol.library_id as _))?;
+
+        if !library.name.starts_with("libSce") && library.name != "libkernel" {
+            return None;
+        }
+
+        SceError::decode(return_value)
+    }
+}
+
+impl fmt::Display for SceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} ({:#010x})", name, self.code),
+            None => write!(f, "SCE error {:#010x}", self.code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sce_error::SceError;
+
+    #[test]
+    fn decodes_error_codes() {
+        let error = SceError::decode(0xffff_ffff_8002_0023).unwrap();
+        assert_eq!(error.to_string(), "SCE_KERNEL_ERROR_EAGAIN (0x80020023)");
+
+        let error = SceError::decode(0x8096_0001).unwrap();
+        assert_eq!(error.to_string(), "SCE error 0x80960001");
+
+        assert_eq!(SceError::decode(0), None);
+        assert_eq!(SceError::decode(0x0000_0002_8002_0023), None);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::proto::{CallArgs, SpanEnd, SpanEvent, SpanStart, SubmitCapture};
+use std::collections::VecDeque;
+
+#[derive(Clone)]
+pub struct ThreadSpan {
+    pub start_time: u64,
+    pub end_time: u64,
+    pub label_id: u64,
+
+    /// Number of spans of the same thread this span is nested in.
+    pub depth: u32,
+
+    pub return_value: Option<u64>,
+    pub args: Option<Box<CallArgs>>,
+
+    /// Set for submits captured at the request of the viewer.
+    pub capture: Option<Box<SubmitCapture>>,
+
+    pub start_extra_data: Option<Vec<u8>>,
+    pub end_extra_data: Option<Vec<u8>>,
+}
+
+impl ThreadSpan {
+    pub fn from_events(start: SpanStart, end: SpanEnd, depth: u32) -> ThreadSpan {
+        ThreadSpan {
+            start_time: start.time,
+            end_time: end.time,
+            label_id: start.label_id,
+            depth,
+            return_value: end.return_value,
+            args: start.args,
+            capture: start.capture,
+            start_extra_data: start.extra_data,
+            end_extra_data: end.extra_data,
+        }
+    }
+}
+
+/// Number of finished spans held back per thread so spans arriving late can still be put in
+/// order.
+const REORDER_WINDOW: usize = 64;
+
+/// Counts of spans which arrived out of order.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ReorderCounts {
+    /// Spans which were put back in order.
+    pub corrected: u64,
+
+    /// Spans which arrived after later spans were already released and were dropped.
+    pub unrecoverable: u64,
+}
+
+/// Pairs up the span events of a single thread into `ThreadSpan`s. Used both when viewing and
+/// when writing trace files so both agree on which spans exist.
+///
+/// Finished spans are released in the order they ended, after passing through a reorder buffer
+/// of `REORDER_WINDOW` spans.
+pub struct SpanAssembler {
+    /// Spans which have started but not ended yet, innermost last.
+    stack: Vec<StartedSpan>,
+
+    /// Finished spans ordered by end time, waiting to be released.
+    pending: VecDeque<ThreadSpan>,
+
+    /// Latest end time of any finished span.
+    last_end_time: Option<u64>,
+
+    /// End time of the last released span. Spans ending before it can't be put in order anymore.
+    released_end_time: Option<u64>,
+
+    counts: ReorderCounts,
+}
+
+struct StartedSpan {
+    start: SpanStart,
+
+    /// Set for spans which started before the end of an earlier span.
+    out_of_order: bool,
+}
+
+impl SpanAssembler {
+    pub fn new() -> SpanAssembler {
+        SpanAssembler::resume_after(None, Vec::new())
+    }
+
+    /// Continues assembling spans after a span ending at `last_end_time` was released, inside the
+    /// spans started by `open_starts` which didn't end yet, outermost first.
+    pub fn resume_after(last_end_time: Option<u64>, open_starts: Vec<SpanStart>) -> SpanAssembler {
+        let stack = open_starts
+            .into_iter()
+            .map(|start| StartedSpan {
+                start,
+                out_of_order: false,
+            })
+            .collect();
+
+        SpanAssembler {
+            stack,
+            pending: VecDeque::new(),
+            last_end_time,
+            released_end_time: last_end_time,
+            counts: ReorderCounts::default(),
+        }
+    }
+
+    /// Starts of the spans waiting for their end, outermost first.
+    pub fn open_starts(&self) -> impl Iterator<Item = &SpanStart> {
+        self.stack.iter().map(|it| &it.start)
+    }
+
+    pub fn last_end_time(&self) -> Option<u64> {
+        self.last_end_time
+    }
+
+    pub fn counts(&self) -> ReorderCounts {
+        self.counts
+    }
+
+    /// Pushes an event, returning a span once it leaves the reorder buffer.
+    pub fn push(&mut self, event: SpanEvent) -> Option<ThreadSpan> {
+        match event {
+            SpanEvent::Start(start) => {
+                let out_of_order = self
+                    .last_end_time
+                    .is_some_and(|last_end_time| start.time < last_end_time);
+
+                self.stack.push(StartedSpan {
+                    start,
+                    out_of_order,
+                });
+
+                None
+            }
+            SpanEvent::End(end) => {
+                let started = self.stack.pop()?;
+                let span = ThreadSpan::from_events(started.start, end, self.stack.len() as u32);
+
+                if self
+                    .released_end_time
+                    .is_some_and(|released_end_time| span.end_time < released_end_time)
+                {
+                    self.counts.unrecoverable += 1;
+                    return None;
+                }
+
+                self.last_end_time = Some(
+                    self.last_end_time
+                        .map_or(span.end_time, |it| it.max(span.end_time)),
+                );
+
+                let position = self
+                    .pending
+                    .partition_point(|it| it.end_time <= span.end_time);
+                if started.out_of_order || position != self.pending.len() {
+                    self.counts.corrected += 1;
+                }
+                self.pending.insert(position, span);
+
+                if self.pending.len() <= REORDER_WINDOW {
+                    return None;
+                }
+
+                let span = self.pending.pop_front()?;
+                self.released_end_time = Some(span.end_time);
+
+                Some(span)
+            }
+        }
+    }
+
+    /// Releases every span in the reorder buffer.
+    pub fn drain(&mut self) -> impl Iterator<Item = ThreadSpan> + '_ {
+        self.release_ended_by(u64::MAX)
+    }
+
+    /// Releases the spans in the reorder buffer which ended by `time`, for when no span is
+    /// expected to arrive that late anymore.
+    pub fn release_ended_by(&mut self, time: u64) -> impl Iterator<Item = ThreadSpan> + '_ {
+        let count = self.pending.partition_point(|it| it.end_time <= time);
+        if let Some(span) = count.checked_sub(1).map(|idx| &self.pending[idx]) {
+            self.released_end_time = Some(span.end_time);
+        }
+
+        self.pending.drain(..count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::{SpanEnd, SpanEvent, SpanStart};
+    use crate::spans::{ReorderCounts, SpanAssembler, ThreadSpan, REORDER_WINDOW};
+
+    fn start(time: u64) -> SpanEvent {
+        SpanEvent::Start(SpanStart {
+            thread_id: 1,
+            time,
+            label_id: time,
+            extra_data: None,
+            args: None,
+            capture: None,
+        })
+    }
+
+    fn end(time: u64) -> SpanEvent {
+        SpanEvent::End(SpanEnd {
+            thread_id: 1,
+            time,
+            extra_data: None,
+            return_value: None,
+        })
+    }
+
+    fn assemble(assembler: &mut SpanAssembler, events: Vec<SpanEvent>) -> Vec<ThreadSpan> {
+        let mut spans = events
+            .into_iter()
+            .filter_map(|event| assembler.push(event))
+            .collect::<Vec<_>>();
+        spans.extend(assembler.drain());
+
+        spans
+    }
+
+    #[test]
+    fn assembles_nested_spans() {
+        let mut assembler = SpanAssembler::new();
+        let spans = assemble(
+            &mut assembler,
+            vec![start(1), start(2), end(3), start(4), end(5), end(6)],
+        )
+        .into_iter()
+        .map(|span| (span.label_id, span.end_time, span.depth))
+        .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec![(2, 3, 1), (4, 5, 1), (1, 6, 0)]);
+        assert_eq!(assembler.open_starts().count(), 0);
+    }
+
+    #[test]
+    fn drops_ends_of_calls_started_before_the_capture() {
+        let mut assembler = SpanAssembler::new();
+        let spans = assemble(
+            &mut assembler,
+            vec![end(1), start(2), start(3), end(4), end(5), end(6)],
+        )
+        .into_iter()
+        .map(|span| (span.label_id, span.end_time, span.depth))
+        .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec![(3, 4, 1), (2, 5, 0)]);
+        assert_eq!(assembler.open_starts().count(), 0);
+    }
+
+    #[test]
+    fn reorders_late_spans() {
+        let mut assembler = SpanAssembler::new();
+        let spans = assemble(
+            &mut assembler,
+            vec![start(10), end(20), start(30), end(40), start(22), end(28)],
+        )
+        .into_iter()
+        .map(|span| span.start_time)
+        .collect::<Vec<_>>();
+
+        assert_eq!(spans, vec![10, 22, 30]);
+        assert_eq!(
+            assembler.counts(),
+            ReorderCounts {
+                corrected: 1,
+                unrecoverable: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn drops_spans_older_than_the_window() {
+        let mut assembler = SpanAssembler::new();
+        let mut events = Vec::new();
+        for idx in 0..=REORDER_WINDOW as u64 {
+            events.push(start(100 + idx * 10));
+            events.push(end(105 + idx * 10));
+        }
+        events.push(start(1));
+        events.push(end(2));
+
+        let spans = assemble(&mut assembler, events);
+
+        assert_eq!(spans.len(), REORDER_WINDOW + 1);
+        assert_eq!(assembler.counts().unrecoverable, 1);
+    }
+
+    #[test]
+    fn releases_spans_ended_by_a_time() {
+        let mut assembler = SpanAssembler::new();
+        for event in [start(10), end(20), start(30), end(40)] {
+            assert!(assembler.push(event).is_none());
+        }
+
+        let released = assembler
+            .release_ended_by(25)
+            .map(|span| span.start_time)
+            .collect::<Vec<_>>();
+        assert_eq!(released, vec![10]);
+
+        // too late to be put before the released span
+        assert!(assembler.push(start(12)).is_none());
+        assert!(assembler.push(end(15)).is_none());
+        assert_eq!(assembler.counts().unrecoverable, 1);
+
+        let spans = assembler
+            .drain()
+            .map(|span| span.start_time)
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![30]);
+    }
+}
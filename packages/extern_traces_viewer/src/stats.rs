@@ -0,0 +1,217 @@
+use crate::frames::FrameMarkers;
+use crate::proto::{InitialMessage, SpanEvent, TraceEvent, TraceEventReader};
+use crate::spans::{SpanAssembler, ThreadSpan};
+use crate::trace_file::TraceFile;
+use ps4libdoc::LoadedDocumentation;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+
+/// Summary of a set of span durations, in cycles.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DurationSummary {
+    pub count: u64,
+    pub total: u64,
+    pub min: u64,
+    pub mean: u64,
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl DurationSummary {
+    /// Summarizes `durations`, which must be sorted in ascending order.
+    pub fn from_sorted(durations: &[u64]) -> Option<DurationSummary> {
+        let max = *durations.last()?;
+        let count = durations.len() as u64;
+        let total = durations.iter().sum::<u64>();
+
+        Some(DurationSummary {
+            count,
+            total,
+            min: durations[0],
+            mean: total / count,
+            p50: percentile(durations, 0.5),
+            p95: percentile(durations, 0.95),
+            p99: percentile(durations, 0.99),
+            max,
+        })
+    }
+}
+
+/// Nearest-rank percentile of sorted, non-empty `durations`.
+fn percentile(durations: &[u64], percentile: f64) -> u64 {
+    let rank = (percentile * durations.len() as f64).ceil() as usize;
+
+    durations[rank.clamp(1, durations.len()) - 1]
+}
+
+/// Durations of every span in a trace, grouped by symbol then thread.
+pub struct TraceStats {
+    pub symbols: BTreeMap<u64, BTreeMap<u64, Vec<u64>>>,
+    pub frames: FrameMarkers,
+}
+
+impl TraceStats {
+    pub fn read(mut stream: impl Read) -> io::Result<(InitialMessage, TraceStats)> {
+        let initial_message = InitialMessage::read(&mut stream)?;
+
+        let mut assemblers = BTreeMap::<u64, SpanAssembler>::new();
+        let mut symbols = BTreeMap::<u64, BTreeMap<u64, Vec<u64>>>::new();
+        let mut frames = FrameMarkers::new(&initial_message);
+        let mut record = |thread_id: u64, span: ThreadSpan| {
+            symbols
+                .entry(span.label_id)
+                .or_default()
+                .entry(thread_id)
+                .or_default()
+                .push(span.end_time - span.start_time);
+        };
+
+        let mut reader = TraceEventReader::new(stream);
+        loop {
+            let event = match reader.read() {
+                Ok(TraceEvent::Span(event)) => event,
+                Ok(..) => continue,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            };
+
+            if let SpanEvent::Start(start) = &event {
+                if frames.is_flip(start.label_id) {
+                    frames.add_flip(start.time);
+                }
+            }
+
+            let thread_id = event.thread_id();
+            let assembler = assemblers
+                .entry(thread_id)
+                .or_insert_with(SpanAssembler::new);
+
+            if let Some(span) = assembler.push(event) {
+                record(thread_id, span);
+            }
+        }
+
+        for (thread_id, assembler) in &mut assemblers {
+            for span in assembler.drain() {
+                record(*thread_id, span);
+            }
+        }
+
+        for threads in symbols.values_mut() {
+            for durations in threads.values_mut() {
+                durations.sort_unstable();
+            }
+        }
+
+        Ok((initial_message, TraceStats { symbols, frames }))
+    }
+
+    /// Summaries of every symbol across all threads, with the highest total duration first.
+    pub fn summaries(&self) -> Vec<(u64, DurationSummary)> {
+        let mut summaries = self
+            .symbols
+            .iter()
+            .filter_map(|(symbol_idx, threads)| {
+                let mut durations = threads.values().flatten().copied().collect::<Vec<_>>();
+                durations.sort_unstable();
+
+                Some((*symbol_idx, DurationSummary::from_sorted(&durations)?))
+            })
+            .collect::<Vec<_>>();
+
+        summaries.sort_by(|(_, a), (_, b)| b.total.cmp(&a.total));
+
+        summaries
+    }
+
+    /// Summary of the frame times, when the trace has frames.
+    pub fn frame_summary(&self) -> Option<DurationSummary> {
+        let mut durations = self
+            .frames
+            .frames()
+            .map(|it| it.end - it.start)
+            .collect::<Vec<_>>();
+        durations.sort_unstable();
+
+        DurationSummary::from_sorted(&durations)
+    }
+}
+
+/// Prints per symbol statistics of the trace file at `path` as tab separated values.
+pub fn print_stats(path: &Path, per_thread: bool, limit: Option<usize>) -> anyhow::Result<()> {
+    let docs = LoadedDocumentation::bundled()?;
+    let (initial_message, stats) = TraceStats::read(TraceFile::open(path)?.stream()?)?;
+
+    let micros = |cycles: u64| cycles as f64 * 1_000_000. / initial_message.tsc_frequency as f64;
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "symbol\tthread\tcount\ttotal_us\tmean_us\tp50_us\tp99_us\tmax_us"
+    )?;
+
+    let mut write_row = |name: &str, thread: &str, summary: &DurationSummary| {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{:.3}\t{:.3}\t{:.3}\t{:.3}\t{:.3}",
+            name,
+            thread,
+            summary.count,
+            micros(summary.total),
+            micros(summary.mean),
+            micros(summary.p50),
+            micros(summary.p99),
+            micros(summary.max),
+        )
+    };
+
+    let summaries = stats.summaries();
+    for (symbol_idx, summary) in summaries.iter().take(limit.unwrap_or(usize::MAX)) {
+        let name = initial_message
+            .symbol_name(&docs, *symbol_idx as usize)
+            .unwrap_or_else(|| format!("unknown symbol {}", symbol_idx));
+
+        write_row(&name, "all", summary)?;
+
+        if per_thread {
+            for (thread_id, durations) in &stats.symbols[symbol_idx] {
+                let Some(summary) = DurationSummary::from_sorted(durations) else {
+                    continue;
+                };
+
+                write_row(&name, &thread_id.to_string(), &summary)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::DurationSummary;
+
+    #[test]
+    fn summarizes_durations() {
+        let durations = (1..=200).collect::<Vec<u64>>();
+
+        assert_eq!(
+            DurationSummary::from_sorted(&durations),
+            Some(DurationSummary {
+                count: 200,
+                total: 20100,
+                min: 1,
+                mean: 100,
+                p50: 100,
+                p95: 190,
+                p99: 198,
+                max: 200,
+            })
+        );
+        assert_eq!(DurationSummary::from_sorted(&[]), None);
+    }
+}
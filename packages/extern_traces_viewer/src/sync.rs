@@ -0,0 +1,149 @@
+use crate::proto::InitialMessage;
+use crate::spans::ThreadSpan;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum SyncObjectKind {
+    Mutex,
+    Cond,
+    Sema,
+    Equeue,
+    EventFlag,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SyncOp {
+    /// Takes the object, blocking until it's available.
+    Wait,
+
+    /// Releases the object, waking a waiting thread.
+    Signal,
+}
+
+/// Encoded names of the calls which the plugin attaches the handle of their sync object to, as
+/// the first argument of the call.
+const SYNC_CALLS: [(&str, SyncObjectKind, SyncOp); 17] = [
+    // scePthreadMutexLock
+    ("9UK1vLZQft4", SyncObjectKind::Mutex, SyncOp::Wait),
+    // scePthreadMutexTrylock
+    ("upoVrzMHFeE", SyncObjectKind::Mutex, SyncOp::Wait),
+    // scePthreadMutexTimedlock
+    ("IafI2PxcPnQ", SyncObjectKind::Mutex, SyncOp::Wait),
+    // scePthreadMutexUnlock
+    ("tn3VlD0hG60", SyncObjectKind::Mutex, SyncOp::Signal),
+    // pthread_mutex_lock
+    ("7H0iTOciTLo", SyncObjectKind::Mutex, SyncOp::Wait),
+    // pthread_mutex_unlock
+    ("2Z+PpY6CaJg", SyncObjectKind::Mutex, SyncOp::Signal),
+    // scePthreadCondWait
+    ("WKAXJ4XBPQ4", SyncObjectKind::Cond, SyncOp::Wait),
+    // scePthreadCondTimedwait
+    ("BmMjYxmew1w", SyncObjectKind::Cond, SyncOp::Wait),
+    // scePthreadCondSignal
+    ("kDh-NfxgMtE", SyncObjectKind::Cond, SyncOp::Signal),
+    // scePthreadCondBroadcast
+    ("JGgj7Uvrl+A", SyncObjectKind::Cond, SyncOp::Signal),
+    // sceKernelWaitSema
+    ("Zxa0VhQVTsk", SyncObjectKind::Sema, SyncOp::Wait),
+    // sceKernelPollSema
+    ("12wOHk8ywb0", SyncObjectKind::Sema, SyncOp::Wait),
+    // sceKernelSignalSema
+    ("4czppHBiriw", SyncObjectKind::Sema, SyncOp::Signal),
+    // sceKernelWaitEqueue
+    ("fzyMKs9kim0", SyncObjectKind::Equeue, SyncOp::Wait),
+    // sceKernelTriggerUserEvent
+    ("F6e0kwo4cnk", SyncObjectKind::Equeue, SyncOp::Signal),
+    // sceKernelWaitEventFlag
+    ("JTvBflhYazQ", SyncObjectKind::EventFlag, SyncOp::Wait),
+    // sceKernelSetEventFlag
+    ("IOnSvHzqu6A", SyncObjectKind::EventFlag, SyncOp::Signal),
+];
+
+/// A lock, semaphore, queue or other object threads wait on.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct SyncObject {
+    pub kind: SyncObjectKind,
+    pub handle: u64,
+}
+
+impl fmt::Display for SyncObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            SyncObjectKind::Mutex => "mutex",
+            SyncObjectKind::Cond => "cond",
+            SyncObjectKind::Sema => "sema",
+            SyncObjectKind::Equeue => "equeue",
+            SyncObjectKind::EventFlag => "event flag",
+        };
+
+        write!(f, "{} {:#x}", kind, self.handle)
+    }
+}
+
+/// Labels of the calls on sync objects of a trace.
+pub struct SyncCalls {
+    labels: BTreeMap<u64, (SyncObjectKind, SyncOp)>,
+}
+
+impl SyncCalls {
+    pub fn new(initial_message: &InitialMessage) -> SyncCalls {
+        let labels = initial_message
+            .symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(symbol_idx, symbol)| {
+                let (_, kind, op) = SYNC_CALLS.iter().find(|(nid, _, _)| *nid == symbol.name)?;
+
+                Some((symbol_idx as u64, (*kind, *op)))
+            })
+            .collect();
+
+        SyncCalls { labels }
+    }
+
+    /// The object a span waits on or signals, when it's a call on a sync object.
+    pub fn call(&self, span: &ThreadSpan) -> Option<(SyncObject, SyncOp)> {
+        let (kind, op) = *self.labels.get(&span.label_id)?;
+        let extra_data = span.start_extra_data.as_ref()?;
+        let handle = u64::from_le_bytes(extra_data.as_slice().try_into().ok()?);
+
+        Some((SyncObject { kind, handle }, op))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::spans::ThreadSpan;
+    use crate::sync::{SyncCalls, SyncObject, SyncObjectKind, SyncOp};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn reads_handles() {
+        let calls = SyncCalls {
+            labels: BTreeMap::from([(4, (SyncObjectKind::Sema, SyncOp::Signal))]),
+        };
+
+        let mut span = ThreadSpan {
+            start_time: 0,
+            end_time: 10,
+            label_id: 4,
+            depth: 0,
+            return_value: None,
+            args: None,
+            capture: None,
+            start_extra_data: Some(0x2au64.to_le_bytes().to_vec()),
+            end_extra_data: None,
+        };
+
+        let object = SyncObject {
+            kind: SyncObjectKind::Sema,
+            handle: 0x2a,
+        };
+        assert_eq!(calls.call(&span), Some((object, SyncOp::Signal)));
+        assert_eq!(object.to_string(), "sema 0x2a");
+
+        span.label_id = 5;
+        assert_eq!(calls.call(&span), None);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::proto::{InitialMessage, SpanEvent, ThreadExit};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Encoded names of the calls which create a thread. The plugin attaches the name of the new
+/// thread to the start of the call and the created thread to its end.
+const CREATE_NIDS: [&str; 1] = [
+    // scePthreadCreate
+    "6UgtwV+0zb4",
+];
+
+/// Encoded names of the calls which rename a thread. The plugin attaches the renamed thread
+/// followed by its new name to the start of the call.
+const RENAME_NIDS: [&str; 2] = [
+    // scePthreadRename
+    "GBUY7ywdULE",
+    // pthread_setname_np
+    "cB4rMoKU4UI",
+];
+
+#[derive(Clone, Default)]
+pub struct ThreadInfo {
+    /// Latest name the traced program gave the thread.
+    pub name: Option<String>,
+    pub created: Option<ThreadCreation>,
+
+    /// When the thread exited, unset for threads still running when the trace ended.
+    pub exited: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct ThreadCreation {
+    /// Thread which called `scePthreadCreate`.
+    pub creator: u64,
+    pub time: u64,
+}
+
+/// Names, creation and exit of threads, from the thread calls and exits of the trace.
+pub struct ThreadNames {
+    create_labels: BTreeSet<u64>,
+    rename_labels: BTreeSet<u64>,
+
+    /// Calls of each thread which didn't return yet.
+    depths: BTreeMap<u64, u32>,
+
+    /// Depth, time and name of create calls which didn't return yet, by the calling thread.
+    pending_creates: BTreeMap<u64, (u32, u64, String)>,
+
+    threads: BTreeMap<u64, ThreadInfo>,
+}
+
+impl ThreadNames {
+    pub fn new(initial_message: &InitialMessage) -> ThreadNames {
+        ThreadNames {
+            create_labels: labels(initial_message, &CREATE_NIDS),
+            rename_labels: labels(initial_message, &RENAME_NIDS),
+            depths: BTreeMap::new(),
+            pending_creates: BTreeMap::new(),
+            threads: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, thread_id: u64) -> Option<&ThreadInfo> {
+        self.threads.get(&thread_id)
+    }
+
+    /// Picks up names from span events, in the order they were written by each thread.
+    pub fn observe(&mut self, event: &SpanEvent) {
+        match event {
+            SpanEvent::Start(start) => {
+                let depth = self.depths.entry(start.thread_id).or_default();
+                *depth += 1;
+
+                let Some(extra_data) = &start.extra_data else {
+                    return;
+                };
+
+                if self.create_labels.contains(&start.label_id) {
+                    self.pending_creates.insert(
+                        start.thread_id,
+                        (
+                            *depth,
+                            start.time,
+                            String::from_utf8_lossy(extra_data).into_owned(),
+                        ),
+                    );
+                } else if self.rename_labels.contains(&start.label_id) && extra_data.len() >= 8 {
+                    let (thread_id, name) = extra_data.split_at(8);
+                    let thread_id = u64::from_le_bytes(thread_id.try_into().unwrap());
+
+                    self.threads.entry(thread_id).or_default().name =
+                        Some(String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            SpanEvent::End(end) => {
+                let depth = self.depths.entry(end.thread_id).or_default();
+                let call_depth = *depth;
+                *depth = depth.saturating_sub(1);
+
+                // calls made by the create call end before it
+                let create_depth = self.pending_creates.get(&end.thread_id).map(|it| it.0);
+                if create_depth != Some(call_depth) {
+                    return;
+                }
+
+                let Some((_, time, name)) = self.pending_creates.remove(&end.thread_id) else {
+                    return;
+                };
+
+                // only successful calls carry the created thread
+                let Some(extra_data) = end.extra_data.as_ref().filter(|it| it.len() == 8) else {
+                    return;
+                };
+                let thread_id = u64::from_le_bytes(extra_data[..].try_into().unwrap());
+
+                let thread = self.threads.entry(thread_id).or_default();
+                thread.created = Some(ThreadCreation {
+                    creator: end.thread_id,
+                    time,
+                });
+
+                // a rename from the new thread may have arrived first
+                if thread.name.is_none() && !name.is_empty() {
+                    thread.name = Some(name);
+                }
+            }
+        }
+    }
+
+    pub fn observe_exit(&mut self, exit: &ThreadExit) {
+        self.threads.entry(exit.thread_id).or_default().exited = Some(exit.time);
+    }
+}
+
+fn labels(initial_message: &InitialMessage, nids: &[&str]) -> BTreeSet<u64> {
+    initial_message
+        .symbols
+        .iter()
+        .enumerate()
+        .filter(|(_, symbol)| nids.contains(&symbol.name.as_str()))
+        .map(|(symbol_idx, _)| symbol_idx as u64)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::{SpanEnd, SpanEvent, SpanStart};
+    use crate::threads::ThreadNames;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn start(thread_id: u64, time: u64, label_id: u64, extra_data: Vec<u8>) -> SpanEvent {
+        SpanEvent::Start(SpanStart {
+            thread_id,
+            time,
+            label_id,
+            extra_data: Some(extra_data),
+            args: None,
+            capture: None,
+        })
+    }
+
+    fn end(thread_id: u64, time: u64, extra_data: Option<Vec<u8>>) -> SpanEvent {
+        SpanEvent::End(SpanEnd {
+            thread_id,
+            time,
+            extra_data,
+            return_value: None,
+        })
+    }
+
+    #[test]
+    fn names_created_and_renamed_threads() {
+        let mut names = ThreadNames {
+            create_labels: BTreeSet::from([1]),
+            rename_labels: BTreeSet::from([2]),
+            depths: BTreeMap::new(),
+            pending_creates: BTreeMap::new(),
+            threads: BTreeMap::new(),
+        };
+
+        // the create call makes a traced call of its own
+        names.observe(&start(10, 100, 1, b"render".to_vec()));
+        names.observe(&start(10, 102, 3, Vec::new()));
+        names.observe(&end(10, 104, None));
+        names.observe(&end(10, 110, Some(20u64.to_le_bytes().to_vec())));
+
+        // failed create, nothing was created
+        names.observe(&start(10, 120, 1, b"audio".to_vec()));
+        names.observe(&end(10, 130, None));
+
+        let info = names.get(20).unwrap();
+        assert_eq!(info.name.as_deref(), Some("render"));
+        assert_eq!(
+            info.created.as_ref().map(|it| (it.creator, it.time)),
+            Some((10, 100))
+        );
+        assert_eq!(names.threads.len(), 1);
+
+        let mut rename = 20u64.to_le_bytes().to_vec();
+        rename.extend_from_slice(b"render 2");
+        names.observe(&start(20, 140, 2, rename));
+
+        assert_eq!(names.get(20).unwrap().name.as_deref(), Some("render 2"));
+    }
+}
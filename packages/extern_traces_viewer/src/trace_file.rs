@@ -0,0 +1,1144 @@
+use crate::proto::{
+    CompactSpanDecoder, CountersUpdate, InitialMessage, SamplingUpdate, SpanEvent, SpanStart,
+    TraceEvent,
+};
+use crate::spans::SpanAssembler;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufReader, BufWriter, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// First bytes of a trace file. Raw captures start with the tsc frequency instead, which never
+/// looks like this.
+pub const MAGIC: [u8; 8] = *b"EXTRNTRC";
+
+/// Extension of saved trace files, raw captures are usually saved as `.bin` instead.
+pub const EXTENSION: &str = "trace";
+
+/// Version of the container layout written by this build. Files with a newer version are
+/// rejected instead of being misparsed.
+pub const FORMAT_VERSION: u32 = 1;
+
+const HEADER_SIZE: u64 = 8 + 4 + 4;
+const SECTION_ENTRY_SIZE: u64 = 4 + 4 + 8 + 8;
+const CHUNK_ENTRY_SIZE: usize = 8 * 8;
+
+/// Number of spans after which the events of a thread are written out as a chunk.
+const SPANS_PER_CHUNK: u64 = 1 << 16;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SectionKind {
+    /// The `InitialMessage` as sent by the plugin.
+    InitialMessage = 0,
+
+    /// `TraceEvent`s grouped into per thread chunks. Events of a thread are kept in order.
+    Events = 1,
+
+    /// A `TraceIndex` of the chunks in the events section.
+    Index = 2,
+
+    /// Copies of every `CountersUpdate` from the events section, so they are available without
+    /// reading every chunk.
+    Counters = 3,
+
+    /// Bookmarks and annotations made in the viewer, as JSON. Written empty as the last section
+    /// so it can be filled in later without moving the others.
+    Annotations = 4,
+
+    /// Every `SamplingUpdate` of the trace. They belong to no thread, so aren't kept in the
+    /// events section.
+    Sampling = 5,
+
+    /// Starts of the spans still open where a chunk begins, for chunks split inside a call. Each
+    /// chunk with open spans has the offset of the chunk and the length of its starts, followed
+    /// by the start events.
+    OpenSpans = 6,
+}
+
+impl SectionKind {
+    fn from_u32(value: u32) -> Option<SectionKind> {
+        match value {
+            0 => Some(SectionKind::InitialMessage),
+            1 => Some(SectionKind::Events),
+            2 => Some(SectionKind::Index),
+            3 => Some(SectionKind::Counters),
+            4 => Some(SectionKind::Annotations),
+            5 => Some(SectionKind::Sampling),
+            6 => Some(SectionKind::OpenSpans),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub kind: u32,
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl Section {
+    fn read(stream: &mut impl Read) -> io::Result<Section> {
+        let mut data = [0u8; SECTION_ENTRY_SIZE as usize];
+        stream.read_exact(&mut data)?;
+        let kind = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let offset = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let length = u64::from_le_bytes(data[16..24].try_into().unwrap());
+
+        Ok(Section {
+            kind,
+            offset,
+            length,
+        })
+    }
+
+    fn write(&self, stream: &mut impl Write) -> io::Result<()> {
+        stream.write_all(&self.kind.to_le_bytes())?;
+        stream.write_all(&0u32.to_le_bytes())?;
+        stream.write_all(&self.offset.to_le_bytes())?;
+        stream.write_all(&self.length.to_le_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn section_kind(&self) -> Option<SectionKind> {
+        SectionKind::from_u32(self.kind)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceFileHeader {
+    pub version: u32,
+    pub sections: Vec<Section>,
+}
+
+impl TraceFileHeader {
+    /// Reads the header following `MAGIC`.
+    fn read(stream: &mut impl Read) -> io::Result<TraceFileHeader> {
+        let mut data = [0u8; 8];
+        stream.read_exact(&mut data)?;
+        let version = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let section_count = u32::from_le_bytes(data[4..8].try_into().unwrap());
+
+        if version > FORMAT_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "unsupported trace file version {}, this viewer supports up to version {}",
+                    version, FORMAT_VERSION
+                ),
+            ));
+        }
+
+        let mut sections = Vec::with_capacity(section_count as usize);
+        for _ in 0..section_count {
+            sections.push(Section::read(stream)?);
+        }
+
+        Ok(TraceFileHeader { version, sections })
+    }
+
+    fn write(&self, stream: &mut impl Write) -> io::Result<()> {
+        stream.write_all(&MAGIC)?;
+        stream.write_all(&self.version.to_le_bytes())?;
+        stream.write_all(&(self.sections.len() as u32).to_le_bytes())?;
+        for section in &self.sections {
+            section.write(stream)?;
+        }
+
+        Ok(())
+    }
+
+    fn size(section_count: usize) -> u64 {
+        HEADER_SIZE + section_count as u64 * SECTION_ENTRY_SIZE
+    }
+
+    pub fn section(&self, kind: SectionKind) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|it| it.section_kind() == Some(kind))
+    }
+}
+
+/// A run of events from a single thread in the events section.
+#[derive(Debug, Clone)]
+pub struct ChunkInfo {
+    pub thread_id: u64,
+    pub start_time: u64,
+    pub end_time: u64,
+
+    /// Position of the chunk relative to the start of the events section.
+    pub offset: u64,
+    pub length: u64,
+
+    /// Index of the first span of the chunk among all spans of the thread.
+    pub first_span_idx: u64,
+    pub span_count: u64,
+
+    /// End time of the last span of the thread before this chunk, used to resume span assembly
+    /// from this chunk.
+    pub previous_end_time: Option<u64>,
+
+    /// Starts of the spans of the thread which are still open where this chunk begins, outermost
+    /// first. Kept in the open spans section, the spans end in this or a later chunk.
+    pub open_starts: Vec<SpanStart>,
+}
+
+impl ChunkInfo {
+    fn read(data: &[u8]) -> ChunkInfo {
+        let value =
+            |idx: usize| u64::from_le_bytes(data[idx * 8..(idx + 1) * 8].try_into().unwrap());
+
+        ChunkInfo {
+            thread_id: value(0),
+            start_time: value(1),
+            end_time: value(2),
+            offset: value(3),
+            length: value(4),
+            first_span_idx: value(5),
+            span_count: value(6),
+            previous_end_time: Some(value(7)).filter(|it| *it != u64::MAX),
+            open_starts: Vec::new(),
+        }
+    }
+
+    fn write(&self, stream: &mut impl Write) -> io::Result<()> {
+        for value in [
+            self.thread_id,
+            self.start_time,
+            self.end_time,
+            self.offset,
+            self.length,
+            self.first_span_idx,
+            self.span_count,
+            self.previous_end_time.unwrap_or(u64::MAX),
+        ] {
+            stream.write_all(&value.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Chunks of every thread, ordered by time.
+pub struct TraceIndex {
+    pub threads: BTreeMap<u64, Vec<ChunkInfo>>,
+}
+
+impl TraceIndex {
+    fn read(stream: &mut impl Read) -> io::Result<TraceIndex> {
+        let mut count = [0u8; 8];
+        stream.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+
+        let mut threads = BTreeMap::<u64, Vec<ChunkInfo>>::new();
+        let mut data = [0u8; CHUNK_ENTRY_SIZE];
+        for _ in 0..count {
+            stream.read_exact(&mut data)?;
+            let chunk = ChunkInfo::read(&data);
+            threads.entry(chunk.thread_id).or_default().push(chunk);
+        }
+
+        Ok(TraceIndex { threads })
+    }
+}
+
+/// Open span starts by the offset of their chunk.
+fn read_open_starts(
+    reader: &mut io::Take<BufReader<File>>,
+) -> io::Result<BTreeMap<u64, Vec<SpanStart>>> {
+    let mut open_starts = BTreeMap::new();
+    while reader.limit() > 0 {
+        let mut data = [0u8; 16];
+        reader.read_exact(&mut data)?;
+        let offset = u64::from_le_bytes(data[0..8].try_into().unwrap());
+        let length = u64::from_le_bytes(data[8..16].try_into().unwrap());
+
+        let mut starts = Vec::new();
+        let mut events = reader.by_ref().take(length);
+        while events.limit() > 0 {
+            let TraceEvent::Span(SpanEvent::Start(start)) = TraceEvent::read(&mut events)? else {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "open spans section holds an event other than a span start",
+                ));
+            };
+
+            starts.push(start);
+        }
+
+        open_starts.insert(offset, starts);
+    }
+
+    Ok(open_starts)
+}
+
+/// A saved trace. Either a container written by `write_from_raw` or a raw capture of the socket
+/// (as written by `nc -l 9090 > traces.bin`).
+#[derive(Clone)]
+pub struct TraceFile {
+    path: PathBuf,
+    header: Option<TraceFileHeader>,
+}
+
+impl TraceFile {
+    pub fn open(path: &Path) -> io::Result<TraceFile> {
+        let mut file = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; MAGIC.len()];
+        let header = match file.read_exact(&mut magic) {
+            Ok(()) if magic == MAGIC => Some(TraceFileHeader::read(&mut file)?),
+            Ok(()) => None,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(TraceFile {
+            path: path.to_path_buf(),
+            header,
+        })
+    }
+
+    pub fn initial_message(&self) -> io::Result<InitialMessage> {
+        InitialMessage::read(self.stream()?)
+    }
+
+    /// The index of the events section. Only present in trace files written by
+    /// `write_from_raw`.
+    pub fn index(&self) -> io::Result<Option<TraceIndex>> {
+        let Some(header) = &self.header else {
+            return Ok(None);
+        };
+
+        if header.section(SectionKind::Index).is_none() {
+            return Ok(None);
+        }
+
+        let mut reader = self.section_reader(header, SectionKind::Index)?;
+        let mut index = TraceIndex::read(&mut reader)?;
+
+        // files written before chunks were split inside calls don't have open spans
+        if header.section(SectionKind::OpenSpans).is_some() {
+            let mut reader = self.section_reader(header, SectionKind::OpenSpans)?;
+            let mut open_starts = read_open_starts(&mut reader)?;
+
+            for chunk in index.threads.values_mut().flatten() {
+                if let Some(starts) = open_starts.remove(&chunk.offset) {
+                    chunk.open_starts = starts;
+                }
+            }
+        }
+
+        Ok(Some(index))
+    }
+
+    pub fn chunk_events(&self, chunk: &ChunkInfo) -> io::Result<Vec<TraceEvent>> {
+        let Some(header) = &self.header else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "raw captures do not have chunks",
+            ));
+        };
+
+        let mut reader = self.section_reader(header, SectionKind::Events)?;
+        reader.get_mut().seek_relative(chunk.offset as i64)?;
+
+        let mut data = vec![0u8; chunk.length as usize];
+        reader.read_exact(&mut data)?;
+
+        let mut cursor = Cursor::new(data);
+        let mut events = Vec::new();
+        while cursor.position() < chunk.length {
+            events.push(TraceEvent::read(&mut cursor)?);
+        }
+
+        Ok(events)
+    }
+
+    /// Every `CountersUpdate` in the trace. Empty for raw captures and files written before the
+    /// counters section was added, their counters are only found in the events.
+    pub fn counters(&self) -> io::Result<Vec<CountersUpdate>> {
+        let Some(header) = &self.header else {
+            return Ok(Vec::new());
+        };
+
+        if header.section(SectionKind::Counters).is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = self.section_reader(header, SectionKind::Counters)?;
+        let mut counters = Vec::new();
+        while reader.limit() > 0 {
+            if let TraceEvent::CountersUpdate(update) = TraceEvent::read(&mut reader)? {
+                counters.push(update);
+            }
+        }
+
+        Ok(counters)
+    }
+
+    /// Every `SamplingUpdate` in the trace. Empty for raw captures, whose updates are found in the
+    /// stream, and files written before the sampling section was added.
+    pub fn sampling(&self) -> io::Result<Vec<SamplingUpdate>> {
+        let Some(header) = &self.header else {
+            return Ok(Vec::new());
+        };
+
+        if header.section(SectionKind::Sampling).is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = self.section_reader(header, SectionKind::Sampling)?;
+        let mut updates = Vec::new();
+        while reader.limit() > 0 {
+            if let TraceEvent::SamplingUpdate(update) = TraceEvent::read(&mut reader)? {
+                updates.push(update);
+            }
+        }
+
+        Ok(updates)
+    }
+
+    /// Contents of the annotations section. `None` for raw captures and files written before the
+    /// section was added, which have nowhere to keep annotations.
+    pub fn annotations(&self) -> io::Result<Option<Vec<u8>>> {
+        let Some(header) = &self.header else {
+            return Ok(None);
+        };
+
+        if header.section(SectionKind::Annotations).is_none() {
+            return Ok(None);
+        }
+
+        let mut reader = self.section_reader(header, SectionKind::Annotations)?;
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        Ok(Some(data))
+    }
+
+    /// Replaces the contents of the annotations section. The live contents are never
+    /// overwritten, the new contents are written to unused space after the other sections or
+    /// appended, and only then is the header pointed at them. An interrupted write leaves the
+    /// previous annotations in place.
+    pub fn write_annotations(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some(header) = self.header.as_mut() else {
+            return Err(no_annotations_section());
+        };
+
+        // end of everything but the annotations, what follows is only ever annotations
+        let data_end = header
+            .sections
+            .iter()
+            .filter(|it| it.section_kind() != Some(SectionKind::Annotations))
+            .map(|it| it.offset + it.length)
+            .max()
+            .unwrap_or(TraceFileHeader::size(header.sections.len()));
+
+        let Some((section_idx, section)) = header
+            .sections
+            .iter_mut()
+            .enumerate()
+            .find(|(_, it)| it.section_kind() == Some(SectionKind::Annotations))
+        else {
+            return Err(no_annotations_section());
+        };
+
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        let file_length = file.metadata()?.len();
+
+        // ahead of the live contents when they fit, letting the file shrink back afterwards
+        let fits_before = section.offset >= data_end + data.len() as u64;
+        let offset = if fits_before {
+            data_end
+        } else {
+            file_length.max(section.offset + section.length)
+        };
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(data)?;
+        file.sync_data()?;
+
+        section.offset = offset;
+        section.length = data.len() as u64;
+
+        file.seek(SeekFrom::Start(
+            HEADER_SIZE + section_idx as u64 * SECTION_ENTRY_SIZE,
+        ))?;
+        section.write(&mut file)?;
+        file.sync_data()?;
+
+        if fits_before {
+            file.set_len(offset + data.len() as u64)?;
+        }
+
+        Ok(())
+    }
+
+    /// A stream of the `InitialMessage` followed by every `TraceEvent`, the same as what is
+    /// received over the network.
+    pub fn stream(&self) -> io::Result<Box<dyn Read + Send>> {
+        let Some(header) = &self.header else {
+            return Ok(Box::new(BufReader::new(File::open(&self.path)?)));
+        };
+
+        let initial_message = self.section_reader(header, SectionKind::InitialMessage)?;
+        let events = self.section_reader(header, SectionKind::Events)?;
+
+        Ok(Box::new(initial_message.chain(events)))
+    }
+
+    fn section_reader(
+        &self,
+        header: &TraceFileHeader,
+        kind: SectionKind,
+    ) -> io::Result<io::Take<BufReader<File>>> {
+        let section = header.section(kind).ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("trace file is missing the {:?} section", kind),
+            )
+        })?;
+
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(section.offset))?;
+
+        Ok(BufReader::new(file).take(section.length))
+    }
+}
+
+fn no_annotations_section() -> io::Error {
+    io::Error::new(
+        ErrorKind::Unsupported,
+        "trace file has no annotations section",
+    )
+}
+
+/// Writes a raw capture at `source` into an indexed trace file at `destination`. A message cut
+/// off at the end of `source` is left out. Compressed blocks are written decompressed and compact
+/// spans in full, so chunks can be read without the events before them.
+pub fn write_from_raw(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut source = CapturingReader::new(BufReader::new(File::open(source)?));
+
+    InitialMessage::read(&mut source)?;
+    let initial_message = source.take_captured();
+
+    let header_size = TraceFileHeader::size(7);
+    let mut destination = BufWriter::new(File::create(destination)?);
+    destination.seek(SeekFrom::Start(header_size))?;
+    destination.write_all(&initial_message)?;
+
+    let mut chunks = ChunkWriter::new();
+    let mut counters = Vec::new();
+    let mut sampling = Vec::new();
+    let mut compact_spans = CompactSpanDecoder::default();
+    let mut write_event = |event: TraceEvent, data: Vec<u8>| -> io::Result<()> {
+        // chunks are read without the events before them, so compact spans are written in full
+        let is_compact = matches!(event, TraceEvent::CompactSpan(..));
+        let Some(event) = compact_spans.decode(event)? else {
+            return Ok(());
+        };
+
+        let data = match &event {
+            TraceEvent::Span(span) if is_compact => span.encode(),
+            _ => data,
+        };
+
+        match event {
+            TraceEvent::CountersUpdate(..) => counters.extend_from_slice(&data),
+            TraceEvent::SamplingUpdate(..) => sampling.extend_from_slice(&data),
+            _ => {}
+        }
+
+        chunks.push(event, &data, &mut destination)
+    };
+
+    loop {
+        let event = match TraceEvent::read(&mut source) {
+            Ok(event) => event,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+
+        let data = source.take_captured();
+        let TraceEvent::Compressed(block) = event else {
+            write_event(event, data)?;
+            continue;
+        };
+
+        let length = block.len() as u64;
+        let mut block = CapturingReader::new(Cursor::new(block));
+        while block.inner.position() < length {
+            let event = TraceEvent::read(&mut block)?;
+            let data = block.take_captured();
+            write_event(event, data)?;
+        }
+    }
+
+    let (chunks, open_starts) = chunks.finish(&mut destination)?;
+    let events_length = chunks.iter().map(|it| it.length).sum::<u64>();
+
+    destination.write_all(&(chunks.len() as u64).to_le_bytes())?;
+    for chunk in &chunks {
+        chunk.write(&mut destination)?;
+    }
+
+    destination.write_all(&counters)?;
+    destination.write_all(&sampling)?;
+    destination.write_all(&open_starts)?;
+
+    let initial_message_offset = header_size;
+    let events_offset = initial_message_offset + initial_message.len() as u64;
+    let index_offset = events_offset + events_length;
+    let index_length = 8 + (chunks.len() * CHUNK_ENTRY_SIZE) as u64;
+    let counters_offset = index_offset + index_length;
+    let sampling_offset = counters_offset + counters.len() as u64;
+    let open_starts_offset = sampling_offset + sampling.len() as u64;
+    let annotations_offset = open_starts_offset + open_starts.len() as u64;
+
+    let header = TraceFileHeader {
+        version: FORMAT_VERSION,
+        sections: vec![
+            Section {
+                kind: SectionKind::InitialMessage as u32,
+                offset: initial_message_offset,
+                length: initial_message.len() as u64,
+            },
+            Section {
+                kind: SectionKind::Events as u32,
+                offset: events_offset,
+                length: events_length,
+            },
+            Section {
+                kind: SectionKind::Index as u32,
+                offset: index_offset,
+                length: index_length,
+            },
+            Section {
+                kind: SectionKind::Counters as u32,
+                offset: counters_offset,
+                length: counters.len() as u64,
+            },
+            Section {
+                kind: SectionKind::Sampling as u32,
+                offset: sampling_offset,
+                length: sampling.len() as u64,
+            },
+            Section {
+                kind: SectionKind::OpenSpans as u32,
+                offset: open_starts_offset,
+                length: open_starts.len() as u64,
+            },
+            Section {
+                kind: SectionKind::Annotations as u32,
+                offset: annotations_offset,
+                length: 0,
+            },
+        ],
+    };
+
+    destination.seek(SeekFrom::Start(0))?;
+    header.write(&mut destination)?;
+    destination.flush()?;
+
+    Ok(())
+}
+
+struct PendingChunk {
+    assembler: SpanAssembler,
+    spans_written: u64,
+
+    /// Messages of the starts of the spans in `assembler` which didn't end yet.
+    open_start_messages: Vec<Vec<u8>>,
+
+    data: Vec<u8>,
+    time_range: Option<(u64, u64)>,
+    span_count: u64,
+    previous_end_time: Option<u64>,
+    open_starts: Vec<SpanStart>,
+    open_start_data: Vec<u8>,
+}
+
+/// Groups events by thread, writing them out once enough spans of a thread are collected.
+struct ChunkWriter {
+    pending: BTreeMap<u64, PendingChunk>,
+    chunks: Vec<ChunkInfo>,
+    offset: u64,
+
+    /// Contents of the open spans section.
+    open_starts: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn new() -> ChunkWriter {
+        ChunkWriter {
+            pending: BTreeMap::new(),
+            chunks: Vec::new(),
+            offset: 0,
+            open_starts: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, event: TraceEvent, data: &[u8], out: &mut impl Write) -> io::Result<()> {
+        let Some((thread_id, time)) = event.thread_time() else {
+            return Ok(());
+        };
+
+        let pending = self
+            .pending
+            .entry(thread_id)
+            .or_insert_with(|| PendingChunk {
+                assembler: SpanAssembler::new(),
+                spans_written: 0,
+                open_start_messages: Vec::new(),
+                data: Vec::new(),
+                time_range: None,
+                span_count: 0,
+                previous_end_time: None,
+                open_starts: Vec::new(),
+                open_start_data: Vec::new(),
+            });
+
+        if pending.data.is_empty() {
+            pending.previous_end_time = pending.assembler.last_end_time();
+            pending.open_starts = pending.assembler.open_starts().cloned().collect();
+            pending.open_start_data = pending.open_start_messages.concat();
+        }
+
+        pending.data.extend_from_slice(data);
+        pending.time_range = Some(match pending.time_range {
+            Some((low, high)) => (u64::min(low, time), u64::max(high, time)),
+            None => (time, time),
+        });
+
+        if let TraceEvent::Span(span_event) = event {
+            match &span_event {
+                SpanEvent::Start(..) => pending.open_start_messages.push(data.to_vec()),
+                SpanEvent::End(..) => {
+                    pending.open_start_messages.pop();
+                }
+            }
+
+            if pending.assembler.push(span_event).is_some() {
+                pending.span_count += 1;
+            }
+        }
+
+        // threads blocked in a call which never returns would otherwise never end their chunk,
+        // spans still open are picked up again from the open starts of the next chunk
+        if pending.span_count >= SPANS_PER_CHUNK {
+            self.flush(thread_id, out)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self, thread_id: u64, out: &mut impl Write) -> io::Result<()> {
+        let Some(pending) = self.pending.get_mut(&thread_id) else {
+            return Ok(());
+        };
+
+        // chunks are assembled independently, so spans can't be reordered across them
+        pending.span_count += pending.assembler.drain().count() as u64;
+
+        let Some((start_time, end_time)) = pending.time_range.take() else {
+            return Ok(());
+        };
+
+        out.write_all(&pending.data)?;
+
+        if !pending.open_start_data.is_empty() {
+            self.open_starts
+                .extend_from_slice(&self.offset.to_le_bytes());
+            self.open_starts
+                .extend_from_slice(&(pending.open_start_data.len() as u64).to_le_bytes());
+            self.open_starts.extend_from_slice(&pending.open_start_data);
+        }
+
+        self.chunks.push(ChunkInfo {
+            thread_id,
+            start_time,
+            end_time,
+            offset: self.offset,
+            length: pending.data.len() as u64,
+            first_span_idx: pending.spans_written,
+            span_count: pending.span_count,
+            previous_end_time: pending.previous_end_time,
+            open_starts: std::mem::take(&mut pending.open_starts),
+        });
+
+        self.offset += pending.data.len() as u64;
+        pending.spans_written += pending.span_count;
+        pending.span_count = 0;
+        pending.data.clear();
+        pending.open_start_data.clear();
+
+        Ok(())
+    }
+
+    /// The chunks written and the contents of the open spans section.
+    fn finish(mut self, out: &mut impl Write) -> io::Result<(Vec<ChunkInfo>, Vec<u8>)> {
+        let thread_ids = self.pending.keys().copied().collect::<Vec<_>>();
+        for thread_id in thread_ids {
+            self.flush(thread_id, out)?;
+        }
+
+        self.chunks.sort_by_key(|it| it.thread_id);
+
+        Ok((self.chunks, self.open_starts))
+    }
+}
+
+/// Keeps a copy of every byte read from `inner`.
+struct CapturingReader<R> {
+    inner: R,
+    captured: Vec<u8>,
+}
+
+impl<R> CapturingReader<R> {
+    fn new(inner: R) -> CapturingReader<R> {
+        CapturingReader {
+            inner,
+            captured: Vec::new(),
+        }
+    }
+
+    fn take_captured(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.captured)
+    }
+}
+
+impl<R: Read> Read for CapturingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.captured.extend_from_slice(&buf[..bytes_read]);
+
+        Ok(bytes_read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::{SpanEvent, TraceEvent};
+    use crate::spans::SpanAssembler;
+    use crate::trace_file::{write_from_raw, TraceFile, FORMAT_VERSION, MAGIC, SPANS_PER_CHUNK};
+    use std::fs;
+    use std::io::Read;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+
+    fn raw_capture(spans: &[(u64, u64, u64)]) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        // tsc_frequency, anchor_seconds, anchor_nanoseconds, anchor_timestamp
+        for value in [1_000_000u64, 0, 0, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+
+        // module, library and symbol counts
+        for _ in 0..3 {
+            data.extend_from_slice(&0u32.to_le_bytes());
+        }
+
+        // SpanStart followed by SpanEnd
+        for &(thread_id, start_time, end_time) in spans {
+            for value in [0u64, thread_id, start_time, 0, 1, thread_id, end_time] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        data
+    }
+
+    /// A path in the temp directory whose file is removed once dropped, also when a test fails.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> TempPath {
+            TempPath(std::env::temp_dir().join(format!(
+                "extern_traces_test_{}_{}",
+                std::process::id(),
+                name
+            )))
+        }
+    }
+
+    impl Deref for TempPath {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl AsRef<Path> for TempPath {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Writes `raw` as a raw capture named `name` and the trace file written from it.
+    fn write_trace(name: &str, raw: &[u8]) -> (TempPath, TempPath) {
+        let raw_path = TempPath::new(&format!("{}.bin", name));
+        let trace_path = TempPath::new(&format!("{}.trace", name));
+        fs::write(&raw_path, raw).unwrap();
+
+        write_from_raw(&raw_path, &trace_path).unwrap();
+
+        (raw_path, trace_path)
+    }
+
+    fn read_stream(path: &Path) -> Vec<u8> {
+        let mut data = Vec::new();
+        TraceFile::open(path)
+            .unwrap()
+            .stream()
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+
+        data
+    }
+
+    #[test]
+    fn decompresses_compressed_blocks() {
+        let raw = raw_capture(&[(7, 10, 20), (8, 30, 40), (7, 50, 60)]);
+
+        // the initial message is never compressed, the spans are sent as a block of literals
+        let (initial_message, messages) = raw.split_at(44);
+        let mut block = vec![0xf0];
+        let mut length = messages.len() - 15;
+        while length >= 255 {
+            block.push(255);
+            length -= 255;
+        }
+        block.push(length as u8);
+        block.extend_from_slice(messages);
+
+        let mut compressed = initial_message.to_vec();
+        for value in [11, messages.len() as u64, block.len() as u64] {
+            compressed.extend_from_slice(&value.to_le_bytes());
+        }
+        compressed.extend_from_slice(&block);
+
+        let (_, trace_path) = write_trace("compressed", &compressed);
+        let index = TraceFile::open(&trace_path)
+            .unwrap()
+            .index()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(index.threads.len(), 2);
+        assert_eq!(index.threads[&7][0].span_count, 2);
+        assert_eq!(read_stream(&trace_path).len(), raw.len());
+    }
+
+    #[test]
+    fn writes_compact_spans_in_full() {
+        let mut raw = raw_capture(&[]);
+        let thread_context = |raw: &mut Vec<u8>, thread_id: u64| {
+            for value in [12, thread_id] {
+                raw.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        let time_sync = |raw: &mut Vec<u8>, time: u64| {
+            raw.push(0x80);
+            raw.extend_from_slice(&time.to_le_bytes());
+        };
+
+        thread_context(&mut raw, 7);
+        time_sync(&mut raw, 10);
+        // start at 10 and end at 20
+        raw.extend_from_slice(&[0x81, 0, 3, 0x82, 10, 0]);
+        thread_context(&mut raw, 8);
+        time_sync(&mut raw, 15);
+        raw.extend_from_slice(&[0x81, 0, 4, 0x82, 10, 2]);
+        thread_context(&mut raw, 7);
+        // start at 30 and end at 230, with a two byte delta
+        raw.extend_from_slice(&[0x81, 10, 3, 0x82, 0xc8, 0x01, 0]);
+
+        let (_, trace_path) = write_trace("compact", &raw);
+        let trace_file = TraceFile::open(&trace_path).unwrap();
+        let index = trace_file.index().unwrap().unwrap();
+        let events = trace_file.chunk_events(&index.threads[&8][0]).unwrap();
+
+        let chunk = &index.threads[&7][0];
+        assert_eq!((chunk.start_time, chunk.end_time), (10, 230));
+        assert_eq!(chunk.span_count, 2);
+
+        assert!(matches!(
+            events.as_slice(),
+            [
+                TraceEvent::Span(SpanEvent::Start(start)),
+                TraceEvent::Span(SpanEvent::End(end))
+            ] if start.time == 15 && start.label_id == 4 && end.time == 25
+                && end.return_value == Some(2)
+        ));
+    }
+
+    #[test]
+    fn round_trips_raw_capture() {
+        let raw = raw_capture(&[(7, 10, 20), (7, 30, 40)]);
+        let (raw_path, trace_path) = write_trace("round_trip", &raw);
+
+        assert_eq!(&fs::read(&trace_path).unwrap()[0..8], &MAGIC);
+        assert_eq!(read_stream(&raw_path), raw);
+        assert_eq!(read_stream(&trace_path), raw);
+    }
+
+    #[test]
+    fn keeps_sampling_updates() {
+        let mut raw = raw_capture(&[(7, 10, 20)]);
+
+        // SamplingUpdate muting label 3 from time 15
+        for value in [9u64, 15, 1, 1, 3, 0] {
+            raw.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let (_, trace_path) = write_trace("sampling", &raw);
+        let updates = TraceFile::open(&trace_path).unwrap().sampling().unwrap();
+
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].time, 15);
+        assert_eq!(updates[0].filter.rate(3), 0);
+        assert_eq!(updates[0].filter.rate(4), 1);
+    }
+
+    #[test]
+    fn indexes_chunks_per_thread() {
+        let raw = raw_capture(&[(7, 10, 20), (8, 15, 25), (7, 30, 40)]);
+        let (_, trace_path) = write_trace("index", &raw);
+
+        let trace_file = TraceFile::open(&trace_path).unwrap();
+        let index = trace_file.index().unwrap().unwrap();
+        let events = trace_file.chunk_events(&index.threads[&8][0]).unwrap();
+
+        assert_eq!(index.threads.len(), 2);
+
+        let chunk = &index.threads[&7][0];
+        assert_eq!((chunk.start_time, chunk.end_time), (10, 40));
+        assert_eq!(chunk.span_count, 2);
+
+        assert!(matches!(
+            events.as_slice(),
+            [
+                TraceEvent::Span(SpanEvent::Start(start)),
+                TraceEvent::Span(SpanEvent::End(end))
+            ] if start.time == 15 && end.time == 25
+        ));
+    }
+
+    #[test]
+    fn splits_chunks_inside_open_spans() {
+        let mut raw = raw_capture(&[]);
+        let mut span_event = |values: &[u64]| {
+            for value in values {
+                raw.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+
+        // a call with label 3 which stays open for more spans than fit in a chunk
+        span_event(&[0, 7, 1, 3]);
+        let inner_count = SPANS_PER_CHUNK + 100;
+        for time in (0..inner_count).map(|it| 10 + it * 4) {
+            span_event(&[0, 7, time, 0]);
+            span_event(&[1, 7, time + 2]);
+        }
+        span_event(&[1, 7, 10 + inner_count * 4]);
+
+        let (_, trace_path) = write_trace("open_spans", &raw);
+        let trace_file = TraceFile::open(&trace_path).unwrap();
+        let index = trace_file.index().unwrap().unwrap();
+        let chunks = &index.threads[&7];
+
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].open_starts.is_empty());
+        assert!(matches!(
+            chunks[1].open_starts.as_slice(),
+            [start] if start.time == 1 && start.label_id == 3
+        ));
+
+        // the second chunk is assembled on its own
+        let chunk = &chunks[1];
+        let mut assembler =
+            SpanAssembler::resume_after(chunk.previous_end_time, chunk.open_starts.clone());
+        let mut spans = Vec::new();
+        for event in trace_file.chunk_events(chunk).unwrap() {
+            if let TraceEvent::Span(event) = event {
+                spans.extend(assembler.push(event));
+            }
+        }
+        spans.extend(assembler.drain());
+
+        let outer = spans.iter().find(|it| it.label_id == 3).unwrap();
+        assert_eq!((outer.start_time, outer.depth), (1, 0));
+        assert!(spans.iter().all(|it| it.label_id == 3 || it.depth == 1));
+    }
+
+    #[test]
+    fn collects_counters() {
+        let mut raw = raw_capture(&[(7, 10, 20)]);
+        for value in [2u64, 7, 3, 20, 30] {
+            raw.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let (_, trace_path) = write_trace("counters", &raw);
+        let counters = TraceFile::open(&trace_path).unwrap().counters().unwrap();
+
+        assert!(matches!(
+            counters.as_slice(),
+            [counters] if counters.dropped_packets_delta == 3 && counters.last_time == 20
+        ));
+    }
+
+    #[test]
+    fn rewrites_annotations() {
+        let raw = raw_capture(&[(7, 10, 20)]);
+        let (raw_path, trace_path) = write_trace("annotations", &raw);
+
+        let mut trace_file = TraceFile::open(&trace_path).unwrap();
+        assert_eq!(trace_file.annotations().unwrap(), Some(Vec::new()));
+
+        trace_file.write_annotations(b"first").unwrap();
+        trace_file.write_annotations(b"2nd").unwrap();
+        trace_file.write_annotations(b"3").unwrap();
+        let file_length = fs::metadata(&trace_path).unwrap().len();
+
+        let annotations = TraceFile::open(&trace_path).unwrap().annotations().unwrap();
+        assert_eq!(annotations, Some(b"3".to_vec()));
+        assert_eq!(read_stream(&trace_path), raw);
+        assert_eq!(
+            TraceFile::open(&raw_path).unwrap().annotations().unwrap(),
+            None
+        );
+
+        // the second write was appended after the first, the third took the place of the first
+        // and the file was cut after it
+        let header_length = 8 + 4 + 4 + 7 * (4 + 4 + 8 + 8);
+        let index_length = 8 + 8 * 8;
+        assert_eq!(
+            file_length,
+            (header_length + raw.len() + index_length + 1) as u64
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_version() {
+        let path = TempPath::new("unsupported_version.trace");
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        fs::write(&path, data).unwrap();
+
+        let err = TraceFile::open(&path).err().unwrap();
+        assert!(err.to_string().contains("unsupported trace file version"));
+    }
+}
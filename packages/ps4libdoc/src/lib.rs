@@ -1,4 +1,7 @@
 use crate::read::ReadExt;
+pub use crate::signatures::{
+    FunctionSignature, NamedValue, Parameter, SignatureDatabase, StructField, TypeDefinition,
+};
 use glob::glob;
 use rust_embed::{Embed, RustEmbed};
 use serde::Deserialize;
@@ -9,6 +12,7 @@ use std::fs::File;
 use std::path::PathBuf;
 
 mod read;
+mod signatures;
 
 #[derive(Embed)]
 #[folder = "defs"]
@@ -16,6 +20,7 @@ struct EmbeddedDocs;
 
 pub struct LoadedDocumentation {
     modules: Vec<ModuleDocumentation>,
+    signatures: SignatureDatabase,
 }
 
 impl LoadedDocumentation {
@@ -24,6 +29,7 @@ impl LoadedDocumentation {
             modules: SharedObjectDocumentation::open_directory(path)?
                 .flat_map(|it| it.modules)
                 .collect(),
+            signatures: load_signatures()?,
         })
     }
 
@@ -32,6 +38,7 @@ impl LoadedDocumentation {
             modules: SharedObjectDocumentation::open_embed::<EmbeddedDocs>()?
                 .flat_map(|it| it.modules)
                 .collect(),
+            signatures: load_signatures()?,
         })
     }
 
@@ -77,6 +84,25 @@ impl LoadedDocumentation {
             )
         })
     }
+
+    pub fn signatures(&self) -> &SignatureDatabase {
+        &self.signatures
+    }
+
+    /// Prototype of the function with the documented name `name`.
+    pub fn signature(&self, name: &str) -> Option<&FunctionSignature> {
+        self.signatures.function(name)
+    }
+}
+
+/// Bundled signatures, extended by the sidecar file at `PS4_LIB_SIGNATURES` if it is set.
+fn load_signatures() -> Result<SignatureDatabase, anyhow::Error> {
+    let mut signatures = SignatureDatabase::bundled()?;
+    if let Some(path) = path_env("PS4_LIB_SIGNATURES") {
+        signatures.extend(SignatureDatabase::open(&path)?);
+    }
+
+    Ok(signatures)
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,105 @@
+use crate::read::ReadExt;
+use rust_embed::Embed;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Embed)]
+#[folder = "signatures"]
+struct EmbeddedSignatures;
+
+/// Function prototypes and the types they reference, keyed by symbol name.
+#[derive(Deserialize, Debug, Default)]
+pub struct SignatureDatabase {
+    #[serde(default)]
+    pub types: BTreeMap<String, TypeDefinition>,
+
+    #[serde(default)]
+    pub functions: BTreeMap<String, FunctionSignature>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct FunctionSignature {
+    pub params: Vec<Parameter>,
+
+    /// Missing for functions returning `void`.
+    pub returns: Option<Parameter>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Parameter {
+    pub name: Option<String>,
+
+    /// C type of the value, either a primitive like `int` or `const char*` or the name of an
+    /// entry in `SignatureDatabase::types`.
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeDefinition {
+    Enum {
+        values: Vec<NamedValue>,
+    },
+
+    /// Bit flags, shown as the names of the set bits.
+    Flags {
+        values: Vec<NamedValue>,
+    },
+
+    /// Shown field by field from the bytes behind `const` pointers, which the plugin captures
+    /// along with the argument registers.
+    Struct {
+        size: u64,
+        fields: Vec<StructField>,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NamedValue {
+    pub name: String,
+    pub value: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StructField {
+    pub name: String,
+
+    #[serde(rename = "type")]
+    pub type_name: String,
+    pub offset: u64,
+}
+
+impl SignatureDatabase {
+    pub fn open(path: &Path) -> Result<SignatureDatabase, anyhow::Error> {
+        let file = File::open(path)?.read_all()?;
+
+        Ok(serde_json::from_slice(&file)?)
+    }
+
+    pub fn bundled() -> Result<SignatureDatabase, anyhow::Error> {
+        let mut database = SignatureDatabase::default();
+        for name in EmbeddedSignatures::iter() {
+            let file = EmbeddedSignatures::get(&name).unwrap();
+            database.extend(serde_json::from_slice(&file.data)?);
+        }
+
+        Ok(database)
+    }
+
+    /// Adds the entries of `other`, replacing existing entries with the same name.
+    pub fn extend(&mut self, other: SignatureDatabase) {
+        self.types.extend(other.types);
+        self.functions.extend(other.functions);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionSignature> {
+        self.functions.get(name)
+    }
+
+    pub fn type_definition(&self, type_name: &str) -> Option<&TypeDefinition> {
+        self.types.get(type_name)
+    }
+}